@@ -0,0 +1,386 @@
+//! `stake-admin` runs the operator flows of `token_stake_model` against any
+//! cluster, `solana-test-validator` included.
+//!
+//! With `--dry-run` nothing is sent, the transactions are printed instead.
+
+use anyhow::{anyhow, Context as _, Result};
+use clap::{Parser, Subcommand};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::native_token::sol_to_lamports;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{read_keypair_file, Keypair, Signer};
+use solana_sdk::transaction::Transaction;
+use token_stake_model_client::{accounts, instructions, pda, DISTRIBUTION_DAILY, DISTRIBUTION_MERKLE};
+
+// daily reward instructions sent in one transaction
+const DAILY_REWARD_BATCH: usize = 8;
+
+#[derive(Parser)]
+#[clap(name = "stake-admin", version, about = "Operator CLI for token-stake-model")]
+struct Opts {
+    /// RPC URL of the cluster
+    #[clap(short, long, default_value = "http://localhost:8899")]
+    url: String,
+    /// Keypair of the bank / admin wallet
+    #[clap(short, long, default_value = "~/.config/solana/id.json")]
+    keypair: String,
+    /// Print the transactions instead of sending them
+    #[clap(long)]
+    dry_run: bool,
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create the pool with a first deposit, its epoch history and first registry page
+    Initialize {
+        /// Deposit moved from the bank to the treasury, in SOL
+        #[clap(long)]
+        amount: f64,
+    },
+    /// Create the next registry page once the existing ones are full
+    AddRegistryPage,
+    /// Create the whitelist of the wallet or replace its root
+    SetMerkleRoot {
+        /// Merkle root, 32 bytes in hex
+        #[clap(long)]
+        root: String,
+        /// The root is a v2 root with proofs at most this long
+        #[clap(long)]
+        max_depth: Option<u8>,
+        /// The root is over weighted leaves, requires --max-depth
+        #[clap(long, requires = "max-depth")]
+        weighted: bool,
+    },
+    /// Whitelist the nfts of a verified Metaplex collection instead of a root
+    SetWhitelistCollection {
+        /// Mint of the collection nft
+        #[clap(long)]
+        collection_mint: Pubkey,
+    },
+    /// Whitelist the nfts signed by a verified creator instead of a root
+    SetWhitelistCreator {
+        /// Creator listed as verified in the metadata
+        #[clap(long)]
+        creator: Pubkey,
+        /// Also require this metadata update authority
+        #[clap(long)]
+        update_authority: Option<Pubkey>,
+    },
+    /// Send a bonus to the treasury for the next epoch
+    SendBonus {
+        /// Bonus, in SOL
+        #[clap(long)]
+        amount: f64,
+    },
+    /// Move the bank balance to the treasury and start the next epoch
+    StartEpoch,
+    /// Advance the day of the epoch if it is due
+    TickDay,
+    /// Credit the daily reward of every staked nft
+    DailyReward,
+    /// Restart the epoch with the treasury balance
+    RestartEpoch,
+    /// Set the fee paid to the crank of a day or an epoch, in SOL
+    SetCrankFee {
+        #[clap(long)]
+        amount: f64,
+    },
+    /// Choose between the daily crank and merkle distributions
+    SetDistributionMode {
+        #[clap(long, possible_values = &["daily", "merkle"])]
+        mode: String,
+    },
+    /// Post the balance root of an epoch, built with `merkle-whitelist --balances`
+    PostDistribution {
+        #[clap(long)]
+        epoch: u8,
+        /// Balance root, 32 bytes in hex
+        #[clap(long)]
+        root: String,
+        /// What the balances add up to, in lamports
+        #[clap(long)]
+        total_lamports: u64,
+        /// Number of balances in the tree
+        #[clap(long)]
+        num_nodes: u32,
+    },
+    /// Stop the pool, stakers keep what they are owed
+    Sunset,
+    /// Take the treasury balance above what stakers are owed back to the bank
+    ReclaimTreasury,
+    /// Return an nft left staked after the sunset grace period to its owner
+    ForceUnstake {
+        /// Owner of the staked nft
+        #[clap(long)]
+        user: Pubkey,
+        #[clap(long)]
+        nft_mint: Pubkey,
+        /// Token account of the owner receiving the nft
+        #[clap(long)]
+        token_account: Pubkey,
+    },
+    /// Pay a user what they left unclaimed after the sunset grace period
+    SettleUser {
+        #[clap(long)]
+        user: Pubkey,
+    },
+    /// Close the distribution of an epoch after the sunset grace period, its unclaimed balances are no longer owed
+    CloseDistribution {
+        #[clap(long)]
+        epoch: u8,
+    },
+    /// Close the sunset pool once nothing is staked or owed
+    ClosePool,
+}
+
+struct Context {
+    rpc: RpcClient,
+    payer: Keypair,
+    dry_run: bool,
+}
+
+impl Context {
+    /// Sends `instructions` in one transaction signed by the payer, or prints it
+    /// on a dry run.
+    fn send(&self, label: &str, instructions: &[Instruction]) -> Result<()> {
+        if self.dry_run {
+            print_transaction(label, &self.payer.pubkey(), instructions);
+            return Ok(());
+        }
+        let blockhash = self.rpc.get_latest_blockhash()?;
+        let tx = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.payer.pubkey()),
+            &[&self.payer],
+            blockhash,
+        );
+        let signature = self
+            .rpc
+            .send_and_confirm_transaction(&tx)
+            .with_context(|| format!("{} failed", label))?;
+        println!("{}: {}", label, signature);
+        Ok(())
+    }
+
+    fn account_data(&self, address: &Pubkey) -> Result<Option<Vec<u8>>> {
+        let account = self
+            .rpc
+            .get_account_with_commitment(address, self.rpc.commitment())?
+            .value;
+        Ok(account.map(|account| account.data))
+    }
+
+    fn stake_info(&self) -> Result<accounts::StakeInfoState> {
+        let data = self
+            .account_data(&pda::stake_info().0)?
+            .ok_or_else(|| anyhow!("the pool is not initialized"))?;
+        Ok(accounts::stake_info(&data)?)
+    }
+}
+
+fn print_transaction(label: &str, payer: &Pubkey, instructions: &[Instruction]) {
+    println!("{} (payer {})", label, payer);
+    for (i, ix) in instructions.iter().enumerate() {
+        println!("  instruction {}: program {}", i, ix.program_id);
+        for meta in &ix.accounts {
+            println!(
+                "    {} {}{}",
+                meta.pubkey,
+                if meta.is_writable { "w" } else { "-" },
+                if meta.is_signer { "s" } else { "-" },
+            );
+        }
+        println!("    data {}", hex::encode(&ix.data));
+    }
+}
+
+fn parse_root(root: &str) -> Result<[u8; 32]> {
+    let bytes = hex::decode(root.trim_start_matches("0x")).context("root is not hex")?;
+    if bytes.len() != 32 {
+        return Err(anyhow!("root must be 32 bytes, got {}", bytes.len()));
+    }
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&bytes);
+    Ok(out)
+}
+
+fn expand_tilde(path: &str) -> String {
+    match (path.strip_prefix("~/"), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) => format!("{}/{}", home, rest),
+        _ => path.to_string(),
+    }
+}
+
+fn daily_reward(ctx: &Context) -> Result<()> {
+    let admin = ctx.payer.pubkey();
+    let pages = ctx.stake_info()?.registry_pages;
+
+    let mut staked = Vec::new();
+    for page in 0..pages {
+        let data = ctx
+            .account_data(&pda::stake_registry(page).0)?
+            .ok_or_else(|| anyhow!("registry page {} is missing", page))?;
+        let registry = accounts::stake_registry(&data)?;
+        staked.extend(registry.entries[..registry.count as usize].iter().map(|entry| (entry.nft_mint, entry.user_account)));
+    }
+    if staked.is_empty() {
+        println!("no staked nft");
+        return Ok(());
+    }
+
+    // a failed batch does not stop the next ones, its nfts are listed at the end
+    let batches: Vec<_> = staked.chunks(DAILY_REWARD_BATCH).collect();
+    let mut failed = Vec::new();
+    for (i, batch) in batches.iter().enumerate() {
+        let ixs: Vec<Instruction> = batch
+            .iter()
+            .map(|(nft_mint, user)| instructions::daily_reward(admin, *nft_mint, *user))
+            .collect();
+        if let Err(err) = ctx.send(&format!("daily reward batch {}", i), &ixs) {
+            eprintln!("{:#}", err);
+            failed.push(i);
+        }
+    }
+
+    println!(
+        "{} of {} batches sent, {} nfts staked",
+        batches.len() - failed.len(),
+        batches.len(),
+        staked.len()
+    );
+    if failed.is_empty() {
+        return Ok(());
+    }
+    for i in failed.iter() {
+        for (nft_mint, user) in batches[*i] {
+            println!("  batch {}: nft {} of {} not credited", i, nft_mint, user);
+        }
+    }
+    Err(anyhow!("{} of {} daily reward batches failed", failed.len(), batches.len()))
+}
+
+fn run(ctx: &Context, command: Command) -> Result<()> {
+    let payer = ctx.payer.pubkey();
+    match command {
+        Command::Initialize { amount } => ctx.send(
+            "initialize",
+            &[
+                instructions::initialize(payer, sol_to_lamports(amount)),
+                instructions::initialize_epoch_history(payer),
+                instructions::initialize_registry_page(payer, 0),
+            ],
+        ),
+        Command::AddRegistryPage => {
+            let page = ctx.stake_info()?.registry_pages;
+            ctx.send(
+                &format!("add registry page {}", page),
+                &[instructions::initialize_registry_page(payer, page)],
+            )
+        }
+        Command::SetMerkleRoot { root, max_depth, weighted } => {
+            let root = parse_root(&root)?;
+            let mut ixs = Vec::new();
+            if ctx.account_data(&pda::merkle(&payer).0)?.is_none() {
+                ixs.push(instructions::initialize_merkle(payer, root));
+            }
+            match max_depth {
+                Some(max_depth) if weighted => ixs.push(instructions::update_merkle_weighted(payer, root, max_depth)),
+                Some(max_depth) => ixs.push(instructions::update_merkle_v2(payer, root, max_depth)),
+                None if ixs.is_empty() => ixs.push(instructions::update_merkle(payer, root)),
+                None => {}
+            }
+            ctx.send("set merkle root", &ixs)
+        }
+        Command::SetWhitelistCollection { collection_mint } => {
+            let mut ixs = Vec::new();
+            if ctx.account_data(&pda::merkle(&payer).0)?.is_none() {
+                ixs.push(instructions::initialize_merkle(payer, [0; 32]));
+            }
+            ixs.push(instructions::set_whitelist_collection(payer, collection_mint));
+            ctx.send("set whitelist collection", &ixs)
+        }
+        Command::SetWhitelistCreator { creator, update_authority } => {
+            let mut ixs = Vec::new();
+            if ctx.account_data(&pda::merkle(&payer).0)?.is_none() {
+                ixs.push(instructions::initialize_merkle(payer, [0; 32]));
+            }
+            ixs.push(instructions::set_whitelist_creator(payer, creator, update_authority));
+            ctx.send("set whitelist creator", &ixs)
+        }
+        Command::SendBonus { amount } => ctx.send(
+            "send bonus",
+            &[instructions::send_epoch_bonus(payer, sol_to_lamports(amount))],
+        ),
+        Command::StartEpoch => ctx.send("start epoch", &[instructions::start_epoch(payer)]),
+        Command::TickDay => ctx.send("tick day", &[instructions::update_day_of_epoch(payer)]),
+        Command::DailyReward => daily_reward(ctx),
+        Command::RestartEpoch => ctx.send("restart epoch", &[instructions::restart_epoch(payer)]),
+        Command::SetCrankFee { amount } => ctx.send(
+            "set crank fee",
+            &[instructions::set_crank_fee(payer, sol_to_lamports(amount))],
+        ),
+        Command::SetDistributionMode { mode } => {
+            let distribution_mode = match mode.as_str() {
+                "merkle" => DISTRIBUTION_MERKLE,
+                _ => DISTRIBUTION_DAILY,
+            };
+            ctx.send(
+                "set distribution mode",
+                &[instructions::set_distribution_mode(payer, distribution_mode)],
+            )
+        }
+        Command::PostDistribution {
+            epoch,
+            root,
+            total_lamports,
+            num_nodes,
+        } => {
+            let root = parse_root(&root)?;
+            ctx.send(
+                &format!("post distribution of epoch {}", epoch),
+                &[instructions::post_epoch_distribution(payer, epoch, root, total_lamports, num_nodes)],
+            )
+        }
+        Command::Sunset => ctx.send("sunset", &[instructions::sunset_pool(payer)]),
+        Command::ReclaimTreasury => ctx.send(
+            "reclaim treasury",
+            &[instructions::reclaim_treasury(payer)],
+        ),
+        Command::ForceUnstake { user, nft_mint, token_account } => {
+            let data = ctx
+                .account_data(&pda::user_stake(&nft_mint, &user).0)?
+                .ok_or_else(|| anyhow!("nft {} of {} is not staked", nft_mint, user))?;
+            let registry_page = accounts::stake_nft_info(&data)?.registry_page;
+            ctx.send(
+                &format!("force unstake {}", nft_mint),
+                &[instructions::force_unstake_nft(payer, user, token_account, nft_mint, registry_page)],
+            )
+        }
+        Command::SettleUser { user } => ctx.send(
+            &format!("settle {}", user),
+            &[instructions::settle_stake_user(payer, user)],
+        ),
+        Command::CloseDistribution { epoch } => ctx.send(
+            &format!("close distribution of epoch {}", epoch),
+            &[instructions::close_epoch_distribution(payer, epoch)],
+        ),
+        Command::ClosePool => ctx.send("close pool", &[instructions::close_pool(payer)]),
+    }
+}
+
+fn main() -> Result<()> {
+    let opts = Opts::parse();
+    let keypair = expand_tilde(&opts.keypair);
+    let payer = read_keypair_file(&keypair)
+        .map_err(|e| anyhow!("cannot read keypair {}: {}", keypair, e))?;
+    let ctx = Context {
+        rpc: RpcClient::new_with_commitment(opts.url, CommitmentConfig::confirmed()),
+        payer,
+        dry_run: opts.dry_run,
+    };
+    run(&ctx, opts.command)
+}
@@ -0,0 +1,54 @@
+//! Deserializers for the accounts of `token_stake_model`, from raw account data.
+
+use anchor_lang::prelude::*;
+use anchor_lang::Discriminator;
+
+pub use token_stake_model::{
+    EpochDistribution, EpochHistory, EpochRecord, EpochState, Merkle, RegistryEntry,
+    StakeInfoState, StakeNftInfoState, StakeRegistry, StakeUserState,
+};
+
+/// Deserializes any borsh account of the program, checking its discriminator.
+pub fn deserialize<T: AccountDeserialize>(data: &[u8]) -> Result<T> {
+    let mut data = data;
+    T::try_deserialize(&mut data)
+}
+
+pub fn epoch_state(data: &[u8]) -> Result<EpochState> {
+    deserialize(data)
+}
+
+pub fn stake_info(data: &[u8]) -> Result<StakeInfoState> {
+    deserialize(data)
+}
+
+pub fn epoch_history(data: &[u8]) -> Result<EpochHistory> {
+    deserialize(data)
+}
+
+pub fn stake_user(data: &[u8]) -> Result<StakeUserState> {
+    deserialize(data)
+}
+
+pub fn stake_nft_info(data: &[u8]) -> Result<StakeNftInfoState> {
+    deserialize(data)
+}
+
+pub fn merkle(data: &[u8]) -> Result<Merkle> {
+    deserialize(data)
+}
+
+pub fn epoch_distribution(data: &[u8]) -> Result<EpochDistribution> {
+    deserialize(data)
+}
+
+/// Reads a zero-copy registry page. Only the first `count` entries are listed.
+pub fn stake_registry(data: &[u8]) -> Result<StakeRegistry> {
+    if data.len() < 8 + StakeRegistry::LEN {
+        return Err(ErrorCode::AccountDidNotDeserialize.into());
+    }
+    if data[..8] != StakeRegistry::discriminator() {
+        return Err(ErrorCode::AccountDiscriminatorMismatch.into());
+    }
+    Ok(bytemuck::pod_read_unaligned(&data[8..8 + StakeRegistry::LEN]))
+}
@@ -0,0 +1,684 @@
+//! One builder for every instruction of `token_stake_model`. The program
+//! addresses are derived here, callers only pass the wallets and mints.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::{system_program, sysvar};
+use anchor_lang::InstructionData;
+use token_stake_model::{accounts, instruction};
+
+use crate::pda;
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: crate::PROGRAM_ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+pub fn initialize(bank_account: Pubkey, amount: u64) -> Instruction {
+    build(
+        accounts::Initialize {
+            bank_account,
+            epoch_state: pda::epoch_state().0,
+            stake_info: pda::stake_info().0,
+            treasury_account: pda::treasury().0,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        instruction::ProcessInitialize { amount },
+    )
+}
+
+pub fn send_epoch_bonus(bonus_account: Pubkey, amount: u64) -> Instruction {
+    build(
+        accounts::EpochBonus {
+            bonus_account,
+            treasury_account: pda::treasury().0,
+            epoch_state: pda::epoch_state().0,
+            system_program: system_program::ID,
+        },
+        instruction::ProcessSendEpochBonus { amount },
+    )
+}
+
+pub fn start_epoch(bank_account: Pubkey) -> Instruction {
+    build(
+        accounts::StartEpoch {
+            bank_account,
+            treasury_account: pda::treasury().0,
+            epoch_state: pda::epoch_state().0,
+            stake_info: pda::stake_info().0,
+            epoch_history: pda::epoch_history().0,
+            system_program: system_program::ID,
+        },
+        instruction::ProcessStartEpoch {},
+    )
+}
+
+pub fn crank_start_epoch(crank_account: Pubkey) -> Instruction {
+    let (treasury_account, treasury_nonce) = pda::treasury();
+    build(
+        accounts::CrankStartEpoch {
+            crank_account,
+            treasury_account,
+            epoch_state: pda::epoch_state().0,
+            stake_info: pda::stake_info().0,
+            epoch_history: pda::epoch_history().0,
+            system_program: system_program::ID,
+        },
+        instruction::ProcessCrankStartEpoch { treasury_nonce },
+    )
+}
+
+pub fn set_crank_fee(bank_account: Pubkey, crank_fee: u64) -> Instruction {
+    build(
+        accounts::SetCrankFee {
+            bank_account,
+            epoch_state: pda::epoch_state().0,
+        },
+        instruction::ProcessSetCrankFee { crank_fee },
+    )
+}
+
+pub fn set_distribution_mode(bank_account: Pubkey, distribution_mode: u8) -> Instruction {
+    build(
+        accounts::SetDistributionMode {
+            bank_account,
+            epoch_state: pda::epoch_state().0,
+        },
+        instruction::ProcessSetDistributionMode { distribution_mode },
+    )
+}
+
+pub fn post_epoch_distribution(
+    bank_account: Pubkey,
+    epoch_no: u8,
+    root: [u8; 32],
+    total_amount: u64,
+    num_nodes: u32,
+) -> Instruction {
+    build(
+        accounts::PostEpochDistribution {
+            bank_account,
+            epoch_state: pda::epoch_state().0,
+            distribution: pda::epoch_distribution(epoch_no).0,
+            treasury_account: pda::treasury().0,
+            system_program: system_program::ID,
+        },
+        instruction::ProcessPostEpochDistribution {
+            epoch_no,
+            root,
+            total_amount,
+            num_nodes,
+        },
+    )
+}
+
+/// Claims the leaf `index` of the distribution of `epoch_no`.
+pub fn claim_epoch_distribution(
+    user_account: Pubkey,
+    epoch_no: u8,
+    index: u64,
+    amount: u64,
+    proof: Vec<[u8; 32]>,
+) -> Instruction {
+    let (treasury_account, treasury_nonce) = pda::treasury();
+    build(
+        accounts::ClaimEpochDistribution {
+            user_account,
+            distribution: pda::epoch_distribution(epoch_no).0,
+            treasury_account,
+            epoch_state: pda::epoch_state().0,
+            system_program: system_program::ID,
+        },
+        instruction::ProcessClaimEpochDistribution {
+            epoch_no,
+            treasury_nonce,
+            index,
+            amount,
+            proof,
+        },
+    )
+}
+
+pub fn initialize_epoch_history(bank_account: Pubkey) -> Instruction {
+    build(
+        accounts::InitializeEpochHistory {
+            bank_account,
+            epoch_state: pda::epoch_state().0,
+            epoch_history: pda::epoch_history().0,
+            system_program: system_program::ID,
+        },
+        instruction::ProcessInitializeEpochHistory {},
+    )
+}
+
+pub fn initialize_registry_page(bank_account: Pubkey, page: u16) -> Instruction {
+    build(
+        accounts::InitializeRegistryPage {
+            bank_account,
+            epoch_state: pda::epoch_state().0,
+            stake_info: pda::stake_info().0,
+            registry: pda::stake_registry(page).0,
+            system_program: system_program::ID,
+        },
+        instruction::ProcessInitializeRegistryPage { page },
+    )
+}
+
+pub fn initialize_merkle(admin_account: Pubkey, root: [u8; 32]) -> Instruction {
+    build(
+        accounts::InitializeMerkle {
+            admin_account,
+            merkle: pda::merkle(&admin_account).0,
+            system_program: system_program::ID,
+        },
+        instruction::ProcessInitializeMerkle { root },
+    )
+}
+
+pub fn update_merkle(admin_account: Pubkey, root: [u8; 32]) -> Instruction {
+    build(
+        accounts::UpdateMerkle {
+            admin_account,
+            merkle: pda::merkle(&admin_account).0,
+            system_program: system_program::ID,
+        },
+        instruction::UpdateMerkle { root },
+    )
+}
+
+/// Replaces the root with a v2 root, whose proofs are at most `max_depth` long.
+pub fn update_merkle_v2(admin_account: Pubkey, root: [u8; 32], max_depth: u8) -> Instruction {
+    build(
+        accounts::UpdateMerkle {
+            admin_account,
+            merkle: pda::merkle(&admin_account).0,
+            system_program: system_program::ID,
+        },
+        instruction::UpdateMerkleV2 { root, max_depth },
+    )
+}
+
+/// A v2 root over `merkle_whitelist::weighted_leaf`s.
+pub fn update_merkle_weighted(admin_account: Pubkey, root: [u8; 32], max_depth: u8) -> Instruction {
+    build(
+        accounts::UpdateMerkle {
+            admin_account,
+            merkle: pda::merkle(&admin_account).0,
+            system_program: system_program::ID,
+        },
+        instruction::UpdateMerkleWeighted { root, max_depth },
+    )
+}
+
+/// Whitelists the nfts of the verified Metaplex collection `collection_mint`
+/// instead of the root.
+pub fn set_whitelist_collection(admin_account: Pubkey, collection_mint: Pubkey) -> Instruction {
+    build(
+        accounts::UpdateMerkle {
+            admin_account,
+            merkle: pda::merkle(&admin_account).0,
+            system_program: system_program::ID,
+        },
+        instruction::SetWhitelistCollection { collection_mint },
+    )
+}
+
+/// Whitelists the nfts listing `creator` as a verified creator instead of the
+/// root, with `update_authority` if given.
+pub fn set_whitelist_creator(admin_account: Pubkey, creator: Pubkey, update_authority: Option<Pubkey>) -> Instruction {
+    build(
+        accounts::UpdateMerkle {
+            admin_account,
+            merkle: pda::merkle(&admin_account).0,
+            system_program: system_program::ID,
+        },
+        instruction::SetWhitelistCreator { creator, update_authority },
+    )
+}
+
+pub fn initialize_user(user_account: Pubkey) -> Instruction {
+    build(
+        accounts::StakeUser {
+            user_account,
+            stake_user: pda::stake_user(&user_account).0,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+        },
+        instruction::ProcessInitializeUser {},
+    )
+}
+
+pub fn close_stake_user(user_account: Pubkey) -> Instruction {
+    build(
+        accounts::CloseStakeUser {
+            user_account,
+            stake_user: pda::stake_user(&user_account).0,
+            stake_info: pda::stake_info().0,
+        },
+        instruction::ProcessCloseStakeUser {},
+    )
+}
+
+/// `merkle` is the whitelist of the pool, `registry_page` any registry page
+/// with room left.
+pub fn stake_nft(
+    user_account: Pubkey,
+    user_nft_token_account: Pubkey,
+    nft_mint: Pubkey,
+    merkle: Pubkey,
+    registry_page: u16,
+    proof: Vec<[u8; 32]>,
+) -> Instruction {
+    build(
+        accounts::StakeNft {
+            user_account,
+            user_nft_token_account,
+            nft_mint,
+            stake_info_account: pda::user_stake(&nft_mint, &user_account).0,
+            nft_vault_account: pda::vault_stake(&nft_mint, &user_account).0,
+            nft_authority: pda::vault_stake_auth().0,
+            merkle,
+            stake_info: pda::stake_info().0,
+            epoch_state: pda::epoch_state().0,
+            stake_user: pda::stake_user(&user_account).0,
+            registry: pda::stake_registry(registry_page).0,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+            token_program: anchor_spl::token::ID,
+        },
+        instruction::ProcessStakeNft { proof },
+    )
+}
+
+/// Like `stake_nft`, for an nft of `weight` in a weighted whitelist.
+pub fn stake_weighted_nft(
+    user_account: Pubkey,
+    user_nft_token_account: Pubkey,
+    nft_mint: Pubkey,
+    merkle: Pubkey,
+    registry_page: u16,
+    proof: Vec<[u8; 32]>,
+    weight: u16,
+) -> Instruction {
+    let mut ix = stake_nft(user_account, user_nft_token_account, nft_mint, merkle, registry_page, proof.clone());
+    ix.data = instruction::ProcessStakeWeightedNft { proof, weight }.data();
+    ix
+}
+
+/// Like `stake_nft`, for a pool whitelisting nfts by their Metaplex metadata.
+pub fn stake_nft_by_metadata(
+    user_account: Pubkey,
+    user_nft_token_account: Pubkey,
+    nft_mint: Pubkey,
+    merkle: Pubkey,
+    registry_page: u16,
+) -> Instruction {
+    let mut ix = stake_nft(user_account, user_nft_token_account, nft_mint, merkle, registry_page, vec![]);
+    ix.accounts.push(AccountMeta::new_readonly(pda::metadata(&nft_mint).0, false));
+    ix
+}
+
+/// `registry_page` is the page listing the nft, see `StakeNftInfoState::registry_page`.
+/// No proof is needed, the nft may have left the whitelist since it was staked.
+pub fn unstake_nft(
+    user_account: Pubkey,
+    user_nft_token_account: Pubkey,
+    nft_mint: Pubkey,
+    registry_page: u16,
+) -> Instruction {
+    build(
+        accounts::UnStakeNft {
+            user_account,
+            user_nft_token_account,
+            nft_mint,
+            nft_vault_account: pda::vault_stake(&nft_mint, &user_account).0,
+            stake_info_account: pda::user_stake(&nft_mint, &user_account).0,
+            vault_auth: pda::vault_stake_auth().0,
+            stake_user: pda::stake_user(&user_account).0,
+            epoch_state: pda::epoch_state().0,
+            stake_info: pda::stake_info().0,
+            registry: pda::stake_registry(registry_page).0,
+            token_program: anchor_spl::token::ID,
+            system_program: system_program::ID,
+        },
+        instruction::ProcessUnstakeNft {},
+    )
+}
+
+// the accounts of every nft of a batch, as read by `batch_groups`
+fn batch_accounts(user_account: &Pubkey, nfts: &[(Pubkey, Pubkey)], with_metadata: bool) -> Vec<AccountMeta> {
+    let mut accounts = Vec::new();
+    for (nft_mint, user_nft_token_account) in nfts {
+        accounts.extend([
+            AccountMeta::new_readonly(*nft_mint, false),
+            AccountMeta::new(*user_nft_token_account, false),
+            AccountMeta::new(pda::vault_stake(nft_mint, user_account).0, false),
+            AccountMeta::new(pda::user_stake(nft_mint, user_account).0, false),
+        ]);
+        if with_metadata {
+            accounts.push(AccountMeta::new_readonly(pda::metadata(nft_mint).0, false));
+        }
+    }
+    accounts
+}
+
+/// Stakes every `(nft_mint, user_nft_token_account)` of `nfts`. `proof`,
+/// `num_leaves` and the leaf index of every nft are the whitelist multiproof
+/// of the mints, see `merkle_whitelist::Whitelist::multiproof`. `leaves` also
+/// carries the weight of every nft, 1 in an unweighted whitelist.
+/// `registry_page` must have room for all of them.
+pub fn stake_many(
+    user_account: Pubkey,
+    nfts: &[(Pubkey, Pubkey)],
+    merkle: Pubkey,
+    registry_page: u16,
+    proof: Vec<[u8; 32]>,
+    leaves: &[(u32, u16)],
+    num_leaves: u32,
+) -> Instruction {
+    let (leaf_indices, weights) = leaves.iter().copied().unzip();
+    let mut ix = build(
+        accounts::StakeMany {
+            user_account,
+            nft_authority: pda::vault_stake_auth().0,
+            merkle,
+            stake_info: pda::stake_info().0,
+            epoch_state: pda::epoch_state().0,
+            stake_user: pda::stake_user(&user_account).0,
+            registry: pda::stake_registry(registry_page).0,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+            token_program: anchor_spl::token::ID,
+        },
+        instruction::ProcessStakeMany { proof, leaf_indices, num_leaves, weights },
+    );
+    ix.accounts.extend(batch_accounts(&user_account, nfts, false));
+    ix
+}
+
+/// Like `stake_many`, for a pool whitelisting nfts by their Metaplex
+/// metadata.
+pub fn stake_many_by_metadata(
+    user_account: Pubkey,
+    nfts: &[(Pubkey, Pubkey)],
+    merkle: Pubkey,
+    registry_page: u16,
+) -> Instruction {
+    let mut ix = stake_many(user_account, &[], merkle, registry_page, vec![], &[], 0);
+    ix.accounts.extend(batch_accounts(&user_account, nfts, true));
+    ix
+}
+
+/// Unstakes every `(nft_mint, user_nft_token_account)` of `nfts`, all
+/// listed in `registry_page` unless staked before the registry existed.
+pub fn unstake_many(user_account: Pubkey, nfts: &[(Pubkey, Pubkey)], registry_page: u16) -> Instruction {
+    let mut ix = build(
+        accounts::UnstakeMany {
+            user_account,
+            vault_auth: pda::vault_stake_auth().0,
+            stake_user: pda::stake_user(&user_account).0,
+            epoch_state: pda::epoch_state().0,
+            stake_info: pda::stake_info().0,
+            registry: pda::stake_registry(registry_page).0,
+            token_program: anchor_spl::token::ID,
+            system_program: system_program::ID,
+        },
+        instruction::ProcessUnstakeMany {},
+    );
+    ix.accounts.extend(batch_accounts(&user_account, nfts, false));
+    ix
+}
+
+pub fn claim_reward(user_account: Pubkey) -> Instruction {
+    let (treasury_account, treasury_nonce) = pda::treasury();
+    build(
+        accounts::ClaimReward {
+            user_account,
+            treasury_account,
+            stake_user: pda::stake_user(&user_account).0,
+            epoch_state: pda::epoch_state().0,
+            stake_info: pda::stake_info().0,
+            system_program: system_program::ID,
+            token_program: anchor_spl::token::ID,
+        },
+        instruction::ProcessClaimReward { treasury_nonce },
+    )
+}
+
+/// Credits one day of reward for `nft_mint` staked by `user`.
+pub fn daily_reward(admin_account: Pubkey, nft_mint: Pubkey, user: Pubkey) -> Instruction {
+    build(
+        accounts::DailyReward {
+            admin_account,
+            stake_nft_info: pda::user_stake(&nft_mint, &user).0,
+            stake_user_account: pda::stake_user(&user).0,
+            stake_info: pda::stake_info().0,
+            epoch_state: pda::epoch_state().0,
+        },
+        instruction::ProcessDailyReward {},
+    )
+}
+
+pub fn update_day_of_epoch(crank_account: Pubkey) -> Instruction {
+    let (treasury_account, treasury_nonce) = pda::treasury();
+    build(
+        accounts::UpdateDayEpoch {
+            crank_account,
+            stake_info: pda::stake_info().0,
+            epoch_state: pda::epoch_state().0,
+            treasury_account,
+            system_program: system_program::ID,
+        },
+        instruction::ProcessUpdateDayOfEpoch { treasury_nonce },
+    )
+}
+
+pub fn restart_epoch(bank_account: Pubkey) -> Instruction {
+    build(
+        accounts::RestartEpoch {
+            bank_account,
+            epoch_state: pda::epoch_state().0,
+            stake_info: pda::stake_info().0,
+            treasury_account: pda::treasury().0,
+        },
+        instruction::ProcessRestartEpoch {},
+    )
+}
+
+pub fn get_user_rewards(user: Pubkey) -> Instruction {
+    build(
+        accounts::GetUserRewards {
+            stake_user: pda::stake_user(&user).0,
+            epoch_state: pda::epoch_state().0,
+            stake_info: pda::stake_info().0,
+        },
+        instruction::GetUserRewards {},
+    )
+}
+
+fn pool_stats_accounts() -> accounts::GetPoolStats {
+    accounts::GetPoolStats {
+        epoch_state: pda::epoch_state().0,
+        stake_info: pda::stake_info().0,
+        treasury_account: pda::treasury().0,
+    }
+}
+
+pub fn get_pool_stats() -> Instruction {
+    build(pool_stats_accounts(), instruction::GetPoolStats {})
+}
+
+pub fn get_estimated_apr(nft_value: u64) -> Instruction {
+    build(pool_stats_accounts(), instruction::GetEstimatedApr { nft_value })
+}
+
+pub fn sunset_pool(bank_account: Pubkey) -> Instruction {
+    build(
+        accounts::SunsetPool {
+            bank_account,
+            epoch_state: pda::epoch_state().0,
+            stake_info: pda::stake_info().0,
+        },
+        instruction::ProcessSunsetPool {},
+    )
+}
+
+pub fn reclaim_treasury(bank_account: Pubkey) -> Instruction {
+    let (treasury_account, treasury_nonce) = pda::treasury();
+    build(
+        accounts::ReclaimTreasury {
+            bank_account,
+            epoch_state: pda::epoch_state().0,
+            stake_info: pda::stake_info().0,
+            treasury_account,
+            system_program: system_program::ID,
+        },
+        instruction::ProcessReclaimTreasury { treasury_nonce },
+    )
+}
+
+pub fn force_unstake_nft(
+    bank_account: Pubkey,
+    user_account: Pubkey,
+    user_nft_token_account: Pubkey,
+    nft_mint: Pubkey,
+    registry_page: u16,
+) -> Instruction {
+    build(
+        accounts::ForceUnstakeNft {
+            bank_account,
+            user_account,
+            user_nft_token_account,
+            nft_mint,
+            nft_vault_account: pda::vault_stake(&nft_mint, &user_account).0,
+            stake_info_account: pda::user_stake(&nft_mint, &user_account).0,
+            vault_auth: pda::vault_stake_auth().0,
+            stake_user: pda::stake_user(&user_account).0,
+            epoch_state: pda::epoch_state().0,
+            stake_info: pda::stake_info().0,
+            registry: pda::stake_registry(registry_page).0,
+            token_program: anchor_spl::token::ID,
+            system_program: system_program::ID,
+        },
+        instruction::ProcessForceUnstakeNft {},
+    )
+}
+
+pub fn settle_stake_user(bank_account: Pubkey, user_account: Pubkey) -> Instruction {
+    let (treasury_account, treasury_nonce) = pda::treasury();
+    build(
+        accounts::SettleStakeUser {
+            bank_account,
+            user_account,
+            treasury_account,
+            stake_user: pda::stake_user(&user_account).0,
+            epoch_state: pda::epoch_state().0,
+            stake_info: pda::stake_info().0,
+            system_program: system_program::ID,
+        },
+        instruction::ProcessSettleStakeUser { treasury_nonce },
+    )
+}
+
+/// Closes the distribution of `epoch_no` after the sunset grace period, what
+/// was never claimed is no longer owed.
+pub fn close_epoch_distribution(bank_account: Pubkey, epoch_no: u8) -> Instruction {
+    build(
+        accounts::CloseEpochDistribution {
+            bank_account,
+            epoch_state: pda::epoch_state().0,
+            distribution: pda::epoch_distribution(epoch_no).0,
+        },
+        instruction::ProcessCloseEpochDistribution { epoch_no },
+    )
+}
+
+pub fn close_pool(bank_account: Pubkey) -> Instruction {
+    build(
+        accounts::ClosePool {
+            bank_account,
+            epoch_state: pda::epoch_state().0,
+            stake_info: pda::stake_info().0,
+        },
+        instruction::ProcessClosePool {},
+    )
+}
+
+/// `admin_account` must be the upgrade authority of the program and
+/// `bank_account` the admin of the pool's whitelist.
+pub fn migrate_epoch_state(admin_account: Pubkey, bank_account: Pubkey) -> Instruction {
+    build(
+        accounts::MigrateEpochState {
+            admin_account,
+            epoch_state: pda::epoch_state().0,
+            bank_account,
+            merkle: pda::merkle(&bank_account).0,
+            program: crate::PROGRAM_ID,
+            program_data: pda::program_data().0,
+            system_program: system_program::ID,
+        },
+        instruction::ProcessMigrateEpochState {},
+    )
+}
+
+/// `admin_account` must be the upgrade authority of the program.
+pub fn migrate_stake_info(admin_account: Pubkey) -> Instruction {
+    build(
+        accounts::MigrateStakeInfo {
+            admin_account,
+            stake_info: pda::stake_info().0,
+            program: crate::PROGRAM_ID,
+            program_data: pda::program_data().0,
+            system_program: system_program::ID,
+        },
+        instruction::ProcessMigrateStakeInfo {},
+    )
+}
+
+pub fn migrate_merkle(admin_account: Pubkey) -> Instruction {
+    build(
+        accounts::MigrateMerkle {
+            admin_account,
+            merkle: pda::merkle(&admin_account).0,
+            system_program: system_program::ID,
+        },
+        instruction::ProcessMigrateMerkle {},
+    )
+}
+
+pub fn migrate_stake_nft_info(user_account: Pubkey, nft_mint: Pubkey) -> Instruction {
+    build(
+        accounts::MigrateStakeNftInfo {
+            user_account,
+            stake_info_account: pda::user_stake(&nft_mint, &user_account).0,
+            system_program: system_program::ID,
+        },
+        instruction::ProcessMigrateStakeNftInfo {},
+    )
+}
+
+/// `staked_mints` are all the mints the user has staked, a v1 user counts them.
+/// The bank lists them, the program cannot check none is missing.
+pub fn migrate_stake_user(bank_account: Pubkey, user_account: Pubkey, staked_mints: &[Pubkey]) -> Instruction {
+    let mut ix = build(
+        accounts::MigrateStakeUser {
+            bank_account,
+            user_account,
+            stake_user: pda::stake_user(&user_account).0,
+            epoch_state: pda::epoch_state().0,
+            stake_info: pda::stake_info().0,
+            system_program: system_program::ID,
+        },
+        instruction::ProcessMigrateStakeUser {},
+    );
+    ix.accounts.extend(
+        staked_mints
+            .iter()
+            .map(|nft_mint| AccountMeta::new_readonly(pda::user_stake(nft_mint, &user_account).0, false)),
+    );
+    ix
+}
@@ -0,0 +1,14 @@
+//! Rust client for the `token_stake_model` program.
+//!
+//! * [`pda`] derives every program address from its seeds.
+//! * [`instructions`] builds one [`Instruction`] per program instruction.
+//! * [`accounts`] deserializes the program accounts.
+//!
+//! [`Instruction`]: anchor_lang::solana_program::instruction::Instruction
+
+pub mod accounts;
+pub mod instructions;
+pub mod pda;
+
+pub use token_stake_model::ID as PROGRAM_ID;
+pub use token_stake_model::{DISTRIBUTION_DAILY, DISTRIBUTION_MERKLE, WHITELIST_COLLECTION, WHITELIST_CREATOR, WHITELIST_MERKLE};
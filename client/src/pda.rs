@@ -0,0 +1,89 @@
+//! Program derived addresses of `token_stake_model`, returned with their bump.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::bpf_loader_upgradeable;
+
+pub const EPOCH_STATE_SEED: &[u8] = b"epoch-state";
+pub const STAKE_INFO_SEED: &[u8] = b"stake-info";
+pub const EPOCH_TREASURY_SEED: &[u8] = b"epoch-treasury";
+pub const EPOCH_HISTORY_SEED: &[u8] = b"epoch-history";
+pub const STAKE_REGISTRY_SEED: &[u8] = b"stake-registry";
+pub const STAKE_USER_SEED: &[u8] = b"stake_user";
+pub const USER_STAKE_SEED: &[u8] = b"user-stake";
+pub const VAULT_STAKE_SEED: &[u8] = b"vault-stake";
+pub const VAULT_STAKE_AUTH_SEED: &[u8] = b"vault-stake-auth";
+pub const MERKLE_SEED: &[u8] = b"Epoch-Merkle-Whitelist";
+pub const MERKLE_LIST_SEED: &[u8] = b"Solluminati-NFT-List";
+pub const EPOCH_DISTRIBUTION_SEED: &[u8] = b"epoch-distribution";
+
+pub fn epoch_state() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[EPOCH_STATE_SEED], &crate::PROGRAM_ID)
+}
+
+pub fn stake_info() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[STAKE_INFO_SEED], &crate::PROGRAM_ID)
+}
+
+pub fn treasury() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[EPOCH_TREASURY_SEED], &crate::PROGRAM_ID)
+}
+
+pub fn epoch_history() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[EPOCH_HISTORY_SEED], &crate::PROGRAM_ID)
+}
+
+pub fn stake_registry(page: u16) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[STAKE_REGISTRY_SEED, &page.to_le_bytes()],
+        &crate::PROGRAM_ID,
+    )
+}
+
+/// The `StakeUserState` of `user`.
+pub fn stake_user(user: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[STAKE_USER_SEED, user.as_ref()], &crate::PROGRAM_ID)
+}
+
+/// The `StakeNftInfoState` of `nft_mint` staked by `user`.
+pub fn user_stake(nft_mint: &Pubkey, user: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[USER_STAKE_SEED, nft_mint.as_ref(), user.as_ref()],
+        &crate::PROGRAM_ID,
+    )
+}
+
+/// The token account holding `nft_mint` while `user` stakes it.
+pub fn vault_stake(nft_mint: &Pubkey, user: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[VAULT_STAKE_SEED, nft_mint.as_ref(), user.as_ref()],
+        &crate::PROGRAM_ID,
+    )
+}
+
+pub fn vault_stake_auth() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[VAULT_STAKE_AUTH_SEED], &crate::PROGRAM_ID)
+}
+
+/// The `Merkle` whitelist created by `admin`.
+pub fn merkle(admin: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[MERKLE_SEED, MERKLE_LIST_SEED, admin.as_ref()],
+        &crate::PROGRAM_ID,
+    )
+}
+
+/// The `EpochDistribution` posted for `epoch_no`.
+pub fn epoch_distribution(epoch_no: u8) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[EPOCH_DISTRIBUTION_SEED, &[epoch_no]], &crate::PROGRAM_ID)
+}
+
+/// The Metaplex metadata account of `mint`.
+pub fn metadata(mint: &Pubkey) -> (Pubkey, u8) {
+    token_stake_model::metadata::find_address(mint)
+}
+
+/// The program data account of the upgradeable program, which holds the
+/// upgrade authority checked by the admin migrations.
+pub fn program_data() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[crate::PROGRAM_ID.as_ref()], &bpf_loader_upgradeable::id())
+}
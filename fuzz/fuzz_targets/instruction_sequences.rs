@@ -0,0 +1,236 @@
+//! Random sequences of stake, unstake, claim, bonus, clock, tick, daily reward
+//! and epoch-start calls of several users, sent to the program running in
+//! `solana-program-test`. Calls the program rejects are skipped, and after
+//! every call, read back from the accounts:
+//! - the treasury holds at least what is owed to the users
+//! - `total_stakers` is the number of staked nfts and `total_weight` the sum
+//!   of their weights
+//! - no user claims more than was credited, and all claims together never
+//!   exceed what was sent to the treasury
+//! - no call overflows
+//!
+//! cargo +nightly fuzz run instruction_sequences
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use solana_program_test::tokio;
+use solana_sdk::instruction::InstructionError;
+use solana_sdk::rent::Rent;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::TransactionError;
+use token_stake_model::error::StakeError;
+use token_stake_model::MAX_CRANK_FEE;
+use token_stake_model_integration_tests::{Nft, Pool, LAMPORTS_PER_SOL};
+
+const USERS: usize = 3;
+const NFTS_PER_USER: usize = 2;
+// every call is a transaction, longer sequences only slow the fuzzer down
+const MAX_OPS: usize = 64;
+
+#[derive(Arbitrary, Debug)]
+enum Op {
+    Deposit { amount: u32 },
+    SendBonus { amount: u32 },
+    Stake { nft: u8 },
+    Unstake { nft: u8 },
+    Claim { user: u8 },
+    Wait { hours: u8 },
+    TickDay,
+    DailyReward,
+    StartEpoch,
+    CrankStartEpoch,
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    initial_amount: u32,
+    crank_fee: u32,
+    weights: [u8; USERS * NFTS_PER_USER],
+    ops: Vec<Op>,
+}
+
+struct Harness {
+    pool: Pool,
+    users: Vec<Keypair>,
+    nfts: Vec<Nft>,
+    weights: Vec<u16>,
+    is_staked: Vec<bool>,
+    // lamports sent to the treasury by the bank
+    deposited: u64,
+}
+
+fn is_overflow(err: &TransactionError) -> bool {
+    let overflow: u32 = StakeError::MathOverflow.into();
+    matches!(err, TransactionError::InstructionError(_, InstructionError::Custom(code)) if *code == overflow)
+}
+
+impl Harness {
+    // the pool rejected the call unless it failed on an overflow
+    fn accepted(result: Result<(), TransactionError>, op: &Op) -> bool {
+        match result {
+            Ok(()) => true,
+            Err(err) if is_overflow(&err) => panic!("{:?} overflowed", op),
+            Err(_) => false,
+        }
+    }
+
+    async fn apply(&mut self, op: &Op) {
+        match op {
+            Op::Deposit { amount } => {
+                let bank = self.pool.bank.pubkey();
+                self.pool.transfer(&bank, *amount as u64).await;
+            }
+            Op::SendBonus { amount } => {
+                let result = self.pool.send_bonus(*amount as u64).await;
+                if Self::accepted(result, op) {
+                    self.deposited += *amount as u64;
+                }
+            }
+            Op::Stake { nft } => {
+                let nft = *nft as usize % self.nfts.len();
+                let owner = &self.users[nft / NFTS_PER_USER];
+                let result = self.pool.stake(owner, &self.nfts[nft]).await;
+                if Self::accepted(result, op) {
+                    self.is_staked[nft] = true;
+                }
+            }
+            Op::Unstake { nft } => {
+                let nft = *nft as usize % self.nfts.len();
+                let owner = &self.users[nft / NFTS_PER_USER];
+                let result = self.pool.unstake(owner, &self.nfts[nft]).await;
+                if Self::accepted(result, op) {
+                    self.is_staked[nft] = false;
+                }
+            }
+            Op::Claim { user } => {
+                let user = &self.users[*user as usize % USERS];
+                let claimable = self.pool.stake_user(&user.pubkey()).await.reward_amount;
+                let balance = self.pool.balance(&user.pubkey()).await;
+                let result = self.pool.claim(user).await;
+                if Self::accepted(result, op) {
+                    assert_eq!(self.pool.balance(&user.pubkey()).await, balance + claimable);
+                }
+            }
+            Op::Wait { hours } => {
+                let now = self.pool.now + *hours as i64 * 3600;
+                self.pool.warp_to(now).await;
+            }
+            Op::TickDay => {
+                let result = self.pool.tick_day().await;
+                Self::accepted(result, op);
+            }
+            // the crank credits the staked nfts one by one, a repeated day is
+            // rejected by the program
+            Op::DailyReward => {
+                for nft in 0..self.nfts.len() {
+                    if self.is_staked[nft] {
+                        let result = self.pool.daily_reward(&[&self.nfts[nft]]).await;
+                        Self::accepted(result, op);
+                    }
+                }
+            }
+            Op::StartEpoch => {
+                let bank_balance = self.pool.bank_balance().await;
+                let result = self.pool.start_epoch().await;
+                if Self::accepted(result, op) {
+                    self.deposited += bank_balance - self.pool.bank_balance().await;
+                }
+            }
+            Op::CrankStartEpoch => {
+                let result = self.pool.crank_start_epoch().await;
+                Self::accepted(result, op);
+            }
+        }
+    }
+
+    async fn check_invariants(&mut self, op: &Op) {
+        let treasury_balance = self.pool.treasury_balance().await;
+        let epoch_state = self.pool.epoch_state().await;
+        assert!(
+            treasury_balance >= epoch_state.total_owed,
+            "treasury {} underfunded, {} owed after {:?}",
+            treasury_balance,
+            epoch_state.total_owed,
+            op
+        );
+
+        let stake_info = self.pool.stake_info().await;
+        let staked = self.is_staked.iter().filter(|staked| **staked).count();
+        assert_eq!(stake_info.total_stakers as usize, staked, "total_stakers after {:?}", op);
+        let staked_weight: u64 = self
+            .weights
+            .iter()
+            .zip(&self.is_staked)
+            .filter(|(_, staked)| **staked)
+            .map(|(weight, _)| *weight as u64)
+            .sum();
+        assert_eq!(stake_info.total_weight, staked_weight, "total_weight after {:?}", op);
+
+        let mut total_claimed = 0;
+        for (i, user) in self.users.iter().enumerate() {
+            let state = self.pool.stake_user(&user.pubkey()).await;
+            let staked = self.is_staked[i * NFTS_PER_USER..(i + 1) * NFTS_PER_USER]
+                .iter()
+                .filter(|staked| **staked)
+                .count();
+            assert_eq!(state.staked_count as usize, staked, "staked_count after {:?}", op);
+            assert!(
+                state.total_claimed <= state.total_credited,
+                "user {} claimed {} of {} credited",
+                i,
+                state.total_claimed,
+                state.total_credited
+            );
+            total_claimed += state.total_claimed;
+        }
+        assert!(
+            total_claimed <= self.deposited,
+            "{} claimed of {} deposited",
+            total_claimed,
+            self.deposited
+        );
+    }
+}
+
+async fn run(input: Input) {
+    // the first deposit creates the treasury, it has to be rent exempt
+    let initial_amount = (input.initial_amount as u64).max(Rent::default().minimum_balance(0));
+    let mut pool = Pool::start(100 * LAMPORTS_PER_SOL).await;
+    pool.initialize(initial_amount).await.unwrap();
+    pool.set_crank_fee(input.crank_fee as u64 % (MAX_CRANK_FEE + 1)).await.unwrap();
+
+    let mut users = Vec::new();
+    for _ in 0..USERS {
+        users.push(pool.new_user(LAMPORTS_PER_SOL).await);
+    }
+    let weights: Vec<u16> = input.weights.iter().map(|weight| (*weight).max(1) as u16).collect();
+    let owners: Vec<(&Keypair, u16)> = weights
+        .iter()
+        .enumerate()
+        .map(|(nft, weight)| (&users[nft / NFTS_PER_USER], *weight))
+        .collect();
+    let nfts = pool.mint_weighted_nfts(&owners).await;
+
+    let mut harness = Harness {
+        pool,
+        users,
+        nfts,
+        weights,
+        is_staked: vec![false; USERS * NFTS_PER_USER],
+        deposited: initial_amount,
+    };
+    for op in input.ops.iter().take(MAX_OPS) {
+        harness.apply(op).await;
+        harness.check_invariants(op).await;
+    }
+}
+
+fuzz_target!(|input: Input| {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(run(input));
+});
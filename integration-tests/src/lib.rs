@@ -0,0 +1,734 @@
+//! Harness driving `token_stake_model` in `solana-program-test`, with the
+//! clock under the control of the test.
+//!
+//! The test payer pays every transaction fee, so the bank, the users and the
+//! treasury only move by what the program does.
+
+use anchor_lang::{AnchorDeserialize, AnchorSerialize};
+use merkle_whitelist::balance::BalanceTree;
+use merkle_whitelist::Whitelist;
+use solana_program_test::{processor, ProgramTest, ProgramTestContext};
+use solana_sdk::account::Account;
+use solana_sdk::account_info::AccountInfo;
+use solana_sdk::clock::Clock;
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
+use solana_sdk::entrypoint::ProgramResult;
+use solana_sdk::hash::hash;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::program_pack::Pack;
+use solana_sdk::program_stubs::{set_syscall_stubs, SyscallStubs};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::system_instruction;
+use solana_sdk::transaction::{Transaction, TransactionError};
+use std::sync::Once;
+use token_stake_model::metadata::{self, Metadata};
+use token_stake_model::views::{EstimatedApr, PoolStats, UserRewards};
+use token_stake_model_client::{accounts, instructions, pda, PROGRAM_ID};
+
+pub const DAY: i64 = 86400;
+pub const LAMPORTS_PER_SOL: u64 = 1_000_000_000;
+// clock of the first epoch
+pub const START_TIME: i64 = 1_650_000_000;
+pub const MAX_COMPUTE_UNITS: u32 = 1_400_000;
+// size of every account created by the metadata program
+pub const METADATA_LEN: usize = 679;
+
+pub struct Nft {
+    pub mint: Pubkey,
+    pub owner: Pubkey,
+    pub token_account: Pubkey,
+}
+
+/// The data of the Metaplex metadata account of `metadata`, laid out and
+/// zero padded the way of the metadata program.
+pub fn metadata_data(metadata: &Metadata) -> Vec<u8> {
+    let mut data = vec![metadata::KEY_METADATA_V1];
+    data.extend_from_slice(metadata.update_authority.as_ref());
+    data.extend_from_slice(metadata.mint.as_ref());
+    for (field, len) in [("Solluminati", 32), ("SOL", 10), ("https://example.com/nft.json", 200)] {
+        let mut padded = field.as_bytes().to_vec();
+        padded.resize(len, 0);
+        padded.serialize(&mut data).unwrap();
+    }
+    // seller_fee_basis_points
+    500u16.serialize(&mut data).unwrap();
+    let creators = if metadata.creators.is_empty() { None } else { Some(metadata.creators.clone()) };
+    creators.serialize(&mut data).unwrap();
+    // primary_sale_happened, is_mutable, edition_nonce, token_standard
+    data.extend_from_slice(&[1, 1, 0, 1, 0]);
+    metadata.collection.serialize(&mut data).unwrap();
+    data.resize(METADATA_LEN, 0);
+    data
+}
+
+/// The events named `name` the program logged in `logs`.
+pub fn events<T: AnchorDeserialize>(logs: &[String], name: &str) -> Vec<T> {
+    let discriminator = hash(format!("event:{}", name).as_bytes()).to_bytes();
+    logs.iter()
+        .filter_map(|log| log.split_once("Program data: ").map(|(_, data)| data))
+        .map(|data| base64::decode(data).unwrap())
+        .filter(|data| data.starts_with(&discriminator[..8]))
+        .map(|data| T::deserialize(&mut &data[8..]).unwrap())
+        .collect()
+}
+
+// program-test prints what builtin programs log with sol_log_data instead of
+// logging it, these stubs log it as a program log of the transaction
+struct EventLogStubs(Box<dyn SyscallStubs>);
+
+impl SyscallStubs for EventLogStubs {
+    fn sol_log(&self, message: &str) {
+        self.0.sol_log(message)
+    }
+
+    fn sol_log_data(&self, fields: &[&[u8]]) {
+        let fields: Vec<String> = fields.iter().map(base64::encode).collect();
+        self.0.sol_log(&format!("Program data: {}", fields.join(" ")))
+    }
+
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        self.0.sol_invoke_signed(instruction, account_infos, signers_seeds)
+    }
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_clock_sysvar(var_addr)
+    }
+
+    fn sol_get_epoch_schedule_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_epoch_schedule_sysvar(var_addr)
+    }
+
+    fn sol_get_fees_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_fees_sysvar(var_addr)
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_rent_sysvar(var_addr)
+    }
+
+    fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+        self.0.sol_get_return_data()
+    }
+
+    fn sol_set_return_data(&self, data: &[u8]) {
+        self.0.sol_set_return_data(data)
+    }
+}
+
+// stands in for the program-test stubs while they are wrapped
+struct NoStubs;
+
+impl SyscallStubs for NoStubs {}
+
+static EVENT_LOGS: Once = Once::new();
+
+// the anchor entrypoint takes accounts that live for 'info, program-test only
+// lends them for the call
+fn process_instruction(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let accounts = Box::leak(Box::new(accounts.to_vec()));
+    token_stake_model::entry(program_id, accounts, data)
+}
+
+pub struct Pool {
+    pub context: ProgramTestContext,
+    pub bank: Keypair,
+    pub now: i64,
+    // mint_nfts posts v2 roots
+    pub merkle_v2: bool,
+    slot: u64,
+    whitelist: Option<Whitelist>,
+}
+
+impl Pool {
+    /// Starts a validator with a funded bank, the pool is not initialized yet.
+    pub async fn start(bank_lamports: u64) -> Pool {
+        let mut program_test = ProgramTest::new(
+            "token_stake_model",
+            PROGRAM_ID,
+            processor!(process_instruction),
+        );
+        let bank = Keypair::new();
+        program_test.add_account(
+            bank.pubkey(),
+            Account::new(bank_lamports, 0, &solana_sdk::system_program::id()),
+        );
+        let context = program_test.start_with_context().await;
+        // program-test installs its stubs on its first start, every test
+        // starts a pool before its first transaction
+        EVENT_LOGS.call_once(|| {
+            let program_test_stubs = set_syscall_stubs(Box::new(NoStubs));
+            set_syscall_stubs(Box::new(EventLogStubs(program_test_stubs)));
+        });
+        let mut pool = Pool {
+            context,
+            bank,
+            now: START_TIME,
+            merkle_v2: false,
+            slot: 1,
+            whitelist: None,
+        };
+        pool.set_clock().await;
+        pool
+    }
+
+    // a copy of the bank keypair, to sign while the pool is borrowed
+    fn bank_signer(&self) -> Keypair {
+        Keypair::from_bytes(&self.bank.to_bytes()).unwrap()
+    }
+
+    async fn set_clock(&mut self) {
+        let mut clock: Clock = self.context.banks_client.get_sysvar().await.unwrap();
+        clock.unix_timestamp = self.now;
+        self.context.set_sysvar(&clock);
+    }
+
+    /// Moves the clock to `now`.
+    pub async fn warp_to(&mut self, now: i64) {
+        self.now = now;
+        self.set_clock().await;
+    }
+
+    pub async fn warp_days(&mut self, days: i64) {
+        self.warp_to(self.now + days * DAY).await;
+    }
+
+    // signs `instructions` by the payer and `signers` in a new slot, so
+    // repeated instructions are never deduplicated
+    async fn transaction(&mut self, instructions: &[Instruction], signers: &[&Keypair]) -> Transaction {
+        self.slot += 1;
+        self.context.warp_to_slot(self.slot).unwrap();
+        self.set_clock().await;
+
+        let blockhash = self.context.banks_client.get_latest_blockhash().await.unwrap();
+        let mut all_signers = vec![&self.context.payer];
+        all_signers.extend_from_slice(signers);
+        Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.context.payer.pubkey()),
+            &all_signers,
+            blockhash,
+        )
+    }
+
+    /// Sends `instructions` signed by the payer and `signers`.
+    pub async fn process(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Result<(), TransactionError> {
+        let tx = self.transaction(instructions, signers).await;
+        self.context
+            .banks_client
+            .process_transaction(tx)
+            .await
+            .map_err(|err| err.unwrap())
+    }
+
+    /// Like `process`, returning the logs of the transaction.
+    pub async fn process_with_logs(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Result<Vec<String>, TransactionError> {
+        let tx = self.transaction(instructions, signers).await;
+        let outcome = self
+            .context
+            .banks_client
+            .process_transaction_with_metadata(tx)
+            .await
+            .unwrap();
+        outcome.result?;
+        Ok(outcome.metadata.unwrap().log_messages)
+    }
+
+    /// Runs the view `instruction` and deserializes what it returns.
+    pub async fn view<T: AnchorDeserialize>(&mut self, instruction: Instruction) -> T {
+        let tx = self.transaction(&[instruction], &[]).await;
+        let outcome = self
+            .context
+            .banks_client
+            .process_transaction_with_metadata(tx)
+            .await
+            .unwrap();
+        outcome.result.unwrap();
+        let return_data = outcome.metadata.unwrap().return_data.expect("the view returned nothing");
+        T::deserialize(&mut &return_data.data[..]).unwrap()
+    }
+
+    pub async fn user_rewards(&mut self, user: &Pubkey) -> UserRewards {
+        self.view(instructions::get_user_rewards(*user)).await
+    }
+
+    pub async fn pool_stats(&mut self) -> PoolStats {
+        self.view(instructions::get_pool_stats()).await
+    }
+
+    pub async fn estimated_apr(&mut self, nft_value: u64) -> EstimatedApr {
+        self.view(instructions::get_estimated_apr(nft_value)).await
+    }
+
+    pub async fn balance(&mut self, address: &Pubkey) -> u64 {
+        self.context.banks_client.get_balance(*address).await.unwrap()
+    }
+
+    pub async fn treasury_balance(&mut self) -> u64 {
+        self.balance(&pda::treasury().0).await
+    }
+
+    pub async fn bank_balance(&mut self) -> u64 {
+        let bank = self.bank.pubkey();
+        self.balance(&bank).await
+    }
+
+    async fn account_data(&mut self, address: &Pubkey) -> Vec<u8> {
+        self.context
+            .banks_client
+            .get_account(*address)
+            .await
+            .unwrap()
+            .unwrap_or_else(|| panic!("missing account {}", address))
+            .data
+    }
+
+    pub async fn epoch_state(&mut self) -> accounts::EpochState {
+        let data = self.account_data(&pda::epoch_state().0).await;
+        accounts::epoch_state(&data).unwrap()
+    }
+
+    pub async fn stake_info(&mut self) -> accounts::StakeInfoState {
+        let data = self.account_data(&pda::stake_info().0).await;
+        accounts::stake_info(&data).unwrap()
+    }
+
+    pub async fn merkle(&mut self) -> accounts::Merkle {
+        let data = self.account_data(&pda::merkle(&self.bank.pubkey()).0).await;
+        accounts::merkle(&data).unwrap()
+    }
+
+    pub async fn registry(&mut self, page: u16) -> accounts::StakeRegistry {
+        let data = self.account_data(&pda::stake_registry(page).0).await;
+        accounts::stake_registry(&data).unwrap()
+    }
+
+    pub async fn distribution(&mut self, epoch_no: u8) -> accounts::EpochDistribution {
+        let data = self.account_data(&pda::epoch_distribution(epoch_no).0).await;
+        accounts::epoch_distribution(&data).unwrap()
+    }
+
+    pub async fn stake_nft_info(&mut self, nft_mint: &Pubkey, user: &Pubkey) -> accounts::StakeNftInfoState {
+        let data = self.account_data(&pda::user_stake(nft_mint, user).0).await;
+        accounts::stake_nft_info(&data).unwrap()
+    }
+
+    pub async fn stake_user(&mut self, user: &Pubkey) -> accounts::StakeUserState {
+        let data = self.account_data(&pda::stake_user(user).0).await;
+        accounts::stake_user(&data).unwrap()
+    }
+
+    /// Writes the metadata account of `metadata.mint`.
+    pub async fn set_metadata(&mut self, metadata: &Metadata) {
+        self.set_metadata_account(&pda::metadata(&metadata.mint).0, &metadata::ID, metadata).await;
+    }
+
+    /// Writes `metadata` to any `address` with any `owner`.
+    pub async fn set_metadata_account(&mut self, address: &Pubkey, owner: &Pubkey, metadata: &Metadata) {
+        let rent = self.context.banks_client.get_rent().await.unwrap();
+        let account = Account {
+            lamports: rent.minimum_balance(METADATA_LEN),
+            data: metadata_data(metadata),
+            owner: *owner,
+            executable: false,
+            rent_epoch: 0,
+        };
+        self.context.set_account(address, &account.into());
+    }
+
+    /// Writes `data` to a rent exempt account of the program at `address`.
+    pub async fn set_program_account(&mut self, address: &Pubkey, data: Vec<u8>) {
+        let rent = self.context.banks_client.get_rent().await.unwrap();
+        let account = Account {
+            lamports: rent.minimum_balance(data.len()),
+            data,
+            owner: PROGRAM_ID,
+            executable: false,
+            rent_epoch: 0,
+        };
+        self.context.set_account(address, &account.into());
+    }
+
+    pub async fn data_len(&mut self, address: &Pubkey) -> usize {
+        self.account_data(address).await.len()
+    }
+
+    /// Tokens held by `token_account`, 0 once it is closed.
+    pub async fn token_amount(&mut self, token_account: &Pubkey) -> u64 {
+        match self.context.banks_client.get_account(*token_account).await.unwrap() {
+            Some(account) => spl_token::state::Account::unpack(&account.data).unwrap().amount,
+            None => 0,
+        }
+    }
+
+    /// An empty token account of `owner` for `mint`.
+    pub async fn new_token_account(&mut self, owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+        let payer = self.context.payer.pubkey();
+        let rent = self.context.banks_client.get_rent().await.unwrap();
+        let token_account = Keypair::new();
+        let ixs = [
+            system_instruction::create_account(
+                &payer,
+                &token_account.pubkey(),
+                rent.minimum_balance(spl_token::state::Account::LEN),
+                spl_token::state::Account::LEN as u64,
+                &spl_token::id(),
+            ),
+            spl_token::instruction::initialize_account(&spl_token::id(), &token_account.pubkey(), mint, owner)
+                .unwrap(),
+        ];
+        self.process(&ixs, &[&token_account]).await.unwrap();
+        token_account.pubkey()
+    }
+
+    /// Sends SOL from the payer, e.g. revenue landing in the bank.
+    pub async fn transfer(&mut self, to: &Pubkey, lamports: u64) {
+        let ix = system_instruction::transfer(&self.context.payer.pubkey(), to, lamports);
+        self.process(&[ix], &[]).await.unwrap();
+    }
+
+    pub async fn new_user(&mut self, lamports: u64) -> Keypair {
+        let user = Keypair::new();
+        self.transfer(&user.pubkey(), lamports).await;
+        self.process(&[instructions::initialize_user(user.pubkey())], &[&user])
+            .await
+            .unwrap();
+        user
+    }
+
+    /// Creates the pool with a first deposit, its epoch history and first registry page.
+    pub async fn initialize(&mut self, amount: u64) -> Result<(), TransactionError> {
+        let bank = self.bank.pubkey();
+        let ixs = [
+            instructions::initialize(bank, amount),
+            instructions::initialize_epoch_history(bank),
+            instructions::initialize_registry_page(bank, 0),
+        ];
+        let signer = self.bank_signer();
+        self.process(&ixs, &[&signer]).await
+    }
+
+    /// Mints one nft to every owner and whitelists all of them, replacing the
+    /// previous whitelist.
+    pub async fn mint_nfts(&mut self, owners: &[&Keypair]) -> Vec<Nft> {
+        let nfts = self.create_nfts(owners).await;
+        let mints = nfts.iter().map(|nft| nft.mint).collect();
+        let whitelist = if self.merkle_v2 {
+            Whitelist::new_v2(mints).unwrap()
+        } else {
+            Whitelist::new(mints).unwrap()
+        };
+        self.post_whitelist(whitelist).await;
+        nfts
+    }
+
+    /// Like `mint_nfts`, whitelisting every nft with its weight in a weighted
+    /// root.
+    pub async fn mint_weighted_nfts(&mut self, owners: &[(&Keypair, u16)]) -> Vec<Nft> {
+        let keypairs: Vec<&Keypair> = owners.iter().map(|(owner, _)| *owner).collect();
+        let nfts = self.create_nfts(&keypairs).await;
+        let entries = nfts.iter().zip(owners).map(|(nft, (_, weight))| (nft.mint, *weight)).collect();
+        self.post_whitelist(Whitelist::new_weighted(entries).unwrap()).await;
+        nfts
+    }
+
+    async fn create_nfts(&mut self, owners: &[&Keypair]) -> Vec<Nft> {
+        let payer = self.context.payer.pubkey();
+        let rent = self.context.banks_client.get_rent().await.unwrap();
+        let mut nfts = Vec::new();
+
+        for owner in owners {
+            let mint = Keypair::new();
+            let token_account = Keypair::new();
+            let ixs = [
+                system_instruction::create_account(
+                    &payer,
+                    &mint.pubkey(),
+                    rent.minimum_balance(spl_token::state::Mint::LEN),
+                    spl_token::state::Mint::LEN as u64,
+                    &spl_token::id(),
+                ),
+                spl_token::instruction::initialize_mint(&spl_token::id(), &mint.pubkey(), &payer, None, 0)
+                    .unwrap(),
+                system_instruction::create_account(
+                    &payer,
+                    &token_account.pubkey(),
+                    rent.minimum_balance(spl_token::state::Account::LEN),
+                    spl_token::state::Account::LEN as u64,
+                    &spl_token::id(),
+                ),
+                spl_token::instruction::initialize_account(
+                    &spl_token::id(),
+                    &token_account.pubkey(),
+                    &mint.pubkey(),
+                    &owner.pubkey(),
+                )
+                .unwrap(),
+                spl_token::instruction::mint_to(
+                    &spl_token::id(),
+                    &mint.pubkey(),
+                    &token_account.pubkey(),
+                    &payer,
+                    &[],
+                    1,
+                )
+                .unwrap(),
+            ];
+            self.process(&ixs, &[&mint, &token_account]).await.unwrap();
+            nfts.push(Nft {
+                mint: mint.pubkey(),
+                owner: owner.pubkey(),
+                token_account: token_account.pubkey(),
+            });
+        }
+        nfts
+    }
+
+    async fn post_whitelist(&mut self, whitelist: Whitelist) {
+        let bank = self.bank.pubkey();
+        let mut ixs = Vec::new();
+        if self.whitelist.is_none() {
+            ixs.push(instructions::initialize_merkle(bank, whitelist.root()));
+        }
+        if whitelist.is_weighted() {
+            ixs.push(instructions::update_merkle_weighted(bank, whitelist.root(), whitelist.max_depth()));
+        } else if self.merkle_v2 {
+            ixs.push(instructions::update_merkle_v2(bank, whitelist.root(), whitelist.max_depth()));
+        } else if ixs.is_empty() {
+            ixs.push(instructions::update_merkle(bank, whitelist.root()));
+        }
+        let signer = self.bank_signer();
+        self.process(&ixs, &[&signer]).await.unwrap();
+        self.whitelist = Some(whitelist);
+    }
+
+    pub async fn stake(&mut self, owner: &Keypair, nft: &Nft) -> Result<(), TransactionError> {
+        self.stake_on_page(owner, nft, 0).await
+    }
+
+    /// Stakes `nft` listing it on registry `page`.
+    pub async fn stake_on_page(&mut self, owner: &Keypair, nft: &Nft, page: u16) -> Result<(), TransactionError> {
+        let whitelist = self.whitelist.as_ref().unwrap();
+        let proof = whitelist.proof(&nft.mint).unwrap();
+        let merkle = pda::merkle(&self.bank.pubkey()).0;
+        let ix = if whitelist.is_weighted() {
+            let weight = whitelist.weight(&nft.mint).unwrap();
+            instructions::stake_weighted_nft(owner.pubkey(), nft.token_account, nft.mint, merkle, page, proof, weight)
+        } else {
+            instructions::stake_nft(owner.pubkey(), nft.token_account, nft.mint, merkle, page, proof)
+        };
+        self.process(&[ix], &[owner]).await
+    }
+
+    pub async fn stake_by_metadata(&mut self, owner: &Keypair, nft: &Nft) -> Result<(), TransactionError> {
+        let merkle = pda::merkle(&self.bank.pubkey()).0;
+        let ix = instructions::stake_nft_by_metadata(owner.pubkey(), nft.token_account, nft.mint, merkle, 0);
+        self.process(&[ix], &[owner]).await
+    }
+
+    pub async fn unstake(&mut self, owner: &Keypair, nft: &Nft) -> Result<(), TransactionError> {
+        let ix = instructions::unstake_nft(owner.pubkey(), nft.token_account, nft.mint, 0);
+        self.process(&[ix], &[owner]).await
+    }
+
+    /// The bank grows its own whitelist to the current layout.
+    pub async fn migrate_merkle(&mut self) -> Result<(), TransactionError> {
+        let ix = instructions::migrate_merkle(self.bank.pubkey());
+        let signer = self.bank_signer();
+        self.process(&[ix], &[&signer]).await
+    }
+
+    /// The bank migrates the v1 stake user of `user`, counting `staked_mints`.
+    pub async fn migrate_stake_user(&mut self, user: &Pubkey, staked_mints: &[Pubkey]) -> Result<(), TransactionError> {
+        let ix = instructions::migrate_stake_user(self.bank.pubkey(), *user, staked_mints);
+        let signer = self.bank_signer();
+        self.process(&[ix], &[&signer]).await
+    }
+
+    pub async fn close_stake_user(&mut self, user: &Keypair) -> Result<(), TransactionError> {
+        self.process(&[instructions::close_stake_user(user.pubkey())], &[user]).await
+    }
+
+    /// Stakes `nfts` of `owner` in one instruction, with the compute budget
+    /// raised to the maximum.
+    pub async fn stake_many(&mut self, owner: &Keypair, nfts: &[&Nft]) -> Result<(), TransactionError> {
+        let mints: Vec<Pubkey> = nfts.iter().map(|nft| nft.mint).collect();
+        let whitelist = self.whitelist.as_ref().unwrap();
+        let multiproof = whitelist.multiproof(&mints).unwrap();
+        let leaves: Vec<(u32, u16)> = multiproof
+            .indices
+            .iter()
+            .zip(&mints)
+            .map(|(index, mint)| (*index, whitelist.weight(mint).unwrap()))
+            .collect();
+        let ix = instructions::stake_many(
+            owner.pubkey(),
+            &nfts.iter().map(|nft| (nft.mint, nft.token_account)).collect::<Vec<_>>(),
+            pda::merkle(&self.bank.pubkey()).0,
+            0,
+            multiproof.proof,
+            &leaves,
+            multiproof.num_leaves,
+        );
+        let budget = ComputeBudgetInstruction::set_compute_unit_limit(MAX_COMPUTE_UNITS);
+        self.process(&[budget, ix], &[owner]).await
+    }
+
+    pub async fn stake_many_by_metadata(&mut self, owner: &Keypair, nfts: &[&Nft]) -> Result<(), TransactionError> {
+        let nfts: Vec<(Pubkey, Pubkey)> = nfts.iter().map(|nft| (nft.mint, nft.token_account)).collect();
+        let ix = instructions::stake_many_by_metadata(owner.pubkey(), &nfts, pda::merkle(&self.bank.pubkey()).0, 0);
+        let budget = ComputeBudgetInstruction::set_compute_unit_limit(MAX_COMPUTE_UNITS);
+        self.process(&[budget, ix], &[owner]).await
+    }
+
+    pub async fn unstake_many(&mut self, owner: &Keypair, nfts: &[&Nft]) -> Result<(), TransactionError> {
+        let nfts: Vec<(Pubkey, Pubkey)> = nfts.iter().map(|nft| (nft.mint, nft.token_account)).collect();
+        let ix = instructions::unstake_many(owner.pubkey(), &nfts, 0);
+        let budget = ComputeBudgetInstruction::set_compute_unit_limit(MAX_COMPUTE_UNITS);
+        self.process(&[budget, ix], &[owner]).await
+    }
+
+    pub async fn claim(&mut self, user: &Keypair) -> Result<(), TransactionError> {
+        self.process(&[instructions::claim_reward(user.pubkey())], &[user]).await
+    }
+
+    pub async fn send_bonus(&mut self, amount: u64) -> Result<(), TransactionError> {
+        let ix = instructions::send_epoch_bonus(self.bank.pubkey(), amount);
+        let signer = self.bank_signer();
+        self.process(&[ix], &[&signer]).await
+    }
+
+    pub async fn set_crank_fee(&mut self, crank_fee: u64) -> Result<(), TransactionError> {
+        let ix = instructions::set_crank_fee(self.bank.pubkey(), crank_fee);
+        let signer = self.bank_signer();
+        self.process(&[ix], &[&signer]).await
+    }
+
+    pub async fn set_whitelist_collection(&mut self, collection_mint: &Pubkey) -> Result<(), TransactionError> {
+        let ix = instructions::set_whitelist_collection(self.bank.pubkey(), *collection_mint);
+        let signer = self.bank_signer();
+        self.process(&[ix], &[&signer]).await
+    }
+
+    pub async fn set_whitelist_creator(
+        &mut self,
+        creator: &Pubkey,
+        update_authority: Option<Pubkey>,
+    ) -> Result<(), TransactionError> {
+        let ix = instructions::set_whitelist_creator(self.bank.pubkey(), *creator, update_authority);
+        let signer = self.bank_signer();
+        self.process(&[ix], &[&signer]).await
+    }
+
+    pub async fn set_distribution_mode(&mut self, distribution_mode: u8) -> Result<(), TransactionError> {
+        let ix = instructions::set_distribution_mode(self.bank.pubkey(), distribution_mode);
+        let signer = self.bank_signer();
+        self.process(&[ix], &[&signer]).await
+    }
+
+    pub async fn post_distribution(&mut self, epoch_no: u8, tree: &BalanceTree) -> Result<(), TransactionError> {
+        let ix = instructions::post_epoch_distribution(
+            self.bank.pubkey(),
+            epoch_no,
+            tree.root(),
+            tree.total_amount(),
+            tree.num_nodes(),
+        );
+        let signer = self.bank_signer();
+        self.process(&[ix], &[&signer]).await
+    }
+
+    /// `user` claims the leaf `index` of the distribution of `epoch_no`.
+    pub async fn claim_distribution(
+        &mut self,
+        user: &Keypair,
+        epoch_no: u8,
+        tree: &BalanceTree,
+        index: usize,
+        amount: u64,
+    ) -> Result<(), TransactionError> {
+        let proof = tree.proof(index).unwrap();
+        let ix = instructions::claim_epoch_distribution(user.pubkey(), epoch_no, index as u64, amount, proof);
+        self.process(&[ix], &[user]).await
+    }
+
+    pub async fn start_epoch(&mut self) -> Result<(), TransactionError> {
+        let ix = instructions::start_epoch(self.bank.pubkey());
+        let signer = self.bank_signer();
+        self.process(&[ix], &[&signer]).await
+    }
+
+    /// The payer starts the epoch without the bank.
+    pub async fn crank_start_epoch(&mut self) -> Result<(), TransactionError> {
+        let ix = instructions::crank_start_epoch(self.context.payer.pubkey());
+        self.process(&[ix], &[]).await
+    }
+
+    /// The payer cranks the day.
+    pub async fn tick_day(&mut self) -> Result<(), TransactionError> {
+        let ix = instructions::update_day_of_epoch(self.context.payer.pubkey());
+        self.process(&[ix], &[]).await
+    }
+
+    /// The payer credits the daily reward of every nft in `staked`.
+    pub async fn daily_reward(&mut self, staked: &[&Nft]) -> Result<(), TransactionError> {
+        let admin = self.context.payer.pubkey();
+        for nft in staked {
+            let ix = instructions::daily_reward(admin, nft.mint, nft.owner);
+            self.process(&[ix], &[]).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn sunset(&mut self) -> Result<(), TransactionError> {
+        let ix = instructions::sunset_pool(self.bank.pubkey());
+        let signer = self.bank_signer();
+        self.process(&[ix], &[&signer]).await
+    }
+
+    pub async fn reclaim_treasury(&mut self) -> Result<(), TransactionError> {
+        let ix = instructions::reclaim_treasury(self.bank.pubkey());
+        let signer = self.bank_signer();
+        self.process(&[ix], &[&signer]).await
+    }
+
+    /// The bank returns `nft` to its owner after the sunset.
+    pub async fn force_unstake(&mut self, nft: &Nft) -> Result<(), TransactionError> {
+        let ix = instructions::force_unstake_nft(self.bank.pubkey(), nft.owner, nft.token_account, nft.mint, 0);
+        let signer = self.bank_signer();
+        self.process(&[ix], &[&signer]).await
+    }
+
+    /// The bank pays `user` what they left unclaimed after the sunset.
+    pub async fn settle_user(&mut self, user: &Pubkey) -> Result<(), TransactionError> {
+        let ix = instructions::settle_stake_user(self.bank.pubkey(), *user);
+        let signer = self.bank_signer();
+        self.process(&[ix], &[&signer]).await
+    }
+
+    /// The bank closes the distribution of `epoch_no` after the sunset.
+    pub async fn close_distribution(&mut self, epoch_no: u8) -> Result<(), TransactionError> {
+        let ix = instructions::close_epoch_distribution(self.bank.pubkey(), epoch_no);
+        let signer = self.bank_signer();
+        self.process(&[ix], &[&signer]).await
+    }
+
+    pub async fn close_pool(&mut self) -> Result<(), TransactionError> {
+        let ix = instructions::close_pool(self.bank.pubkey());
+        let signer = self.bank_signer();
+        self.process(&[ix], &[&signer]).await
+    }
+}
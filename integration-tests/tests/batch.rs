@@ -0,0 +1,127 @@
+use solana_program_test::tokio;
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
+use solana_sdk::instruction::InstructionError;
+use solana_sdk::signature::Signer;
+use solana_sdk::transaction::TransactionError;
+use token_stake_model::error::StakeError;
+use token_stake_model_client::{instructions, pda};
+use token_stake_model_integration_tests::{Nft, Pool, LAMPORTS_PER_SOL, MAX_COMPUTE_UNITS};
+
+// the compute budget instruction goes first
+fn program_error(err: StakeError) -> TransactionError {
+    TransactionError::InstructionError(1, InstructionError::Custom(err.into()))
+}
+
+#[tokio::test]
+async fn stake_many_and_unstake_many_move_every_nft() {
+    let mut pool = Pool::start(100 * LAMPORTS_PER_SOL).await;
+    pool.initialize(7 * LAMPORTS_PER_SOL).await.unwrap();
+    let alice = pool.new_user(LAMPORTS_PER_SOL).await;
+    let bob = pool.new_user(LAMPORTS_PER_SOL).await;
+    let nfts = pool.mint_nfts(&[&alice, &alice, &alice, &alice, &alice, &bob]).await;
+    let batch: Vec<&Nft> = nfts[..5].iter().collect();
+
+    pool.stake_many(&alice, &batch).await.unwrap();
+    assert_eq!(pool.stake_info().await.total_stakers, 5);
+    let alice_state = pool.stake_user(&alice.pubkey()).await;
+    assert_eq!(alice_state.staked_count, 5);
+    assert!(alice_state.first_stake_time > 0);
+    for nft in &batch {
+        assert_eq!(pool.token_amount(&nft.token_account).await, 0);
+        assert_eq!(pool.token_amount(&pda::vault_stake(&nft.mint, &alice.pubkey()).0).await, 1);
+    }
+
+    // the single instructions see the same stake records
+    pool.unstake(&alice, batch[4]).await.unwrap();
+    pool.stake(&alice, batch[4]).await.unwrap();
+    pool.stake(&bob, &nfts[5]).await.unwrap();
+    assert_eq!(pool.stake_info().await.total_stakers, 6);
+
+    pool.warp_days(1).await;
+    pool.tick_day().await.unwrap();
+    let staked: Vec<&Nft> = nfts.iter().collect();
+    pool.daily_reward(&staked).await.unwrap();
+    let pending_amount = pool.stake_user(&alice.pubkey()).await.pending_amount;
+    assert_eq!(pending_amount, 5 * (LAMPORTS_PER_SOL / 6));
+    let remain_reward = pool.epoch_state().await.remain_reward;
+
+    let alice_balance = pool.balance(&alice.pubkey()).await;
+    pool.unstake_many(&alice, &batch).await.unwrap();
+    assert_eq!(pool.stake_info().await.total_stakers, 1);
+    let alice_state = pool.stake_user(&alice.pubkey()).await;
+    assert_eq!(alice_state.staked_count, 0);
+    // the pending reward is forfeited once
+    assert_eq!(alice_state.total_forfeited, pending_amount);
+    assert_eq!(pool.epoch_state().await.remain_reward, remain_reward + pending_amount);
+    for nft in &batch {
+        assert_eq!(pool.token_amount(&nft.token_account).await, 1);
+        assert_eq!(pool.balance(&pda::vault_stake(&nft.mint, &alice.pubkey()).0).await, 0);
+        assert_eq!(pool.balance(&pda::user_stake(&nft.mint, &alice.pubkey()).0).await, 0);
+    }
+    // the rent of the vaults and stake records is back
+    assert!(pool.balance(&alice.pubkey()).await > alice_balance);
+}
+
+#[tokio::test]
+async fn stake_many_rejects_foreign_and_repeated_nfts() {
+    let mut pool = Pool::start(100 * LAMPORTS_PER_SOL).await;
+    pool.initialize(7 * LAMPORTS_PER_SOL).await.unwrap();
+    let alice = pool.new_user(LAMPORTS_PER_SOL).await;
+    let bob = pool.new_user(LAMPORTS_PER_SOL).await;
+    let nfts = pool.mint_nfts(&[&alice, &alice, &bob]).await;
+
+    assert_eq!(
+        pool.stake_many(&alice, &[&nfts[0], &nfts[2]]).await.unwrap_err(),
+        program_error(StakeError::WrongOwner)
+    );
+
+    // one nft listed twice with the multiproof of one
+    let merkle = pda::merkle(&pool.bank.pubkey()).0;
+    let send = |leaves: Vec<(u32, u16)>, nfts: Vec<(_, _)>| {
+        let budget = ComputeBudgetInstruction::set_compute_unit_limit(MAX_COMPUTE_UNITS);
+        let ix = instructions::stake_many(alice.pubkey(), &nfts, merkle, 0, vec![[0; 32]; 2], &leaves, 3);
+        [budget, ix]
+    };
+    let nft = (nfts[0].mint, nfts[0].token_account);
+    let ixs = send(vec![(0, 1), (0, 1)], vec![nft, nft]);
+    assert_eq!(
+        pool.process(&ixs, &[&alice]).await.unwrap_err(),
+        program_error(StakeError::InvalidProof)
+    );
+    let ixs = send(vec![(0, 1), (1, 1)], vec![nft]);
+    assert_eq!(
+        pool.process(&ixs, &[&alice]).await.unwrap_err(),
+        program_error(StakeError::WrongBatchAccounts)
+    );
+
+    pool.stake_many(&alice, &[&nfts[0], &nfts[1]]).await.unwrap();
+    assert_eq!(
+        pool.stake_many(&alice, &[&nfts[0]]).await.unwrap_err(),
+        program_error(StakeError::WrongOwner)
+    );
+    assert_eq!(pool.stake_info().await.total_stakers, 2);
+}
+
+#[tokio::test]
+async fn unstake_many_only_takes_the_users_own_nfts() {
+    let mut pool = Pool::start(100 * LAMPORTS_PER_SOL).await;
+    pool.initialize(7 * LAMPORTS_PER_SOL).await.unwrap();
+    let alice = pool.new_user(LAMPORTS_PER_SOL).await;
+    let bob = pool.new_user(LAMPORTS_PER_SOL).await;
+    let nfts = pool.mint_nfts(&[&alice, &bob]).await;
+    pool.stake(&alice, &nfts[0]).await.unwrap();
+    pool.stake(&bob, &nfts[1]).await.unwrap();
+
+    // a token account of alice for bob's mint, and bob's vault in place of alice's
+    let alice_token_account = pool.new_token_account(&alice.pubkey(), &nfts[1].mint).await;
+    let mut ix = instructions::unstake_many(alice.pubkey(), &[(nfts[1].mint, alice_token_account)], 0);
+    let len = ix.accounts.len();
+    ix.accounts[len - 2].pubkey = pda::vault_stake(&nfts[1].mint, &bob.pubkey()).0;
+    ix.accounts[len - 1].pubkey = pda::user_stake(&nfts[0].mint, &alice.pubkey()).0;
+    let budget = ComputeBudgetInstruction::set_compute_unit_limit(MAX_COMPUTE_UNITS);
+    assert_eq!(
+        pool.process(&[budget, ix], &[&alice]).await.unwrap_err(),
+        program_error(StakeError::WrongBatchAccounts)
+    );
+    assert_eq!(pool.token_amount(&pda::vault_stake(&nfts[1].mint, &bob.pubkey()).0).await, 1);
+}
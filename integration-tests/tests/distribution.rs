@@ -0,0 +1,138 @@
+use merkle_whitelist::balance::{Balance, BalanceTree};
+use solana_program_test::tokio;
+use solana_sdk::instruction::InstructionError;
+use solana_sdk::signature::Signer;
+use solana_sdk::transaction::TransactionError;
+use token_stake_model::error::StakeError;
+use token_stake_model_client::{pda, DISTRIBUTION_DAILY, DISTRIBUTION_MERKLE};
+use token_stake_model_integration_tests::{Nft, Pool, LAMPORTS_PER_SOL};
+
+fn program_error(err: StakeError) -> TransactionError {
+    TransactionError::InstructionError(0, InstructionError::Custom(err.into()))
+}
+
+#[tokio::test]
+async fn merkle_distribution_replaces_the_daily_crank() {
+    let mut pool = Pool::start(100 * LAMPORTS_PER_SOL).await;
+    pool.initialize(7 * LAMPORTS_PER_SOL).await.unwrap();
+    let alice = pool.new_user(LAMPORTS_PER_SOL).await;
+    let bob = pool.new_user(LAMPORTS_PER_SOL).await;
+    let nfts = pool.mint_nfts(&[&alice, &bob]).await;
+    pool.stake(&alice, &nfts[0]).await.unwrap();
+    pool.stake(&bob, &nfts[1]).await.unwrap();
+    let staked: Vec<&Nft> = nfts.iter().collect();
+
+    // day 1 is credited by the crank, then the pool switches
+    pool.warp_days(1).await;
+    pool.tick_day().await.unwrap();
+    pool.daily_reward(&staked).await.unwrap();
+    assert_eq!(pool.stake_user(&alice.pubkey()).await.pending_amount, LAMPORTS_PER_SOL / 2);
+
+    let tree = BalanceTree::new(vec![
+        Balance { account: alice.pubkey(), amount: 2 * LAMPORTS_PER_SOL },
+        Balance { account: bob.pubkey(), amount: LAMPORTS_PER_SOL },
+    ])
+    .unwrap();
+    assert_eq!(
+        pool.post_distribution(1, &tree).await.unwrap_err(),
+        program_error(StakeError::WrongDistributionMode)
+    );
+    pool.set_distribution_mode(DISTRIBUTION_MERKLE).await.unwrap();
+    assert_eq!(
+        pool.set_distribution_mode(2).await.unwrap_err(),
+        program_error(StakeError::WrongDistributionMode)
+    );
+
+    // the crank no longer credits, it only settles what it credited before
+    pool.warp_days(1).await;
+    pool.tick_day().await.unwrap();
+    assert_eq!(
+        pool.daily_reward(&staked).await.unwrap_err(),
+        program_error(StakeError::WrongDistributionMode)
+    );
+    pool.warp_days(5).await;
+    pool.tick_day().await.unwrap();
+    assert_eq!(pool.stake_info().await.day_of_epoch, 0);
+    pool.daily_reward(&staked).await.unwrap();
+    assert_eq!(pool.stake_user(&alice.pubkey()).await.reward_amount, LAMPORTS_PER_SOL / 2);
+
+    // only ended or running epochs get a root
+    assert_eq!(
+        pool.post_distribution(2, &tree).await.unwrap_err(),
+        program_error(StakeError::WrongDistributionEpoch)
+    );
+    let owed = pool.epoch_state().await.total_owed;
+    pool.post_distribution(1, &tree).await.unwrap();
+    assert_eq!(pool.epoch_state().await.total_owed, owed + 3 * LAMPORTS_PER_SOL);
+    let distribution = pool.distribution(1).await;
+    assert_eq!(distribution.version, 2);
+    assert_eq!(distribution.max_depth, tree.max_depth());
+
+    // the leaf commits to the claimer
+    assert_eq!(
+        pool.claim_distribution(&bob, 1, &tree, 0, 2 * LAMPORTS_PER_SOL).await.unwrap_err(),
+        program_error(StakeError::InvalidProof)
+    );
+    let treasury = pool.treasury_balance().await;
+    let alice_balance = pool.balance(&alice.pubkey()).await;
+    pool.claim_distribution(&alice, 1, &tree, 0, 2 * LAMPORTS_PER_SOL).await.unwrap();
+    assert_eq!(pool.balance(&alice.pubkey()).await, alice_balance + 2 * LAMPORTS_PER_SOL);
+    assert_eq!(pool.treasury_balance().await, treasury - 2 * LAMPORTS_PER_SOL);
+    assert_eq!(
+        pool.claim_distribution(&alice, 1, &tree, 0, 2 * LAMPORTS_PER_SOL).await.unwrap_err(),
+        program_error(StakeError::AlreadyClaimed)
+    );
+
+    // and to the amount
+    assert_eq!(
+        pool.claim_distribution(&bob, 1, &tree, 1, 2 * LAMPORTS_PER_SOL).await.unwrap_err(),
+        program_error(StakeError::InvalidProof)
+    );
+    pool.claim_distribution(&bob, 1, &tree, 1, LAMPORTS_PER_SOL).await.unwrap();
+    assert_eq!(pool.epoch_state().await.total_owed, owed);
+
+    pool.set_distribution_mode(DISTRIBUTION_DAILY).await.unwrap();
+    assert_eq!(pool.epoch_state().await.distribution_mode, DISTRIBUTION_DAILY);
+}
+
+#[tokio::test]
+async fn unclaimed_distribution_closes_after_the_sunset() {
+    let mut pool = Pool::start(100 * LAMPORTS_PER_SOL).await;
+    pool.initialize(7 * LAMPORTS_PER_SOL).await.unwrap();
+    let alice = pool.new_user(LAMPORTS_PER_SOL).await;
+    let bob = pool.new_user(LAMPORTS_PER_SOL).await;
+    pool.set_distribution_mode(DISTRIBUTION_MERKLE).await.unwrap();
+
+    let tree = BalanceTree::new(vec![
+        Balance { account: alice.pubkey(), amount: 2 * LAMPORTS_PER_SOL },
+        Balance { account: bob.pubkey(), amount: LAMPORTS_PER_SOL },
+    ])
+    .unwrap();
+    pool.post_distribution(1, &tree).await.unwrap();
+    pool.claim_distribution(&alice, 1, &tree, 0, 2 * LAMPORTS_PER_SOL).await.unwrap();
+
+    // bob never claims his leaf
+    pool.sunset().await.unwrap();
+    assert_eq!(
+        pool.close_distribution(1).await.unwrap_err(),
+        program_error(StakeError::SunsetGracePeriod)
+    );
+    pool.warp_days(30).await;
+    assert_eq!(pool.epoch_state().await.total_owed, LAMPORTS_PER_SOL);
+    assert_eq!(pool.close_pool().await.unwrap_err(), program_error(StakeError::PoolNotEmpty));
+
+    let distribution_rent = pool.balance(&pda::epoch_distribution(1).0).await;
+    let bank_balance = pool.bank_balance().await;
+    pool.close_distribution(1).await.unwrap();
+    assert_eq!(pool.epoch_state().await.total_owed, 0);
+    assert_eq!(pool.balance(&pda::epoch_distribution(1).0).await, 0);
+    assert_eq!(pool.bank_balance().await, bank_balance + distribution_rent);
+
+    // bob's lamports go back to the bank with the rest of the treasury
+    let treasury_balance = pool.treasury_balance().await;
+    let bank_balance = pool.bank_balance().await;
+    pool.reclaim_treasury().await.unwrap();
+    assert_eq!(pool.bank_balance().await, bank_balance + treasury_balance);
+    pool.close_pool().await.unwrap();
+    assert_eq!(pool.balance(&pda::epoch_state().0).await, 0);
+}
@@ -0,0 +1,531 @@
+use reward_sim::{Action, Params, Simulator};
+use solana_program_test::tokio;
+use solana_sdk::instruction::InstructionError;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::TransactionError;
+use token_stake_model::error::StakeError;
+use token_stake_model::events::StakeUserCloseEvent;
+use token_stake_model::MAX_CRANK_FEE;
+use token_stake_model_client::{instructions, pda};
+use token_stake_model_integration_tests::{events, Nft, Pool, DAY, LAMPORTS_PER_SOL};
+
+fn program_error(err: StakeError) -> TransactionError {
+    TransactionError::InstructionError(0, InstructionError::Custom(err.into()))
+}
+
+// tick to the next day and credit every staked nft
+async fn next_day(pool: &mut Pool, staked: &[&Nft]) {
+    pool.warp_days(1).await;
+    pool.tick_day().await.unwrap();
+    pool.daily_reward(staked).await.unwrap();
+}
+
+#[tokio::test]
+async fn first_epochs_exact_balances() {
+    let mut pool = Pool::start(100 * LAMPORTS_PER_SOL).await;
+    pool.initialize(7 * LAMPORTS_PER_SOL).await.unwrap();
+    assert_eq!(pool.treasury_balance().await, 7 * LAMPORTS_PER_SOL);
+    assert_eq!(pool.epoch_state().await.cur_epoch_reward_per_day, LAMPORTS_PER_SOL);
+
+    let alice = pool.new_user(LAMPORTS_PER_SOL).await;
+    let bob = pool.new_user(LAMPORTS_PER_SOL).await;
+    let nfts = pool.mint_nfts(&[&alice, &alice, &bob]).await;
+    let stake_time = pool.now;
+    pool.stake(&alice, &nfts[0]).await.unwrap();
+    // the first stake is the one remembered
+    pool.warp_to(stake_time + 60).await;
+    pool.stake(&alice, &nfts[1]).await.unwrap();
+    pool.stake(&bob, &nfts[2]).await.unwrap();
+    assert_eq!(pool.stake_info().await.total_stakers, 3);
+    let staked: Vec<&Nft> = nfts.iter().collect();
+    let alice_state = pool.stake_user(&alice.pubkey()).await;
+    assert_eq!(alice_state.staked_count, 2);
+    assert_eq!(alice_state.first_stake_time, stake_time);
+    assert_eq!(alice_state.last_claim_time, 0);
+    assert_eq!(pool.stake_user(&bob.pubkey()).await.first_stake_time, stake_time + 60);
+
+    let stats = pool.pool_stats().await;
+    assert_eq!(stats.epoch_no, 1);
+    assert_eq!(stats.total_stakers, 3);
+    assert_eq!(stats.total_weight, 3);
+    assert_eq!(stats.reward_per_nft, 333_333_333);
+    assert_eq!(stats.treasury_amount, 7 * LAMPORTS_PER_SOL);
+    let apr = pool.estimated_apr(100 * LAMPORTS_PER_SOL).await;
+    assert_eq!(apr.reward_per_nft_per_day, 333_333_333);
+    assert_eq!(apr.reward_per_nft_per_epoch, 6 * 333_333_333);
+    assert_eq!(apr.reward_per_nft_per_year, 52 * 6 * 333_333_333);
+    assert_eq!(apr.apr_bps, 10399);
+
+    // days 1 to 6 are credited, 1 SOL a day shared by 3 nfts, and the view
+    // projects every day what alice has pending when the epoch ends
+    for day in 0..=6 {
+        if day > 0 {
+            next_day(&mut pool, &staked).await;
+        }
+        assert_eq!(pool.stake_info().await.day_of_epoch, day);
+        let rewards = pool.user_rewards(&alice.pubkey()).await;
+        assert_eq!(rewards.staked_count, 2);
+        assert_eq!(rewards.daily_amount, 2 * 333_333_333);
+        assert_eq!(rewards.pending_amount, day as u64 * 2 * 333_333_333);
+        assert_eq!(rewards.projected_pending_amount, 12 * 333_333_333);
+        assert_eq!(rewards.claimable_amount, 0);
+    }
+    assert_eq!(pool.stake_user(&alice.pubkey()).await.pending_amount, 12 * 333_333_333);
+    assert_eq!(pool.stake_user(&bob.pubkey()).await.pending_amount, 6 * 333_333_333);
+    assert_eq!(pool.epoch_state().await.total_owed, 18 * 333_333_333);
+
+    // nothing is claimable before the epoch is settled
+    let alice_balance = pool.balance(&alice.pubkey()).await;
+    pool.claim(&alice).await.unwrap();
+    assert_eq!(pool.balance(&alice.pubkey()).await, alice_balance);
+    let alice_state = pool.stake_user(&alice.pubkey()).await;
+    assert_eq!(alice_state.total_claimed, 0);
+    assert_eq!(alice_state.last_claim_time, 0);
+
+    // day 7 is day 0, which settles the epoch
+    next_day(&mut pool, &staked).await;
+    assert_eq!(pool.stake_info().await.day_of_epoch, 0);
+    let alice_state = pool.stake_user(&alice.pubkey()).await;
+    assert_eq!(alice_state.reward_amount, 12 * 333_333_333);
+    assert_eq!(alice_state.pending_amount, 0);
+    let rewards = pool.user_rewards(&alice.pubkey()).await;
+    assert_eq!(rewards.claimable_amount, 12 * 333_333_333);
+    assert_eq!(rewards.pending_amount, 0);
+
+    pool.claim(&alice).await.unwrap();
+    assert_eq!(pool.balance(&alice.pubkey()).await, alice_balance + 12 * 333_333_333);
+    assert_eq!(pool.treasury_balance().await, 7 * LAMPORTS_PER_SOL - 12 * 333_333_333);
+    assert_eq!(pool.epoch_state().await.total_owed, 6 * 333_333_333);
+    let alice_state = pool.stake_user(&alice.pubkey()).await;
+    assert_eq!(alice_state.total_claimed, 12 * 333_333_333);
+    assert_eq!(alice_state.last_claim_time, pool.now);
+    assert_eq!(alice_state.total_forfeited, 0);
+    assert_eq!(alice_state.first_stake_time, stake_time);
+
+    // epoch 2 gets the whole bank and the bonus
+    let bank = pool.bank.pubkey();
+    pool.transfer(&bank, 14 * LAMPORTS_PER_SOL).await;
+    pool.send_bonus(LAMPORTS_PER_SOL / 2).await.unwrap();
+    let treasury_balance = pool.treasury_balance().await;
+    let bank_balance = pool.bank_balance().await;
+    pool.start_epoch().await.unwrap();
+
+    let epoch_state = pool.epoch_state().await;
+    assert_eq!(epoch_state.epoch_no, 2);
+    assert_eq!(epoch_state.epoch_bonus, 0);
+    assert_eq!(epoch_state.cur_epoch_reward_per_day, (bank_balance + LAMPORTS_PER_SOL / 2) / 7);
+    assert_eq!(pool.bank_balance().await, 0);
+    assert_eq!(pool.treasury_balance().await, treasury_balance + bank_balance);
+
+    // an epoch can not start twice
+    assert_eq!(
+        pool.start_epoch().await.unwrap_err(),
+        program_error(StakeError::NoEnoughSol)
+    );
+    pool.transfer(&bank, LAMPORTS_PER_SOL).await;
+    assert_eq!(
+        pool.start_epoch().await.unwrap_err(),
+        program_error(StakeError::EpochWrongDays)
+    );
+
+    // bob leaves on day 2 and gives his pending reward back to epoch 3
+    let reward_per_nft = epoch_state.cur_epoch_reward_per_day / 3;
+    next_day(&mut pool, &staked).await;
+    next_day(&mut pool, &staked).await;
+    assert_eq!(pool.stake_user(&bob.pubkey()).await.pending_amount, 2 * reward_per_nft);
+    pool.unstake(&bob, &nfts[2]).await.unwrap();
+
+    let bob_state = pool.stake_user(&bob.pubkey()).await;
+    assert_eq!(bob_state.pending_amount, 0);
+    assert_eq!(bob_state.reward_amount, 6 * 333_333_333);
+    assert_eq!(bob_state.total_forfeited, 2 * reward_per_nft);
+    assert_eq!(bob_state.staked_count, 0);
+    assert_eq!(bob_state.total_claimed, 0);
+    assert_eq!(bob_state.first_stake_time, stake_time + 60);
+    assert_eq!(pool.stake_info().await.total_stakers, 2);
+    let epoch_state = pool.epoch_state().await;
+    assert_eq!(epoch_state.remain_reward, 2 * reward_per_nft);
+
+    // the forfeited reward joins what the bank sends for epoch 3
+    let staked = &staked[..2];
+    for _ in 3..=7 {
+        next_day(&mut pool, staked).await;
+    }
+    let bank_balance = pool.bank_balance().await;
+    pool.start_epoch().await.unwrap();
+    let epoch_state = pool.epoch_state().await;
+    assert_eq!(epoch_state.epoch_no, 3);
+    assert_eq!(epoch_state.remain_reward, 0);
+    assert_eq!(epoch_state.cur_epoch_reward_per_day, (bank_balance + 2 * reward_per_nft) / 7);
+
+    let bob_balance = pool.balance(&bob.pubkey()).await;
+    pool.claim(&bob).await.unwrap();
+    assert_eq!(pool.balance(&bob.pubkey()).await, bob_balance + 6 * 333_333_333);
+    let bob_state = pool.stake_user(&bob.pubkey()).await;
+    assert_eq!(bob_state.total_claimed, 6 * 333_333_333);
+    assert_eq!(bob_state.last_claim_time, pool.now);
+    assert_eq!(bob_state.total_forfeited, 2 * reward_per_nft);
+}
+
+#[tokio::test]
+async fn days_the_crank_skipped_are_credited_once() {
+    let mut pool = Pool::start(100 * LAMPORTS_PER_SOL).await;
+    pool.initialize(7 * LAMPORTS_PER_SOL).await.unwrap();
+    let alice = pool.new_user(LAMPORTS_PER_SOL).await;
+    let bob = pool.new_user(LAMPORTS_PER_SOL).await;
+    let nfts = pool.mint_nfts(&[&alice, &bob]).await;
+    pool.stake(&alice, &nfts[0]).await.unwrap();
+    pool.stake(&bob, &nfts[1]).await.unwrap();
+    let staked: Vec<&Nft> = nfts.iter().collect();
+    let reward_per_nft = LAMPORTS_PER_SOL / 2;
+
+    next_day(&mut pool, &staked).await;
+    assert_eq!(pool.stake_user(&alice.pubkey()).await.pending_amount, reward_per_nft);
+
+    // the crank misses days 2 and 3, day 4 credits all three
+    pool.warp_days(3).await;
+    pool.tick_day().await.unwrap();
+    let stake_info = pool.stake_info().await;
+    assert_eq!(stake_info.day_of_epoch, 4);
+    assert_eq!(stake_info.days_passed, 4);
+    pool.daily_reward(&staked).await.unwrap();
+    assert_eq!(pool.stake_user(&alice.pubkey()).await.pending_amount, 4 * reward_per_nft);
+    assert_eq!(pool.epoch_state().await.epoch_credited, 8 * reward_per_nft);
+
+    // a day is credited once, however often the crank calls
+    assert_eq!(
+        pool.daily_reward(&staked[..1]).await.unwrap_err(),
+        program_error(StakeError::NoDaily)
+    );
+
+    // days 5 and 6 are credited with the settlement after the epoch ends
+    pool.warp_days(5).await;
+    pool.tick_day().await.unwrap();
+    let stake_info = pool.stake_info().await;
+    assert_eq!(stake_info.day_of_epoch, 0);
+    assert_eq!(stake_info.days_passed, 7);
+    pool.daily_reward(&staked).await.unwrap();
+    for user in [&alice, &bob] {
+        let state = pool.stake_user(&user.pubkey()).await;
+        assert_eq!(state.reward_amount, 6 * reward_per_nft);
+        assert_eq!(state.total_credited, 6 * reward_per_nft);
+        assert_eq!(state.pending_amount, 0);
+    }
+    let epoch_state = pool.epoch_state().await;
+    assert_eq!(epoch_state.total_owed, 6 * LAMPORTS_PER_SOL);
+    assert_eq!(epoch_state.epoch_credited, 6 * LAMPORTS_PER_SOL);
+}
+
+#[tokio::test]
+async fn the_epoch_starts_once_every_nft_is_credited() {
+    let mut pool = Pool::start(100 * LAMPORTS_PER_SOL).await;
+    pool.initialize(7 * LAMPORTS_PER_SOL).await.unwrap();
+    let alice = pool.new_user(LAMPORTS_PER_SOL).await;
+    let bob = pool.new_user(LAMPORTS_PER_SOL).await;
+    let nfts = pool.mint_nfts(&[&alice, &bob]).await;
+    pool.stake(&alice, &nfts[0]).await.unwrap();
+    pool.stake(&bob, &nfts[1]).await.unwrap();
+    let staked: Vec<&Nft> = nfts.iter().collect();
+    let reward_per_nft = LAMPORTS_PER_SOL / 2;
+    for _ in 1..=5 {
+        next_day(&mut pool, &staked).await;
+    }
+
+    // the crank credits alice on the last day and misses bob
+    pool.warp_days(1).await;
+    pool.tick_day().await.unwrap();
+    pool.daily_reward(&staked[..1]).await.unwrap();
+    assert_eq!(pool.stake_info().await.credited_nfts, 1);
+
+    // the epoch is over, but bob's last day would be lost
+    pool.warp_days(2).await;
+    pool.tick_day().await.unwrap();
+    pool.transfer(&pool.bank.pubkey(), 7 * LAMPORTS_PER_SOL).await;
+    assert_eq!(
+        pool.start_epoch().await.unwrap_err(),
+        program_error(StakeError::EpochNotCredited)
+    );
+    assert_eq!(
+        pool.crank_start_epoch().await.unwrap_err(),
+        program_error(StakeError::EpochNotCredited)
+    );
+
+    pool.daily_reward(&staked[1..]).await.unwrap();
+    let bob_state = pool.stake_user(&bob.pubkey()).await;
+    assert_eq!(bob_state.reward_amount, 6 * reward_per_nft);
+    assert_eq!(bob_state.pending_amount, 0);
+    assert_eq!(pool.stake_info().await.credited_nfts, 2);
+
+    pool.start_epoch().await.unwrap();
+    assert_eq!(pool.epoch_state().await.epoch_no, 2);
+    assert_eq!(pool.stake_info().await.credited_nfts, 0);
+}
+
+#[tokio::test]
+async fn missed_epochs_are_skipped_on_the_week_grid() {
+    let mut pool = Pool::start(100 * LAMPORTS_PER_SOL).await;
+    pool.initialize(7 * LAMPORTS_PER_SOL).await.unwrap();
+    let start_time = pool.epoch_state().await.epoch_start_time;
+
+    pool.warp_days(3 * 7 + 2).await;
+    // the day crank waits on day 0 once the epoch is over
+    pool.tick_day().await.unwrap();
+    assert_eq!(pool.stake_info().await.day_of_epoch, 0);
+
+    pool.start_epoch().await.unwrap();
+    let epoch_state = pool.epoch_state().await;
+    assert_eq!(epoch_state.epoch_no, 4);
+    assert_eq!(epoch_state.epoch_start_time, start_time + 3 * 7 * DAY);
+
+    pool.tick_day().await.unwrap();
+    assert_eq!(pool.stake_info().await.day_of_epoch, 2);
+}
+
+#[tokio::test]
+async fn the_crank_starts_the_epoch_after_the_bank_had_a_day() {
+    let mut pool = Pool::start(100 * LAMPORTS_PER_SOL).await;
+    pool.initialize(7 * LAMPORTS_PER_SOL).await.unwrap();
+
+    assert_eq!(
+        pool.set_crank_fee(MAX_CRANK_FEE + 1).await.unwrap_err(),
+        program_error(StakeError::CrankFeeTooHigh)
+    );
+    pool.set_crank_fee(MAX_CRANK_FEE).await.unwrap();
+    assert_eq!(pool.epoch_state().await.crank_fee, MAX_CRANK_FEE);
+
+    // when the epoch ends only the bank can start the next one
+    pool.warp_days(7).await;
+    assert_eq!(
+        pool.crank_start_epoch().await.unwrap_err(),
+        program_error(StakeError::CrankTooEarly)
+    );
+    pool.warp_to(pool.now + DAY - 1).await;
+    assert_eq!(
+        pool.crank_start_epoch().await.unwrap_err(),
+        program_error(StakeError::CrankTooEarly)
+    );
+
+    pool.warp_to(pool.now + 1).await;
+    pool.crank_start_epoch().await.unwrap();
+    assert_eq!(pool.epoch_state().await.epoch_no, 2);
+    assert_eq!(
+        pool.start_epoch().await.unwrap_err(),
+        program_error(StakeError::EpochWrongDays)
+    );
+}
+
+#[tokio::test]
+async fn stake_user_closes_once_nothing_is_staked() {
+    let mut pool = Pool::start(100 * LAMPORTS_PER_SOL).await;
+    pool.initialize(7 * LAMPORTS_PER_SOL).await.unwrap();
+    let alice = pool.new_user(LAMPORTS_PER_SOL).await;
+    let bob = pool.new_user(LAMPORTS_PER_SOL).await;
+    let nfts = pool.mint_nfts(&[&alice]).await;
+    let stake_user = pda::stake_user(&alice.pubkey()).0;
+
+    pool.stake(&alice, &nfts[0]).await.unwrap();
+    assert_eq!(
+        pool.close_stake_user(&alice).await.unwrap_err(),
+        program_error(StakeError::StakeUserNotEmpty)
+    );
+    next_day(&mut pool, &[&nfts[0]]).await;
+    pool.unstake(&alice, &nfts[0]).await.unwrap();
+    assert_eq!(pool.stake_user(&alice.pubkey()).await.staked_count, 0);
+
+    // the rent goes back to the user
+    let rent = pool.balance(&stake_user).await;
+    let balance = pool.balance(&alice.pubkey()).await;
+    pool.close_stake_user(&alice).await.unwrap();
+    assert_eq!(pool.balance(&stake_user).await, 0);
+    assert_eq!(pool.balance(&alice.pubkey()).await, balance + rent);
+
+    // a user who never staked closes too
+    let ix = instructions::close_stake_user(bob.pubkey());
+    let logs = pool.process_with_logs(&[ix], &[&bob]).await.unwrap();
+    let closed: Vec<StakeUserCloseEvent> = events(&logs, "StakeUserCloseEvent");
+    assert_eq!(closed.len(), 1);
+    assert_eq!(closed[0].user_account, bob.pubkey());
+    assert_eq!(pool.balance(&pda::stake_user(&bob.pubkey()).0).await, 0);
+}
+
+#[tokio::test]
+async fn weighted_nfts_split_the_day_by_weight() {
+    let mut pool = Pool::start(100 * LAMPORTS_PER_SOL).await;
+    pool.initialize(7 * LAMPORTS_PER_SOL).await.unwrap();
+    let alice = pool.new_user(LAMPORTS_PER_SOL).await;
+    let bob = pool.new_user(LAMPORTS_PER_SOL).await;
+    let nfts = pool.mint_weighted_nfts(&[(&alice, 5), (&alice, 2), (&bob, 1)]).await;
+    assert!(pool.merkle().await.is_weighted);
+
+    pool.stake_many(&alice, &[&nfts[0], &nfts[1]]).await.unwrap();
+    pool.stake(&bob, &nfts[2]).await.unwrap();
+    let stake_info = pool.stake_info().await;
+    assert_eq!(stake_info.total_stakers, 3);
+    assert_eq!(stake_info.total_weight, 8);
+    assert_eq!(pool.stake_user(&alice.pubkey()).await.staked_weight, 7);
+
+    // 1 SOL a day, 7/8 to alice and 1/8 to bob
+    let staked: Vec<&Nft> = nfts.iter().collect();
+    next_day(&mut pool, &staked).await;
+    next_day(&mut pool, &staked).await;
+    assert_eq!(pool.stake_user(&alice.pubkey()).await.pending_amount, 2 * (625_000_000 + 250_000_000));
+    assert_eq!(pool.stake_user(&bob.pubkey()).await.pending_amount, 2 * 125_000_000);
+
+    // the weight leaves with the nft, the pending reward is forfeited
+    pool.unstake(&alice, &nfts[0]).await.unwrap();
+    assert_eq!(pool.stake_info().await.total_weight, 3);
+    assert_eq!(pool.stake_user(&alice.pubkey()).await.staked_weight, 2);
+    next_day(&mut pool, &staked[1..]).await;
+    assert_eq!(pool.stake_user(&alice.pubkey()).await.pending_amount, 666_666_666);
+    assert_eq!(pool.stake_user(&bob.pubkey()).await.pending_amount, 2 * 125_000_000 + 333_333_333);
+}
+
+struct Staker {
+    name: &'static str,
+    keypair: Keypair,
+}
+
+// the program and the simulator agree on every balance
+async fn assert_matches(pool: &mut Pool, sim: &Simulator, stakers: &[Staker]) {
+    let epoch_state = pool.epoch_state().await;
+    let stake_info = pool.stake_info().await;
+    assert_eq!(epoch_state.epoch_no, sim.epoch_no);
+    assert_eq!(stake_info.day_of_epoch, sim.day_of_epoch);
+    assert_eq!(stake_info.days_passed, sim.ticked_days);
+    assert_eq!(epoch_state.epoch_credited, sim.epoch_credited);
+    assert_eq!(stake_info.total_stakers, sim.total_stakers);
+    assert_eq!(epoch_state.cur_epoch_reward_per_day, sim.cur_epoch_reward_per_day);
+    assert_eq!(epoch_state.remain_reward, sim.remain_reward);
+    assert_eq!(epoch_state.total_owed, sim.total_owed);
+    assert_eq!(pool.treasury_balance().await, sim.treasury);
+    assert_eq!(pool.bank_balance().await, sim.bank);
+
+    for staker in stakers {
+        let state = pool.stake_user(&staker.keypair.pubkey()).await;
+        let expected = sim.users.get(staker.name).cloned().unwrap_or_default();
+        assert_eq!(state.staked_count, expected.staked_count, "{}", staker.name);
+        assert_eq!(state.reward_amount, expected.reward_amount, "{}", staker.name);
+        assert_eq!(state.pending_amount, expected.pending_amount, "{}", staker.name);
+        assert_eq!(state.total_claimed, expected.total_claimed, "{}", staker.name);
+        assert_eq!(state.total_forfeited, expected.total_forfeited, "{}", staker.name);
+        assert_eq!(state.total_credited, expected.total_credited, "{}", staker.name);
+    }
+}
+
+#[tokio::test]
+async fn full_lifecycle_matches_the_simulator() {
+    const CRANK_FEE: u64 = 5000;
+
+    let mut pool = Pool::start(1000 * LAMPORTS_PER_SOL).await;
+    let mut sim = Simulator::new(Params {
+        crank_fee: CRANK_FEE,
+        ..Params::default()
+    });
+
+    pool.initialize(7 * LAMPORTS_PER_SOL).await.unwrap();
+    pool.set_crank_fee(CRANK_FEE).await.unwrap();
+    sim.apply(&Action::Deposit { amount: 7 * LAMPORTS_PER_SOL }).unwrap();
+    sim.apply(&Action::Initialize { amount: 7 * LAMPORTS_PER_SOL }).unwrap();
+
+    let mut stakers = Vec::new();
+    for &name in ["alice", "bob", "carol"].iter() {
+        let keypair = pool.new_user(LAMPORTS_PER_SOL).await;
+        stakers.push(Staker { name, keypair });
+    }
+    let nfts = pool
+        .mint_nfts(&[&stakers[0].keypair, &stakers[0].keypair, &stakers[1].keypair, &stakers[2].keypair])
+        .await;
+    // which nft is staked, and by whom
+    let owner_of = [0usize, 0, 1, 2];
+    let mut is_staked = [false; 4];
+    // the bank also paid the rent of the pool accounts and of the whitelist
+    sim.bank = pool.bank_balance().await;
+
+    for epoch in 1..=52u8 {
+        for day in 0..7 {
+            // stake and unstake before the day is credited
+            let moves: &[(usize, bool)] = match (epoch, day) {
+                (1, 0) => &[(0, true), (1, true), (2, true)],
+                (4, 3) => &[(3, true)],
+                (6, 2) => &[(2, false)],
+                (10, 0) => &[(2, true)],
+                (20, 5) => &[(0, false)],
+                (30, 1) => &[(3, false)],
+                (31, 0) => &[(0, true), (3, true)],
+                _ => &[],
+            };
+            for &(nft, stake) in moves {
+                let staker = &stakers[owner_of[nft]];
+                if stake {
+                    pool.stake(&staker.keypair, &nfts[nft]).await.unwrap();
+                    sim.apply(&Action::Stake {
+                        user: staker.name.to_string(),
+                        nft: nft.to_string(),
+                        weight: 1,
+                    })
+                    .unwrap();
+                } else {
+                    pool.unstake(&staker.keypair, &nfts[nft]).await.unwrap();
+                    sim.apply(&Action::Unstake {
+                        user: staker.name.to_string(),
+                        nft: nft.to_string(),
+                    })
+                    .unwrap();
+                }
+                is_staked[nft] = stake;
+            }
+
+            // the bank was emptied by the epoch start
+            if day == 1 && epoch % 3 == 0 {
+                let bank = pool.bank.pubkey();
+                pool.transfer(&bank, LAMPORTS_PER_SOL / 2).await;
+                sim.apply(&Action::Deposit { amount: LAMPORTS_PER_SOL / 2 }).unwrap();
+                pool.send_bonus(LAMPORTS_PER_SOL / 2).await.unwrap();
+                sim.apply(&Action::SendBonus { amount: LAMPORTS_PER_SOL / 2 }).unwrap();
+            }
+
+            pool.warp_days(1).await;
+            pool.tick_day().await.unwrap();
+            sim.apply(&Action::TickDay).unwrap();
+
+            let staked: Vec<&Nft> = nfts.iter().zip(is_staked.iter()).filter(|(_, s)| **s).map(|(n, _)| n).collect();
+            pool.daily_reward(&staked).await.unwrap();
+            sim.apply(&Action::DailyReward).unwrap();
+            assert_matches(&mut pool, &sim, &stakers).await;
+        }
+
+        // alice claims every epoch, bob every fifth
+        for (i, staker) in stakers.iter().enumerate() {
+            if i == 0 || (i == 1 && epoch % 5 == 0) {
+                let balance = pool.balance(&staker.keypair.pubkey()).await;
+                let claimable = sim.users.get(staker.name).map_or(0, |u| u.reward_amount);
+                pool.claim(&staker.keypair).await.unwrap();
+                sim.apply(&Action::Claim { user: staker.name.to_string() }).unwrap();
+                assert_eq!(pool.balance(&staker.keypair.pubkey()).await, balance + claimable);
+            }
+        }
+
+        let deposit = (epoch as u64 % 4 + 1) * LAMPORTS_PER_SOL;
+        let bank = pool.bank.pubkey();
+        pool.transfer(&bank, deposit).await;
+        sim.apply(&Action::Deposit { amount: deposit }).unwrap();
+
+        if epoch < 52 {
+            pool.start_epoch().await.unwrap();
+            sim.apply(&Action::StartEpoch).unwrap();
+        } else {
+            assert_eq!(pool.start_epoch().await.unwrap_err(), program_error(StakeError::EpochEnd));
+        }
+        assert_matches(&mut pool, &sim, &stakers).await;
+    }
+
+    // everyone takes what is left, and the treasury still covers it
+    for staker in &stakers {
+        pool.claim(&staker.keypair).await.unwrap();
+        sim.apply(&Action::Claim { user: staker.name.to_string() }).unwrap();
+    }
+    assert_matches(&mut pool, &sim, &stakers).await;
+    let epoch_state = pool.epoch_state().await;
+    assert!(pool.treasury_balance().await >= epoch_state.total_owed);
+    assert_eq!(pool.balance(&pda::treasury().0).await, sim.report().treasury_balance);
+}
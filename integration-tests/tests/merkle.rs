@@ -0,0 +1,184 @@
+use merkle_whitelist::Whitelist;
+use solana_program_test::tokio;
+use solana_sdk::instruction::InstructionError;
+use solana_sdk::signature::Signer;
+use solana_sdk::transaction::TransactionError;
+use token_stake_model::error::StakeError;
+use token_stake_model::merkle_proof;
+use token_stake_model_client::{instructions, pda};
+use token_stake_model_integration_tests::{Pool, LAMPORTS_PER_SOL};
+
+// anchor's ErrorCode::ConstraintSeeds
+const CONSTRAINT_SEEDS: u32 = 2006;
+
+fn program_error(err: StakeError) -> TransactionError {
+    TransactionError::InstructionError(0, InstructionError::Custom(err.into()))
+}
+
+#[tokio::test]
+async fn v2_roots_bound_the_proofs_and_v1_roots_keep_working() {
+    let mut pool = Pool::start(100 * LAMPORTS_PER_SOL).await;
+    pool.initialize(7 * LAMPORTS_PER_SOL).await.unwrap();
+    let alice = pool.new_user(LAMPORTS_PER_SOL).await;
+    let bank = pool.bank.pubkey();
+    let bank_signer = solana_sdk::signature::Keypair::from_bytes(&pool.bank.to_bytes()).unwrap();
+
+    pool.merkle_v2 = true;
+    let nfts = pool.mint_nfts(&[&alice, &alice, &alice]).await;
+    let whitelist = Whitelist::new_v2(nfts.iter().map(|nft| nft.mint).collect()).unwrap();
+    let merkle = pool.merkle().await;
+    assert!(merkle.is_hash_v2);
+    assert_eq!(merkle.max_depth, 2);
+
+    // a v1 proof of the same mints is not a v2 proof
+    let v1_proof = Whitelist::new(nfts.iter().map(|nft| nft.mint).collect())
+        .unwrap()
+        .proof(&nfts[0].mint)
+        .unwrap();
+    let ix = instructions::stake_nft(
+        alice.pubkey(),
+        nfts[0].token_account,
+        nfts[0].mint,
+        pda::merkle(&bank).0,
+        0,
+        v1_proof,
+    );
+    assert_eq!(
+        pool.process(&[ix], &[&alice]).await.unwrap_err(),
+        program_error(StakeError::InvalidProof)
+    );
+    pool.stake(&alice, &nfts[0]).await.unwrap();
+
+    // the same root with a lower depth rejects the longest proofs
+    let deepest = nfts[1..]
+        .iter()
+        .find(|nft| whitelist.proof(&nft.mint).unwrap().len() == 2)
+        .unwrap();
+    let ix = instructions::update_merkle_v2(bank, whitelist.root(), 1);
+    pool.process(&[ix], &[&bank_signer]).await.unwrap();
+    assert_eq!(
+        pool.stake(&alice, deepest).await.unwrap_err(),
+        program_error(StakeError::InvalidProof)
+    );
+
+    let ix = instructions::update_merkle_v2(bank, whitelist.root(), merkle_proof::MAX_DEPTH + 1);
+    assert_eq!(
+        pool.process(&[ix], &[&bank_signer]).await.unwrap_err(),
+        program_error(StakeError::MerkleTooDeep)
+    );
+
+    // back to a v1 root
+    pool.merkle_v2 = false;
+    let more = pool.mint_nfts(&[&alice]).await;
+    let merkle = pool.merkle().await;
+    assert!(!merkle.is_hash_v2);
+    pool.stake(&alice, &more[0]).await.unwrap();
+    assert_eq!(pool.stake_info().await.total_stakers, 2);
+}
+
+#[tokio::test]
+async fn nfts_dropped_from_the_whitelist_can_still_be_unstaked() {
+    let mut pool = Pool::start(100 * LAMPORTS_PER_SOL).await;
+    pool.initialize(7 * LAMPORTS_PER_SOL).await.unwrap();
+    let alice = pool.new_user(LAMPORTS_PER_SOL).await;
+    let nfts = pool.mint_nfts(&[&alice, &alice]).await;
+    pool.stake(&alice, &nfts[0]).await.unwrap();
+    pool.stake_many(&alice, &[&nfts[1]]).await.unwrap();
+
+    // a new collection replaces the whitelist
+    pool.mint_nfts(&[&alice]).await;
+    pool.unstake(&alice, &nfts[0]).await.unwrap();
+    pool.unstake_many(&alice, &[&nfts[1]]).await.unwrap();
+    assert_eq!(pool.token_amount(&nfts[0].token_account).await, 1);
+    assert_eq!(pool.token_amount(&nfts[1].token_account).await, 1);
+    assert_eq!(pool.stake_info().await.total_stakers, 0);
+
+    // but they can not be staked again with a proof of the old whitelist
+    let proof = Whitelist::new(vec![nfts[0].mint, nfts[1].mint])
+        .unwrap()
+        .proof(&nfts[0].mint)
+        .unwrap();
+    let merkle = pda::merkle(&pool.bank.pubkey()).0;
+    let ix = instructions::stake_nft(alice.pubkey(), nfts[0].token_account, nfts[0].mint, merkle, 0, proof);
+    assert_eq!(
+        pool.process(&[ix], &[&alice]).await.unwrap_err(),
+        program_error(StakeError::InvalidProof)
+    );
+}
+
+#[tokio::test]
+async fn unstake_takes_the_vault_of_the_stake_record() {
+    let mut pool = Pool::start(100 * LAMPORTS_PER_SOL).await;
+    pool.initialize(7 * LAMPORTS_PER_SOL).await.unwrap();
+    let alice = pool.new_user(LAMPORTS_PER_SOL).await;
+    let bob = pool.new_user(LAMPORTS_PER_SOL).await;
+    let nfts = pool.mint_nfts(&[&alice, &bob]).await;
+    pool.stake(&alice, &nfts[0]).await.unwrap();
+    pool.stake(&bob, &nfts[1]).await.unwrap();
+
+    // alice's stake record with bob's vault
+    let alice_token_account = pool.new_token_account(&alice.pubkey(), &nfts[1].mint).await;
+    let mut ix = instructions::unstake_nft(alice.pubkey(), alice_token_account, nfts[1].mint, 0);
+    ix.accounts[3].pubkey = pda::vault_stake(&nfts[1].mint, &bob.pubkey()).0;
+    ix.accounts[4].pubkey = pda::user_stake(&nfts[0].mint, &alice.pubkey()).0;
+    assert_eq!(
+        pool.process(&[ix], &[&alice]).await.unwrap_err(),
+        TransactionError::InstructionError(0, InstructionError::Custom(CONSTRAINT_SEEDS))
+    );
+    assert_eq!(pool.token_amount(&pda::vault_stake(&nfts[1].mint, &bob.pubkey()).0).await, 1);
+}
+
+#[tokio::test]
+async fn whitelists_of_other_wallets_are_rejected() {
+    let mut pool = Pool::start(100 * LAMPORTS_PER_SOL).await;
+    pool.initialize(7 * LAMPORTS_PER_SOL).await.unwrap();
+    let alice = pool.new_user(LAMPORTS_PER_SOL).await;
+    let nfts = pool.mint_nfts(&[&alice, &alice]).await;
+
+    // alice posts her own root giving her nfts the highest weight
+    let whitelist = Whitelist::new_weighted(nfts.iter().map(|nft| (nft.mint, u16::MAX)).collect()).unwrap();
+    let ixs = [
+        instructions::initialize_merkle(alice.pubkey(), whitelist.root()),
+        instructions::update_merkle_weighted(alice.pubkey(), whitelist.root(), whitelist.max_depth()),
+    ];
+    pool.process(&ixs, &[&alice]).await.unwrap();
+    let foreign_merkle = pda::merkle(&alice.pubkey()).0;
+
+    let proof = whitelist.proof(&nfts[0].mint).unwrap();
+    let ix = instructions::stake_weighted_nft(
+        alice.pubkey(),
+        nfts[0].token_account,
+        nfts[0].mint,
+        foreign_merkle,
+        0,
+        proof,
+        u16::MAX,
+    );
+    assert_eq!(
+        pool.process(&[ix], &[&alice]).await.unwrap_err(),
+        TransactionError::InstructionError(0, InstructionError::Custom(CONSTRAINT_SEEDS))
+    );
+
+    let mints: Vec<_> = nfts.iter().map(|nft| nft.mint).collect();
+    let multiproof = whitelist.multiproof(&mints).unwrap();
+    let leaves: Vec<(u32, u16)> = multiproof.indices.iter().map(|index| (*index, u16::MAX)).collect();
+    let ix = instructions::stake_many(
+        alice.pubkey(),
+        &nfts.iter().map(|nft| (nft.mint, nft.token_account)).collect::<Vec<_>>(),
+        foreign_merkle,
+        0,
+        multiproof.proof,
+        &leaves,
+        multiproof.num_leaves,
+    );
+    assert_eq!(
+        pool.process(&[ix], &[&alice]).await.unwrap_err(),
+        TransactionError::InstructionError(0, InstructionError::Custom(CONSTRAINT_SEEDS))
+    );
+
+    // the pool whitelist still stakes them with their own weight
+    pool.stake(&alice, &nfts[0]).await.unwrap();
+    let stake_info = pool.stake_info().await;
+    assert_eq!(stake_info.total_stakers, 1);
+    assert_eq!(stake_info.total_weight(), 1);
+}
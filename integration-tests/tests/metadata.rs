@@ -0,0 +1,184 @@
+use solana_program_test::tokio;
+use solana_sdk::instruction::{AccountMeta, InstructionError};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signer;
+use solana_sdk::transaction::TransactionError;
+use token_stake_model::error::StakeError;
+use token_stake_model::metadata::{Collection, Creator, Metadata};
+use token_stake_model::{WHITELIST_COLLECTION, WHITELIST_CREATOR, WHITELIST_MERKLE};
+use token_stake_model_client::{instructions, pda};
+use token_stake_model_integration_tests::{Nft, Pool, LAMPORTS_PER_SOL};
+
+fn program_error(index: u8, err: StakeError) -> TransactionError {
+    TransactionError::InstructionError(index, InstructionError::Custom(err.into()))
+}
+
+fn in_collection(nft: &Nft, collection_mint: &Pubkey, verified: bool) -> Metadata {
+    Metadata {
+        update_authority: Pubkey::new_unique(),
+        mint: nft.mint,
+        creators: vec![],
+        collection: Some(Collection { verified, key: *collection_mint }),
+    }
+}
+
+fn by_creators(nft: &Nft, update_authority: &Pubkey, creators: &[(Pubkey, bool)]) -> Metadata {
+    Metadata {
+        update_authority: *update_authority,
+        mint: nft.mint,
+        creators: creators
+            .iter()
+            .map(|(address, verified)| Creator { address: *address, verified: *verified, share: 50 })
+            .collect(),
+        collection: None,
+    }
+}
+
+#[tokio::test]
+async fn verified_collection_members_stake_without_a_proof() {
+    let mut pool = Pool::start(100 * LAMPORTS_PER_SOL).await;
+    pool.initialize(7 * LAMPORTS_PER_SOL).await.unwrap();
+    let alice = pool.new_user(LAMPORTS_PER_SOL).await;
+    let nfts = pool.mint_nfts(&[&alice, &alice, &alice, &alice, &alice]).await;
+    let collection_mint = Pubkey::new_unique();
+
+    pool.set_metadata(&in_collection(&nfts[0], &collection_mint, true)).await;
+    pool.set_metadata(&in_collection(&nfts[1], &collection_mint, false)).await;
+    pool.set_metadata(&in_collection(&nfts[2], &Pubkey::new_unique(), true)).await;
+    let mut no_collection = in_collection(&nfts[3], &collection_mint, true);
+    no_collection.collection = None;
+    pool.set_metadata(&no_collection).await;
+
+    pool.set_whitelist_collection(&collection_mint).await.unwrap();
+    let merkle = pool.merkle().await;
+    assert_eq!(merkle.whitelist_mode, WHITELIST_COLLECTION);
+    assert_eq!(merkle.collection_mint, collection_mint);
+
+    // the root no longer whitelists anything
+    assert_eq!(
+        pool.stake(&alice, &nfts[0]).await.unwrap_err(),
+        program_error(0, StakeError::WrongMetadata)
+    );
+    pool.stake_by_metadata(&alice, &nfts[0]).await.unwrap();
+    assert_eq!(pool.stake_info().await.total_stakers, 1);
+
+    for nft in &nfts[1..4] {
+        assert_eq!(
+            pool.stake_by_metadata(&alice, nft).await.unwrap_err(),
+            program_error(0, StakeError::NotWhitelisted)
+        );
+    }
+    // no metadata account
+    assert_eq!(
+        pool.stake_by_metadata(&alice, &nfts[4]).await.unwrap_err(),
+        program_error(0, StakeError::WrongMetadata)
+    );
+
+    // a copy of good metadata not owned by the metadata program, or at
+    // another address
+    let forged = in_collection(&nfts[4], &collection_mint, true);
+    pool.set_metadata_account(&pda::metadata(&nfts[4].mint).0, &alice.pubkey(), &forged).await;
+    assert_eq!(
+        pool.stake_by_metadata(&alice, &nfts[4]).await.unwrap_err(),
+        program_error(0, StakeError::WrongMetadata)
+    );
+    let elsewhere = Pubkey::new_unique();
+    pool.set_metadata_account(&elsewhere, &token_stake_model::metadata::ID, &forged).await;
+    let merkle = pda::merkle(&pool.bank.pubkey()).0;
+    let mut ix = instructions::stake_nft_by_metadata(alice.pubkey(), nfts[4].token_account, nfts[4].mint, merkle, 0);
+    *ix.accounts.last_mut().unwrap() = AccountMeta::new_readonly(elsewhere, false);
+    assert_eq!(
+        pool.process(&[ix], &[&alice]).await.unwrap_err(),
+        program_error(0, StakeError::WrongMetadata)
+    );
+    // the metadata of another nft of the collection
+    let mut ix = instructions::stake_nft_by_metadata(alice.pubkey(), nfts[4].token_account, nfts[4].mint, merkle, 0);
+    *ix.accounts.last_mut().unwrap() = AccountMeta::new_readonly(pda::metadata(&nfts[0].mint).0, false);
+    assert_eq!(
+        pool.process(&[ix], &[&alice]).await.unwrap_err(),
+        program_error(0, StakeError::WrongMetadata)
+    );
+
+    // posting a root goes back to the Merkle whitelist
+    let bank_signer = solana_sdk::signature::Keypair::from_bytes(&pool.bank.to_bytes()).unwrap();
+    let root = merkle_whitelist::Whitelist::new(nfts.iter().map(|nft| nft.mint).collect()).unwrap().root();
+    pool.process(&[instructions::update_merkle(pool.bank.pubkey(), root)], &[&bank_signer])
+        .await
+        .unwrap();
+    assert_eq!(pool.merkle().await.whitelist_mode, WHITELIST_MERKLE);
+    pool.stake(&alice, &nfts[1]).await.unwrap();
+}
+
+#[tokio::test]
+async fn stake_many_checks_the_metadata_of_every_nft() {
+    let mut pool = Pool::start(100 * LAMPORTS_PER_SOL).await;
+    pool.initialize(7 * LAMPORTS_PER_SOL).await.unwrap();
+    let alice = pool.new_user(LAMPORTS_PER_SOL).await;
+    let nfts = pool.mint_nfts(&[&alice, &alice, &alice]).await;
+    let collection_mint = Pubkey::new_unique();
+    pool.set_metadata(&in_collection(&nfts[0], &collection_mint, true)).await;
+    pool.set_metadata(&in_collection(&nfts[1], &collection_mint, true)).await;
+    pool.set_metadata(&in_collection(&nfts[2], &collection_mint, false)).await;
+    pool.set_whitelist_collection(&collection_mint).await.unwrap();
+
+    // the compute budget instruction goes first
+    assert_eq!(
+        pool.stake_many_by_metadata(&alice, &[&nfts[0], &nfts[2]]).await.unwrap_err(),
+        program_error(1, StakeError::NotWhitelisted)
+    );
+    assert_eq!(
+        pool.stake_many(&alice, &[&nfts[0], &nfts[1]]).await.unwrap_err(),
+        program_error(1, StakeError::WrongBatchAccounts)
+    );
+    pool.stake_many_by_metadata(&alice, &[&nfts[0], &nfts[1]]).await.unwrap();
+    assert_eq!(pool.stake_user(&alice.pubkey()).await.staked_count, 2);
+    assert_eq!(pool.token_amount(&nfts[0].token_account).await, 0);
+
+    pool.unstake_many(&alice, &[&nfts[0], &nfts[1]]).await.unwrap();
+    assert_eq!(pool.token_amount(&nfts[1].token_account).await, 1);
+}
+
+#[tokio::test]
+async fn verified_creators_stake_with_the_update_authority_of_the_pool() {
+    let mut pool = Pool::start(100 * LAMPORTS_PER_SOL).await;
+    pool.initialize(7 * LAMPORTS_PER_SOL).await.unwrap();
+    let alice = pool.new_user(LAMPORTS_PER_SOL).await;
+    let nfts = pool.mint_nfts(&[&alice, &alice, &alice, &alice, &alice]).await;
+    let (creator, other_creator) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let (authority, other_authority) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+    pool.set_metadata(&by_creators(&nfts[0], &authority, &[(other_creator, true), (creator, true)])).await;
+    pool.set_metadata(&by_creators(&nfts[1], &other_authority, &[(creator, true)])).await;
+    pool.set_metadata(&by_creators(&nfts[2], &authority, &[(creator, false), (other_creator, true)])).await;
+    pool.set_metadata(&by_creators(&nfts[3], &authority, &[])).await;
+    // in the collection of the pool, but not by its creator
+    let mut collection_only = by_creators(&nfts[4], &authority, &[(other_creator, true)]);
+    let collection_mint = Pubkey::new_unique();
+    collection_only.collection = Some(Collection { verified: true, key: collection_mint });
+    pool.set_metadata(&collection_only).await;
+    pool.set_whitelist_collection(&collection_mint).await.unwrap();
+
+    pool.set_whitelist_creator(&creator, Some(authority)).await.unwrap();
+    let merkle = pool.merkle().await;
+    assert_eq!(merkle.whitelist_mode, WHITELIST_CREATOR);
+    assert_eq!(merkle.creator, creator);
+    assert_eq!(merkle.update_authority, authority);
+
+    pool.stake_by_metadata(&alice, &nfts[0]).await.unwrap();
+    for nft in &nfts[1..] {
+        assert_eq!(
+            pool.stake_by_metadata(&alice, nft).await.unwrap_err(),
+            program_error(0, StakeError::NotWhitelisted)
+        );
+    }
+
+    // any update authority once the pool does not check it
+    pool.set_whitelist_creator(&creator, None).await.unwrap();
+    assert_eq!(pool.merkle().await.update_authority, Pubkey::default());
+    pool.stake_by_metadata(&alice, &nfts[1]).await.unwrap();
+    assert_eq!(
+        pool.stake_by_metadata(&alice, &nfts[2]).await.unwrap_err(),
+        program_error(0, StakeError::NotWhitelisted)
+    );
+    assert_eq!(pool.stake_info().await.total_stakers, 2);
+}
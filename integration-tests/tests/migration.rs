@@ -0,0 +1,171 @@
+use anchor_lang::AccountSerialize;
+use solana_program_test::tokio;
+use solana_sdk::hash::hash;
+use solana_sdk::instruction::InstructionError;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::transaction::TransactionError;
+use std::mem::size_of;
+use token_stake_model::error::StakeError;
+use token_stake_model::events::MigrationEvent;
+use token_stake_model::migration::{MerkleV1, StakeNftInfoStateV1, StakeUserStateV1};
+use token_stake_model::{Merkle, StakeNftInfoState, StakeUserState};
+use token_stake_model_client::{instructions, pda, WHITELIST_MERKLE};
+use token_stake_model_integration_tests::{events, Pool, DAY, LAMPORTS_PER_SOL, START_TIME};
+
+// the epoch state and stake info migrations are signed by the upgrade
+// authority, which the builtin program of program-test does not have
+
+fn program_error(err: StakeError) -> TransactionError {
+    TransactionError::InstructionError(0, InstructionError::Custom(err.into()))
+}
+
+const GRACE_DAYS: i64 = 30;
+
+// the stake info of a pool migrated with `v1_stakers` nfts staked in v1
+async fn set_v1_stakers(pool: &mut Pool, v1_stakers: u16) {
+    let mut stake_info = pool.stake_info().await;
+    stake_info.total_stakers = v1_stakers;
+    stake_info.v1_stakers = v1_stakers;
+    let mut data = Vec::new();
+    stake_info.try_serialize(&mut data).unwrap();
+    pool.set_program_account(&pda::stake_info().0, data).await;
+}
+
+// a v1 account: the discriminator of `name` then `fields`, padded to the
+// `size_of` the v1 accounts were created with
+fn v1_account<T>(name: &str, fields: &[&[u8]]) -> Vec<u8> {
+    let mut data = hash(format!("account:{}", name).as_bytes()).to_bytes()[..8].to_vec();
+    for field in fields {
+        data.extend_from_slice(field);
+    }
+    data.resize(8 + size_of::<T>(), 0);
+    data
+}
+
+#[tokio::test]
+async fn v1_stake_user_is_counted_from_its_records() {
+    let mut pool = Pool::start(100 * LAMPORTS_PER_SOL).await;
+    pool.initialize(7 * LAMPORTS_PER_SOL).await.unwrap();
+    set_v1_stakers(&mut pool, 2).await;
+    let user = Keypair::new();
+    pool.transfer(&user.pubkey(), LAMPORTS_PER_SOL).await;
+    let mints = [Pubkey::new_unique(), Pubkey::new_unique()];
+    let stake_time = START_TIME - DAY;
+    for mint in &mints {
+        let data = v1_account::<StakeNftInfoStateV1>(
+            "StakeNftInfoState",
+            &[user.pubkey().as_ref(), mint.as_ref(), &stake_time.to_le_bytes()],
+        );
+        pool.set_program_account(&pda::user_stake(mint, &user.pubkey()).0, data).await;
+    }
+    let data = v1_account::<StakeUserStateV1>(
+        "StakeUserState",
+        &[&[1], user.pubkey().as_ref(), &5u64.to_le_bytes(), &7u64.to_le_bytes()],
+    );
+    let stake_user = pda::stake_user(&user.pubkey()).0;
+    pool.set_program_account(&stake_user, data).await;
+
+    // the user grows their stake records
+    let record = pda::user_stake(&mints[0], &user.pubkey()).0;
+    let ix = instructions::migrate_stake_nft_info(user.pubkey(), mints[0]);
+    let logs = pool.process_with_logs(&[ix], &[&user]).await.unwrap();
+    let migrated: Vec<MigrationEvent> = events(&logs, "MigrationEvent");
+    assert_eq!(migrated.len(), 1);
+    assert_eq!(migrated[0].account, record);
+    assert_eq!(migrated[0].version, StakeNftInfoState::VERSION);
+    assert_eq!(pool.data_len(&record).await, 8 + StakeNftInfoState::LEN);
+    let stake_nft_info = pool.stake_nft_info(&mints[0], &user.pubkey()).await;
+    assert_eq!(stake_nft_info.version, StakeNftInfoState::VERSION);
+    assert_eq!(stake_nft_info.user_account, user.pubkey());
+    assert_eq!(stake_nft_info.nft_mint, mints[0]);
+    assert_eq!(stake_nft_info.stake_time, stake_time);
+    assert!(!stake_nft_info.is_registered);
+    assert_eq!(stake_nft_info.weight(), 1);
+    assert_eq!(stake_nft_info.credited_epoch_start, 0);
+
+    // only the bank lists the records, a user could leave some out
+    let impostor = Keypair::new();
+    pool.transfer(&impostor.pubkey(), LAMPORTS_PER_SOL).await;
+    let ix = instructions::migrate_stake_user(impostor.pubkey(), user.pubkey(), &mints[..1]);
+    assert!(pool.process(&[ix], &[&impostor]).await.is_err());
+
+    // v1 and migrated records are both counted
+    pool.migrate_stake_user(&user.pubkey(), &mints).await.unwrap();
+    assert_eq!(pool.data_len(&stake_user).await, 8 + StakeUserState::LEN);
+    let state = pool.stake_user(&user.pubkey()).await;
+    assert_eq!(state.version, StakeUserState::VERSION);
+    assert!(state.is_initial);
+    assert_eq!(state.user_account, user.pubkey());
+    assert_eq!(state.reward_amount, 5);
+    assert_eq!(state.pending_amount, 7);
+    assert_eq!(state.staked_count, 2);
+    assert_eq!(state.staked_weight(), 2);
+    assert_eq!(state.total_claimed, 0);
+    assert_eq!(state.total_credited, 0);
+    assert_eq!(state.first_stake_time, 0);
+    // every v1 nft is counted, and what the user is owed is
+    assert_eq!(pool.stake_info().await.v1_stakers, 0);
+    assert_eq!(pool.epoch_state().await.total_owed, 5 + 7);
+
+    assert_eq!(
+        pool.migrate_stake_user(&user.pubkey(), &mints).await.unwrap_err(),
+        program_error(StakeError::AccountVersion)
+    );
+    assert_eq!(
+        pool.close_stake_user(&user).await.unwrap_err(),
+        program_error(StakeError::StakeUserNotEmpty)
+    );
+}
+
+#[tokio::test]
+async fn uncounted_v1_nfts_hold_the_close_and_the_reclaim() {
+    let mut pool = Pool::start(100 * LAMPORTS_PER_SOL).await;
+    pool.initialize(7 * LAMPORTS_PER_SOL).await.unwrap();
+    set_v1_stakers(&mut pool, 1).await;
+    let user = Keypair::new();
+    pool.transfer(&user.pubkey(), LAMPORTS_PER_SOL).await;
+    let mint = Pubkey::new_unique();
+    let data = v1_account::<StakeNftInfoStateV1>("StakeNftInfoState", &[user.pubkey().as_ref(), mint.as_ref()]);
+    pool.set_program_account(&pda::user_stake(&mint, &user.pubkey()).0, data).await;
+    let data = v1_account::<StakeUserStateV1>("StakeUserState", &[&[1], user.pubkey().as_ref()]);
+    pool.set_program_account(&pda::stake_user(&user.pubkey()).0, data).await;
+
+    // the bank leaves the staked nft out, the user could close with it in the vault
+    pool.migrate_stake_user(&user.pubkey(), &[]).await.unwrap();
+    assert_eq!(pool.stake_user(&user.pubkey()).await.staked_count, 0);
+    assert_eq!(pool.stake_info().await.v1_stakers, 1);
+    assert_eq!(
+        pool.close_stake_user(&user).await.unwrap_err(),
+        program_error(StakeError::MigrationPending)
+    );
+
+    pool.sunset().await.unwrap();
+    pool.warp_days(GRACE_DAYS).await;
+    assert_eq!(
+        pool.reclaim_treasury().await.unwrap_err(),
+        program_error(StakeError::MigrationPending)
+    );
+}
+
+#[tokio::test]
+async fn v1_merkle_grows_into_a_merkle_whitelist() {
+    let mut pool = Pool::start(100 * LAMPORTS_PER_SOL).await;
+    let root = [3; 32];
+    let bank = pool.bank.pubkey();
+    let merkle = pda::merkle(&bank).0;
+    let data = v1_account::<MerkleV1>("Merkle", &[&root, bank.as_ref(), &[1]]);
+    pool.set_program_account(&merkle, data).await;
+
+    pool.migrate_merkle().await.unwrap();
+    assert_eq!(pool.data_len(&merkle).await, 8 + Merkle::LEN);
+    let state = pool.merkle().await;
+    assert_eq!(state.version, Merkle::VERSION);
+    assert_eq!(state.root, root);
+    assert_eq!(state.admin_account, bank);
+    assert!(state.is_init);
+    assert!(!state.is_hash_v2);
+    assert!(!state.is_weighted);
+    assert_eq!(state.max_depth, 0);
+    assert_eq!(state.whitelist_mode, WHITELIST_MERKLE);
+}
@@ -0,0 +1,82 @@
+use solana_program_test::tokio;
+use solana_sdk::instruction::InstructionError;
+use solana_sdk::signature::Signer;
+use solana_sdk::transaction::TransactionError;
+use token_stake_model::error::StakeError;
+use token_stake_model::REGISTRY_PAGE_SIZE;
+use token_stake_model_client::{instructions, pda};
+use token_stake_model_integration_tests::{Pool, LAMPORTS_PER_SOL};
+
+fn program_error(err: StakeError) -> TransactionError {
+    TransactionError::InstructionError(0, InstructionError::Custom(err.into()))
+}
+
+#[tokio::test]
+async fn staked_nfts_are_listed_and_unlisted() {
+    let mut pool = Pool::start(100 * LAMPORTS_PER_SOL).await;
+    pool.initialize(7 * LAMPORTS_PER_SOL).await.unwrap();
+    let alice = pool.new_user(LAMPORTS_PER_SOL).await;
+    let bob = pool.new_user(LAMPORTS_PER_SOL).await;
+    let nfts = pool.mint_nfts(&[&alice, &bob, &alice]).await;
+
+    let registry = pool.registry(0).await;
+    assert_eq!(registry.version, 1);
+    assert_eq!(registry.bump, pda::stake_registry(0).1);
+    assert_eq!(registry.page, 0);
+    assert_eq!(registry.count, 0);
+
+    for nft in nfts.iter() {
+        let owner = if nft.owner == alice.pubkey() { &alice } else { &bob };
+        pool.stake(owner, nft).await.unwrap();
+    }
+    let registry = pool.registry(0).await;
+    assert_eq!(registry.count, 3);
+    for (entry, nft) in registry.entries.iter().zip(nfts.iter()) {
+        assert_eq!(entry.nft_mint, nft.mint);
+        assert_eq!(entry.user_account, nft.owner);
+    }
+
+    // the last entry takes the place of the unstaked one
+    pool.unstake(&alice, &nfts[0]).await.unwrap();
+    let registry = pool.registry(0).await;
+    assert_eq!(registry.count, 2);
+    assert_eq!(registry.entries[0].nft_mint, nfts[2].mint);
+    assert_eq!(registry.entries[1].nft_mint, nfts[1].mint);
+    assert_eq!(registry.entries[2].nft_mint, Default::default());
+
+    pool.unstake(&bob, &nfts[1]).await.unwrap();
+    pool.unstake(&alice, &nfts[2]).await.unwrap();
+    assert_eq!(pool.registry(0).await.count, 0);
+}
+
+#[tokio::test]
+async fn a_full_page_sends_stakes_to_the_next_one() {
+    let mut pool = Pool::start(100 * LAMPORTS_PER_SOL).await;
+    pool.initialize(7 * LAMPORTS_PER_SOL).await.unwrap();
+    let alice = pool.new_user(10 * LAMPORTS_PER_SOL).await;
+    let nfts = pool.mint_nfts(&vec![&alice; REGISTRY_PAGE_SIZE + 1]).await;
+
+    for nft in nfts[..REGISTRY_PAGE_SIZE].iter() {
+        pool.stake(&alice, nft).await.unwrap();
+    }
+    assert_eq!(pool.registry(0).await.count as usize, REGISTRY_PAGE_SIZE);
+    let last = &nfts[REGISTRY_PAGE_SIZE];
+    assert_eq!(
+        pool.stake(&alice, last).await.unwrap_err(),
+        program_error(StakeError::RegistryFull)
+    );
+
+    let bank = pool.bank.pubkey();
+    let bank_signer = solana_sdk::signature::Keypair::from_bytes(&pool.bank.to_bytes()).unwrap();
+    pool.process(&[instructions::initialize_registry_page(bank, 1)], &[&bank_signer])
+        .await
+        .unwrap();
+    pool.stake_on_page(&alice, last, 1).await.unwrap();
+
+    let registry = pool.registry(1).await;
+    assert_eq!(registry.bump, pda::stake_registry(1).1);
+    assert_eq!(registry.page, 1);
+    assert_eq!(registry.count, 1);
+    assert_eq!(registry.entries[0].nft_mint, last.mint);
+    assert_eq!(pool.stake_info().await.total_stakers as usize, REGISTRY_PAGE_SIZE + 1);
+}
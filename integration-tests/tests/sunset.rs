@@ -0,0 +1,172 @@
+use solana_program_test::tokio;
+use solana_sdk::instruction::InstructionError;
+use solana_sdk::signature::Signer;
+use solana_sdk::transaction::TransactionError;
+use token_stake_model::error::StakeError;
+use token_stake_model_client::pda;
+use token_stake_model_integration_tests::{Nft, Pool, LAMPORTS_PER_SOL};
+
+fn program_error(err: StakeError) -> TransactionError {
+    TransactionError::InstructionError(0, InstructionError::Custom(err.into()))
+}
+
+// tick to the next day and credit every staked nft
+async fn next_day(pool: &mut Pool, staked: &[&Nft]) {
+    pool.warp_days(1).await;
+    pool.tick_day().await.unwrap();
+    pool.daily_reward(staked).await.unwrap();
+}
+
+const GRACE_DAYS: i64 = 30;
+
+#[tokio::test]
+async fn sunset_claim_unstake_reclaim_close() {
+    let mut pool = Pool::start(100 * LAMPORTS_PER_SOL).await;
+    pool.initialize(7 * LAMPORTS_PER_SOL).await.unwrap();
+    let alice = pool.new_user(LAMPORTS_PER_SOL).await;
+    let bob = pool.new_user(LAMPORTS_PER_SOL).await;
+    let nfts = pool.mint_nfts(&[&alice, &bob, &bob]).await;
+    pool.stake(&alice, &nfts[0]).await.unwrap();
+    pool.stake(&bob, &nfts[1]).await.unwrap();
+    let staked = [&nfts[0], &nfts[1]];
+    next_day(&mut pool, &staked).await;
+    next_day(&mut pool, &staked).await;
+
+    pool.sunset().await.unwrap();
+    let epoch_state = pool.epoch_state().await;
+    assert!(epoch_state.is_sunset);
+    assert_eq!(epoch_state.sunset_time, pool.now);
+    assert_eq!(epoch_state.total_owed, 2 * LAMPORTS_PER_SOL);
+    assert_eq!(pool.stake(&bob, &nfts[2]).await.unwrap_err(), program_error(StakeError::PoolSunset));
+
+    // nothing is credited anymore, the pending reward is claimable
+    let rewards = pool.user_rewards(&bob.pubkey()).await;
+    assert_eq!(rewards.claimable_amount, LAMPORTS_PER_SOL);
+    assert_eq!(rewards.pending_amount, 0);
+    assert_eq!(rewards.projected_pending_amount, 0);
+    assert!(pool.pool_stats().await.is_sunset);
+    assert_eq!(pool.estimated_apr(LAMPORTS_PER_SOL).await.reward_per_nft_per_day, 0);
+
+    // the pending reward of the last epoch is claimable right away
+    let balance = pool.balance(&alice.pubkey()).await;
+    pool.claim(&alice).await.unwrap();
+    assert_eq!(pool.balance(&alice.pubkey()).await, balance + LAMPORTS_PER_SOL);
+    let stake_user = pool.stake_user(&alice.pubkey()).await;
+    assert_eq!(stake_user.pending_amount, 0);
+    assert_eq!(stake_user.total_claimed, LAMPORTS_PER_SOL);
+    assert_eq!(pool.epoch_state().await.total_owed, LAMPORTS_PER_SOL);
+
+    pool.unstake(&alice, &nfts[0]).await.unwrap();
+    assert_eq!(pool.token_amount(&nfts[0].token_account).await, 1);
+    assert_eq!(pool.stake_user(&alice.pubkey()).await.total_forfeited, 0);
+
+    // the treasury and the accounts stay until the grace period is over
+    assert_eq!(pool.reclaim_treasury().await.unwrap_err(), program_error(StakeError::SunsetGracePeriod));
+    assert_eq!(pool.close_pool().await.unwrap_err(), program_error(StakeError::SunsetGracePeriod));
+    pool.warp_days(GRACE_DAYS).await;
+
+    let balance = pool.balance(&bob.pubkey()).await;
+    pool.claim(&bob).await.unwrap();
+    pool.unstake(&bob, &nfts[1]).await.unwrap();
+    assert!(pool.balance(&bob.pubkey()).await > balance + LAMPORTS_PER_SOL);
+    assert_eq!(pool.stake_user(&bob.pubkey()).await.total_claimed, LAMPORTS_PER_SOL);
+    assert_eq!(pool.epoch_state().await.total_owed, 0);
+
+    // nothing is owed, the whole treasury goes back to the bank
+    let treasury_balance = pool.treasury_balance().await;
+    let bank_balance = pool.bank_balance().await;
+    pool.reclaim_treasury().await.unwrap();
+    assert_eq!(pool.treasury_balance().await, 0);
+    assert_eq!(pool.bank_balance().await, bank_balance + treasury_balance);
+
+    let epoch_state_rent = pool.balance(&pda::epoch_state().0).await;
+    let stake_info_rent = pool.balance(&pda::stake_info().0).await;
+    let bank_balance = pool.bank_balance().await;
+    pool.close_pool().await.unwrap();
+    assert_eq!(pool.balance(&pda::epoch_state().0).await, 0);
+    assert_eq!(pool.balance(&pda::stake_info().0).await, 0);
+    assert_eq!(pool.bank_balance().await, bank_balance + epoch_state_rent + stake_info_rent);
+}
+
+#[tokio::test]
+async fn bank_settles_what_users_left_behind() {
+    let mut pool = Pool::start(100 * LAMPORTS_PER_SOL).await;
+    pool.initialize(7 * LAMPORTS_PER_SOL).await.unwrap();
+    let alice = pool.new_user(LAMPORTS_PER_SOL).await;
+    let nfts = pool.mint_nfts(&[&alice]).await;
+    pool.stake(&alice, &nfts[0]).await.unwrap();
+    next_day(&mut pool, &[&nfts[0]]).await;
+    pool.sunset().await.unwrap();
+
+    assert_eq!(pool.force_unstake(&nfts[0]).await.unwrap_err(), program_error(StakeError::SunsetGracePeriod));
+    assert_eq!(pool.settle_user(&alice.pubkey()).await.unwrap_err(), program_error(StakeError::SunsetGracePeriod));
+    pool.warp_days(GRACE_DAYS).await;
+
+    // alice never came back, her nft still holds the pool open
+    assert_eq!(pool.close_pool().await.unwrap_err(), program_error(StakeError::PoolNotEmpty));
+
+    // what she is owed stays in the treasury
+    pool.reclaim_treasury().await.unwrap();
+    let owed = pool.epoch_state().await.total_owed;
+    assert_eq!(owed, LAMPORTS_PER_SOL);
+    assert!(pool.treasury_balance().await > owed);
+
+    let record_rent = pool.balance(&pda::user_stake(&nfts[0].mint, &alice.pubkey()).0).await;
+    let vault_rent = pool.balance(&pda::vault_stake(&nfts[0].mint, &alice.pubkey()).0).await;
+    let balance = pool.balance(&alice.pubkey()).await;
+    pool.force_unstake(&nfts[0]).await.unwrap();
+    assert_eq!(pool.token_amount(&nfts[0].token_account).await, 1);
+    assert_eq!(pool.balance(&alice.pubkey()).await, balance + record_rent + vault_rent);
+    assert_eq!(pool.stake_info().await.total_stakers, 0);
+    assert_eq!(pool.registry(0).await.count, 0);
+    let stake_user = pool.stake_user(&alice.pubkey()).await;
+    assert_eq!(stake_user.staked_count, 0);
+    assert_eq!(stake_user.pending_amount, 0);
+    assert_eq!(stake_user.reward_amount, owed);
+
+    // the reward is still owed
+    assert_eq!(pool.close_pool().await.unwrap_err(), program_error(StakeError::PoolNotEmpty));
+
+    let balance = pool.balance(&alice.pubkey()).await;
+    pool.settle_user(&alice.pubkey()).await.unwrap();
+    assert_eq!(pool.balance(&alice.pubkey()).await, balance + owed);
+    assert_eq!(pool.stake_user(&alice.pubkey()).await.total_claimed, owed);
+    assert_eq!(pool.epoch_state().await.total_owed, 0);
+
+    pool.close_pool().await.unwrap();
+    assert_eq!(pool.balance(&pda::epoch_state().0).await, 0);
+}
+
+#[tokio::test]
+async fn days_up_to_the_sunset_are_still_paid() {
+    let mut pool = Pool::start(100 * LAMPORTS_PER_SOL).await;
+    pool.initialize(7 * LAMPORTS_PER_SOL).await.unwrap();
+    let alice = pool.new_user(LAMPORTS_PER_SOL).await;
+    let bob = pool.new_user(LAMPORTS_PER_SOL).await;
+    let nfts = pool.mint_nfts(&[&alice, &bob]).await;
+    pool.stake(&alice, &nfts[0]).await.unwrap();
+    pool.stake(&bob, &nfts[1]).await.unwrap();
+    next_day(&mut pool, &[&nfts[0], &nfts[1]]).await;
+
+    // the crank ticks the second day but credits nobody, and misses the third
+    pool.warp_days(1).await;
+    pool.tick_day().await.unwrap();
+    pool.warp_days(1).await;
+    pool.sunset().await.unwrap();
+    assert_eq!(pool.stake_info().await.days_passed, 3);
+    assert_eq!(pool.tick_day().await.unwrap_err(), program_error(StakeError::PoolSunset));
+
+    // bob leaves first, his share of the days stays the same
+    pool.unstake(&bob, &nfts[1]).await.unwrap();
+    assert_eq!(pool.stake_info().await.total_weight, 2);
+    pool.daily_reward(&[&nfts[0]]).await.unwrap();
+    assert_eq!(pool.daily_reward(&[&nfts[0]]).await.unwrap_err(), program_error(StakeError::NoDaily));
+    assert_eq!(pool.epoch_state().await.total_owed, 3 * LAMPORTS_PER_SOL);
+
+    for user in [&alice, &bob] {
+        let balance = pool.balance(&user.pubkey()).await;
+        pool.claim(user).await.unwrap();
+        assert_eq!(pool.balance(&user.pubkey()).await, balance + 3 * LAMPORTS_PER_SOL / 2);
+    }
+    assert_eq!(pool.epoch_state().await.total_owed, 0);
+}
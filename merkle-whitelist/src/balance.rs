@@ -0,0 +1,253 @@
+//! Balance trees of the merkle distribution mode, v2 trees whose leaves are
+//! `keccak(LEAF_PREFIX_V2 || BALANCE_LEAF_PREFIX || index || account || amount)`,
+//! the index and amount little endian, as hashed by
+//! `process_claim_epoch_distribution`. The index is the position of the
+//! balance in the list and its bit in the claim bitmap of the epoch.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::keccak;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{parse_mint, MerkleTree, WhitelistError, LEAF_PREFIX_V2};
+
+pub const BALANCE_LEAF_PREFIX: &[u8] = b"nft-staking-balance-tree";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Balance {
+    pub account: Pubkey,
+    pub amount: u64,
+}
+
+pub fn balance_leaf(index: u64, account: &Pubkey, amount: u64) -> [u8; 32] {
+    keccak::hashv(&[
+        LEAF_PREFIX_V2,
+        BALANCE_LEAF_PREFIX,
+        &index.to_le_bytes(),
+        &account.to_bytes(),
+        &amount.to_le_bytes(),
+    ])
+    .0
+}
+
+pub struct BalanceTree {
+    tree: MerkleTree,
+    balances: Vec<Balance>,
+}
+
+/// What a user needs to claim.
+#[derive(Serialize)]
+pub struct Claim {
+    pub index: u64,
+    pub amount: u64,
+    pub proof: Vec<String>,
+}
+
+/// What is written to the claim file: the arguments of
+/// `process_post_epoch_distribution` and the claim of every account.
+#[derive(Serialize)]
+pub struct ClaimFile {
+    pub root: String,
+    pub total_amount: u64,
+    pub num_nodes: u32,
+    // longest proof, the program derives it from num_nodes
+    pub max_depth: u8,
+    pub claims: BTreeMap<String, Claim>,
+}
+
+impl BalanceTree {
+    /// One leaf per account, an account listed twice is an error.
+    pub fn new(balances: Vec<Balance>) -> Result<Self, WhitelistError> {
+        let mut accounts = BTreeSet::new();
+        for balance in &balances {
+            if !accounts.insert(balance.account) {
+                return Err(WhitelistError::DuplicateAccount(balance.account.to_string()));
+            }
+        }
+        let tree = MerkleTree::new_v2(
+            balances
+                .iter()
+                .enumerate()
+                .map(|(index, balance)| balance_leaf(index as u64, &balance.account, balance.amount))
+                .collect(),
+        )?;
+        Ok(BalanceTree { tree, balances })
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.tree.root()
+    }
+
+    pub fn total_amount(&self) -> u64 {
+        self.balances.iter().map(|balance| balance.amount).sum()
+    }
+
+    pub fn num_nodes(&self) -> u32 {
+        self.balances.len() as u32
+    }
+
+    /// Length of the longest proof.
+    pub fn max_depth(&self) -> u8 {
+        self.tree.depth()
+    }
+
+    pub fn proof(&self, index: usize) -> Option<Vec<[u8; 32]>> {
+        let balance = self.balances.get(index)?;
+        self.tree
+            .proof(&balance_leaf(index as u64, &balance.account, balance.amount))
+    }
+
+    pub fn claim_file(&self) -> ClaimFile {
+        let claims = self
+            .balances
+            .iter()
+            .enumerate()
+            .map(|(index, balance)| {
+                let proof = self.proof(index).unwrap();
+                let claim = Claim {
+                    index: index as u64,
+                    amount: balance.amount,
+                    proof: proof.iter().map(hex::encode).collect(),
+                };
+                (balance.account.to_string(), claim)
+            })
+            .collect();
+        ClaimFile {
+            root: hex::encode(self.root()),
+            total_amount: self.total_amount(),
+            num_nodes: self.num_nodes(),
+            max_depth: self.max_depth(),
+            claims,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct BalanceEntry {
+    account: String,
+    amount: u64,
+}
+
+/// Reads a json array of `{ "account": <base58>, "amount": <lamports> }`.
+pub fn parse_balances_json(input: &str) -> Result<Vec<Balance>, WhitelistError> {
+    let entries: Vec<BalanceEntry> = serde_json::from_str(input)?;
+    entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            Ok(Balance {
+                account: parse_mint(&entry.account, i + 1)?,
+                amount: entry.amount,
+            })
+        })
+        .collect()
+}
+
+/// Reads `account,amount` lines, with or without a header line.
+pub fn parse_balances_csv(input: &str) -> Result<Vec<Balance>, WhitelistError> {
+    let mut balances = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        let mut fields = line.split(',').map(|field| field.trim().trim_matches('"'));
+        let account = fields.next().unwrap_or("");
+        if account.is_empty() {
+            continue;
+        }
+        let amount = fields.next().unwrap_or("");
+        match (parse_mint(account, i + 1), amount.parse::<u64>()) {
+            (Ok(account), Ok(amount)) => balances.push(Balance { account, amount }),
+            // header
+            _ if i == 0 => {}
+            (Err(err), _) => return Err(err),
+            (_, Err(_)) => return Err(WhitelistError::InvalidAmount(amount.to_string(), i + 1)),
+        }
+    }
+    Ok(balances)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use token_stake_model::merkle_proof::{self, verify_v2};
+
+    fn balances(n: usize) -> Vec<Balance> {
+        (0..n)
+            .map(|i| Balance {
+                account: Pubkey::new_unique(),
+                amount: 1000 * (i as u64 + 1),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn leaf_matches_the_program() {
+        assert_eq!(BALANCE_LEAF_PREFIX, token_stake_model::BALANCE_LEAF_PREFIX);
+        let account = Pubkey::new_unique();
+        let leaf = merkle_proof::leaf_hash_v2(&[
+            BALANCE_LEAF_PREFIX,
+            &3u64.to_le_bytes(),
+            &account.to_bytes(),
+            &1000u64.to_le_bytes(),
+        ]);
+        assert_eq!(balance_leaf(3, &account, 1000), leaf);
+    }
+
+    #[test]
+    fn every_claim_verifies_on_chain() {
+        for n in 1..=20 {
+            let balances = balances(n);
+            let tree = BalanceTree::new(balances.clone()).unwrap();
+            assert_eq!(tree.num_nodes(), n as u32);
+            // the depth the program stores with the root
+            assert_eq!(tree.max_depth() as u32, merkle_proof::tree_depth(n as u32));
+            for (index, balance) in balances.iter().enumerate() {
+                let leaf = balance_leaf(index as u64, &balance.account, balance.amount);
+                assert!(verify_v2(&tree.proof(index).unwrap(), tree.root(), leaf, tree.max_depth()), "{} balances", n);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_another_amount_or_index() {
+        let balances = balances(5);
+        let tree = BalanceTree::new(balances.clone()).unwrap();
+        let proof = tree.proof(2).unwrap();
+        let Balance { account, amount } = balances[2];
+        let max_depth = tree.max_depth();
+        assert!(!verify_v2(&proof, tree.root(), balance_leaf(2, &account, amount + 1), max_depth));
+        assert!(!verify_v2(&proof, tree.root(), balance_leaf(3, &account, amount), max_depth));
+    }
+
+    #[test]
+    fn claim_file_totals() {
+        let tree = BalanceTree::new(balances(3)).unwrap();
+        let claim_file = tree.claim_file();
+        assert_eq!(claim_file.total_amount, 6000);
+        assert_eq!(claim_file.num_nodes, 3);
+        assert_eq!(claim_file.claims.len(), 3);
+    }
+
+    #[test]
+    fn duplicate_account_is_an_error() {
+        let mut balances = balances(2);
+        balances.push(balances[0].clone());
+        assert!(matches!(BalanceTree::new(balances), Err(WhitelistError::DuplicateAccount(_))));
+    }
+
+    #[test]
+    fn parses_json_and_csv() {
+        let balances = balances(2);
+        let json = format!(
+            r#"[{{"account":"{}","amount":1000}},{{"account":"{}","amount":2000}}]"#,
+            balances[0].account, balances[1].account
+        );
+        assert_eq!(parse_balances_json(&json).unwrap(), balances);
+
+        let csv = format!("account,amount\n{},1000\n\n{},2000\n", balances[0].account, balances[1].account);
+        assert_eq!(parse_balances_csv(&csv).unwrap(), balances);
+
+        assert!(matches!(
+            parse_balances_csv(&format!("{},1000\n{},lots\n", balances[0].account, balances[1].account)),
+            Err(WhitelistError::InvalidAmount(_, 2))
+        ));
+    }
+}
@@ -0,0 +1,657 @@
+//! Off-chain side of the nft whitelist. Same tree as `tests/merkle-tree.ts`:
+//! the leaves are sorted and deduplicated, pairs are hashed sorted, and the
+//! last node of an odd layer is moved up unhashed. The proofs check with
+//! `token_stake_model::merkle_proof::verify`.
+//!
+//! v2 trees prefix the leaf hashes with `LEAF_PREFIX_V2` and the node hashes
+//! with `NODE_PREFIX_V2`, and check with `merkle_proof::verify_v2`.
+//!
+//! Weighted trees are v2 trees whose leaves also carry the reward weight of
+//! the mint, posted with `update_merkle_weighted`.
+//!
+//! A multiproof proves several leaves at once, checked with
+//! `merkle_proof::verify_multi` or `verify_multi_v2`.
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::solana_program::keccak;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use thiserror::Error;
+
+pub mod balance;
+
+/// Prefix of every leaf, as hashed by `process_stake_nft`.
+pub const LEAF_PREFIX: &[u8] = b"nft-staking-merkle-tree";
+pub const LEAF_PREFIX_V2: &[u8] = &[0];
+pub const NODE_PREFIX_V2: &[u8] = &[1];
+pub const WEIGHTED_LEAF_PREFIX: &[u8] = b"nft-staking-weighted-tree";
+
+#[derive(Debug, Error)]
+pub enum WhitelistError {
+    #[error("the mint list is empty")]
+    Empty,
+    #[error("invalid mint {0:?} on line {1}")]
+    InvalidMint(String, usize),
+    #[error("invalid amount {0:?} on line {1}")]
+    InvalidAmount(String, usize),
+    #[error("invalid weight {0:?} on line {1}")]
+    InvalidWeight(String, usize),
+    #[error("{0} has a zero weight")]
+    ZeroWeight(String),
+    #[error("{0} is listed twice")]
+    DuplicateAccount(String),
+    #[error("invalid json: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// `keccak(LEAF_PREFIX || mint)`
+pub fn leaf(mint: &Pubkey) -> [u8; 32] {
+    keccak::hashv(&[LEAF_PREFIX, &mint.to_bytes()]).0
+}
+
+/// `keccak(LEAF_PREFIX_V2 || LEAF_PREFIX || mint)`
+pub fn leaf_v2(mint: &Pubkey) -> [u8; 32] {
+    keccak::hashv(&[LEAF_PREFIX_V2, LEAF_PREFIX, &mint.to_bytes()]).0
+}
+
+/// `keccak(LEAF_PREFIX_V2 || WEIGHTED_LEAF_PREFIX || mint || weight)`, the
+/// weight little endian.
+pub fn weighted_leaf(mint: &Pubkey, weight: u16) -> [u8; 32] {
+    keccak::hashv(&[LEAF_PREFIX_V2, WEIGHTED_LEAF_PREFIX, &mint.to_bytes(), &weight.to_le_bytes()]).0
+}
+
+fn combined_hash(first: &[u8; 32], second: &[u8; 32]) -> [u8; 32] {
+    if first <= second {
+        keccak::hashv(&[first, second]).0
+    } else {
+        keccak::hashv(&[second, first]).0
+    }
+}
+
+fn combined_hash_v2(first: &[u8; 32], second: &[u8; 32]) -> [u8; 32] {
+    if first <= second {
+        keccak::hashv(&[NODE_PREFIX_V2, first, second]).0
+    } else {
+        keccak::hashv(&[NODE_PREFIX_V2, second, first]).0
+    }
+}
+
+/// One proof for several leaves, the siblings shared by their branches
+/// included once.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MultiProof {
+    pub num_leaves: u32,
+    /// Index of every proved leaf, in the order they were asked for.
+    pub indices: Vec<u32>,
+    pub proof: Vec<[u8; 32]>,
+}
+
+pub struct MerkleTree {
+    // layers[0] are the sorted leaves, the last layer is the root
+    layers: Vec<Vec<[u8; 32]>>,
+}
+
+impl MerkleTree {
+    pub fn new(leaves: Vec<[u8; 32]>) -> Result<Self, WhitelistError> {
+        Self::build(leaves, combined_hash)
+    }
+
+    pub fn new_v2(leaves: Vec<[u8; 32]>) -> Result<Self, WhitelistError> {
+        Self::build(leaves, combined_hash_v2)
+    }
+
+    fn build(
+        mut leaves: Vec<[u8; 32]>,
+        combine: fn(&[u8; 32], &[u8; 32]) -> [u8; 32],
+    ) -> Result<Self, WhitelistError> {
+        leaves.sort_unstable();
+        leaves.dedup();
+        if leaves.is_empty() {
+            return Err(WhitelistError::Empty);
+        }
+
+        let mut layers = vec![leaves];
+        while layers[layers.len() - 1].len() > 1 {
+            let next = layers[layers.len() - 1]
+                .chunks(2)
+                .map(|pair| match pair {
+                    [first, second] => combine(first, second),
+                    [single] => *single,
+                    _ => unreachable!(),
+                })
+                .collect();
+            layers.push(next);
+        }
+        Ok(MerkleTree { layers })
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.layers[self.layers.len() - 1][0]
+    }
+
+    /// Length of the longest proof, the `max_depth` of a v2 root.
+    pub fn depth(&self) -> u8 {
+        (self.layers.len() - 1) as u8
+    }
+
+    /// Sibling hashes from `leaf` up to the root, `None` if it is not a leaf.
+    pub fn proof(&self, leaf: &[u8; 32]) -> Option<Vec<[u8; 32]>> {
+        let mut idx = self.layers[0].binary_search(leaf).ok()?;
+        let mut proof = Vec::new();
+        for layer in &self.layers {
+            let pair_idx = idx ^ 1;
+            if pair_idx < layer.len() {
+                proof.push(layer[pair_idx]);
+            }
+            idx /= 2;
+        }
+        Some(proof)
+    }
+
+    /// The multiproof of `leaves`, `None` if one is not a leaf or is listed
+    /// twice.
+    pub fn multiproof(&self, leaves: &[[u8; 32]]) -> Option<MultiProof> {
+        let indices = leaves
+            .iter()
+            .map(|leaf| self.layers[0].binary_search(leaf).ok())
+            .collect::<Option<Vec<_>>>()?;
+        let mut known = indices.clone();
+        known.sort_unstable();
+        known.dedup();
+        if known.len() != indices.len() || known.is_empty() {
+            return None;
+        }
+
+        // same walk as merkle_proof::verify_multi, taking the siblings that
+        // are not known from the tree
+        let mut proof = Vec::new();
+        for layer in &self.layers[..self.layers.len() - 1] {
+            let mut next = Vec::with_capacity(known.len());
+            let mut i = 0;
+            while i < known.len() {
+                let sibling = known[i] ^ 1;
+                if sibling < layer.len() {
+                    if known.get(i + 1) == Some(&sibling) {
+                        i += 1;
+                    } else {
+                        proof.push(layer[sibling]);
+                    }
+                }
+                next.push(known[i] / 2);
+                i += 1;
+            }
+            known = next;
+        }
+        Some(MultiProof {
+            num_leaves: self.layers[0].len() as u32,
+            indices: indices.into_iter().map(|index| index as u32).collect(),
+            proof,
+        })
+    }
+}
+
+/// The tree over a mint list, with the proof of each mint.
+pub struct Whitelist {
+    tree: MerkleTree,
+    mints: Vec<Pubkey>,
+    is_v2: bool,
+    // the weight of every mint of a weighted tree
+    weights: Option<BTreeMap<Pubkey, u16>>,
+}
+
+/// What is written to the proof file: the root and the proof of every mint,
+/// hex encoded.
+#[derive(Serialize)]
+pub struct ProofFile {
+    pub root: String,
+    pub is_hash_v2: bool,
+    pub max_depth: u8,
+    pub is_weighted: bool,
+    pub proofs: BTreeMap<String, Vec<String>>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub weights: BTreeMap<String, u16>,
+}
+
+impl Whitelist {
+    pub fn new(mints: Vec<Pubkey>) -> Result<Self, WhitelistError> {
+        let tree = MerkleTree::new(mints.iter().map(leaf).collect())?;
+        Ok(Whitelist { tree, mints, is_v2: false, weights: None })
+    }
+
+    pub fn new_v2(mints: Vec<Pubkey>) -> Result<Self, WhitelistError> {
+        let tree = MerkleTree::new_v2(mints.iter().map(leaf_v2).collect())?;
+        Ok(Whitelist { tree, mints, is_v2: true, weights: None })
+    }
+
+    /// A v2 tree over `(mint, weight)` pairs, every mint listed once.
+    pub fn new_weighted(entries: Vec<(Pubkey, u16)>) -> Result<Self, WhitelistError> {
+        let mut weights = BTreeMap::new();
+        for (mint, weight) in &entries {
+            if *weight == 0 {
+                return Err(WhitelistError::ZeroWeight(mint.to_string()));
+            }
+            if weights.insert(*mint, *weight).is_some() {
+                return Err(WhitelistError::DuplicateAccount(mint.to_string()));
+            }
+        }
+        let tree = MerkleTree::new_v2(entries.iter().map(|(mint, weight)| weighted_leaf(mint, *weight)).collect())?;
+        let mints = entries.into_iter().map(|(mint, _)| mint).collect();
+        Ok(Whitelist { tree, mints, is_v2: true, weights: Some(weights) })
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.tree.root()
+    }
+
+    pub fn max_depth(&self) -> u8 {
+        self.tree.depth()
+    }
+
+    pub fn is_weighted(&self) -> bool {
+        self.weights.is_some()
+    }
+
+    /// The weight `mint` is staked with, 1 outside of a weighted tree.
+    pub fn weight(&self, mint: &Pubkey) -> Option<u16> {
+        match &self.weights {
+            Some(weights) => weights.get(mint).copied(),
+            None => Some(1),
+        }
+    }
+
+    fn leaf(&self, mint: &Pubkey) -> Option<[u8; 32]> {
+        match &self.weights {
+            Some(weights) => weights.get(mint).map(|weight| weighted_leaf(mint, *weight)),
+            None if self.is_v2 => Some(leaf_v2(mint)),
+            None => Some(leaf(mint)),
+        }
+    }
+
+    pub fn proof(&self, mint: &Pubkey) -> Option<Vec<[u8; 32]>> {
+        self.tree.proof(&self.leaf(mint)?)
+    }
+
+    pub fn multiproof(&self, mints: &[Pubkey]) -> Option<MultiProof> {
+        let leaves = mints.iter().map(|mint| self.leaf(mint)).collect::<Option<Vec<_>>>()?;
+        self.tree.multiproof(&leaves)
+    }
+
+    pub fn proof_file(&self) -> ProofFile {
+        let proofs = self
+            .mints
+            .iter()
+            .map(|mint| {
+                let proof = self.proof(mint).unwrap();
+                (mint.to_string(), proof.iter().map(hex::encode).collect())
+            })
+            .collect();
+        ProofFile {
+            root: hex::encode(self.root()),
+            is_hash_v2: self.is_v2,
+            max_depth: self.max_depth(),
+            is_weighted: self.is_weighted(),
+            proofs,
+            weights: self
+                .weights
+                .iter()
+                .flatten()
+                .map(|(mint, weight)| (mint.to_string(), *weight))
+                .collect(),
+        }
+    }
+}
+
+/// Reads a json array of base58 mints.
+pub fn parse_json(input: &str) -> Result<Vec<Pubkey>, WhitelistError> {
+    let mints: Vec<String> = serde_json::from_str(input)?;
+    mints
+        .iter()
+        .enumerate()
+        .map(|(i, mint)| parse_mint(mint, i + 1))
+        .collect()
+}
+
+/// Reads the first column of a csv file, with or without a header line.
+pub fn parse_csv(input: &str) -> Result<Vec<Pubkey>, WhitelistError> {
+    let mut mints = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        let field = line.split(',').next().unwrap_or("").trim().trim_matches('"');
+        if field.is_empty() {
+            continue;
+        }
+        match parse_mint(field, i + 1) {
+            Ok(mint) => mints.push(mint),
+            // header
+            Err(_) if i == 0 => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(mints)
+}
+
+#[derive(Deserialize)]
+struct WeightEntry {
+    mint: String,
+    weight: u16,
+}
+
+/// Reads a json array of `{"mint": ..., "weight": ...}` objects.
+pub fn parse_weights_json(input: &str) -> Result<Vec<(Pubkey, u16)>, WhitelistError> {
+    let entries: Vec<WeightEntry> = serde_json::from_str(input)?;
+    entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| Ok((parse_mint(&entry.mint, i + 1)?, entry.weight)))
+        .collect()
+}
+
+/// Reads `mint,weight` lines, with or without a header line.
+pub fn parse_weights_csv(input: &str) -> Result<Vec<(Pubkey, u16)>, WhitelistError> {
+    let mut entries = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        let mut fields = line.split(',').map(|field| field.trim().trim_matches('"'));
+        let mint = fields.next().unwrap_or("");
+        if mint.is_empty() {
+            continue;
+        }
+        let weight = fields.next().unwrap_or("");
+        match (parse_mint(mint, i + 1), weight.parse::<u16>()) {
+            (Ok(mint), Ok(weight)) => entries.push((mint, weight)),
+            // header
+            _ if i == 0 => {}
+            (Err(err), _) => return Err(err),
+            (_, Err(_)) => return Err(WhitelistError::InvalidWeight(weight.to_string(), i + 1)),
+        }
+    }
+    Ok(entries)
+}
+
+fn parse_mint(mint: &str, line: usize) -> Result<Pubkey, WhitelistError> {
+    Pubkey::from_str(mint.trim()).map_err(|_| WhitelistError::InvalidMint(mint.to_string(), line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use token_stake_model::merkle_proof::{self, verify, verify_v2};
+
+    fn mints(n: usize) -> Vec<Pubkey> {
+        (0..n).map(|_| Pubkey::new_unique()).collect()
+    }
+
+    #[test]
+    fn every_proof_verifies_on_chain() {
+        for n in 1..=33 {
+            let mints = mints(n);
+            let whitelist = Whitelist::new(mints.clone()).unwrap();
+            for mint in &mints {
+                let proof = whitelist.proof(mint).unwrap();
+                assert!(verify(proof, whitelist.root(), leaf(mint)), "{} mints", n);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_unlisted_mint_and_wrong_root() {
+        let listed = mints(10);
+        let whitelist = Whitelist::new(listed.clone()).unwrap();
+        let other = Pubkey::new_unique();
+        assert!(whitelist.proof(&other).is_none());
+
+        let proof = whitelist.proof(&listed[0]).unwrap();
+        assert!(!verify(proof.clone(), whitelist.root(), leaf(&other)));
+        assert!(!verify(proof, [0u8; 32], leaf(&listed[0])));
+    }
+
+    #[test]
+    fn single_mint_root_is_its_leaf() {
+        let mint = Pubkey::new_unique();
+        let whitelist = Whitelist::new(vec![mint]).unwrap();
+        assert_eq!(whitelist.root(), leaf(&mint));
+        assert!(whitelist.proof(&mint).unwrap().is_empty());
+    }
+
+    #[test]
+    fn root_ignores_order_and_duplicates() {
+        let mints = mints(7);
+        let mut shuffled: Vec<Pubkey> = mints.iter().rev().cloned().collect();
+        shuffled.push(mints[3]);
+        assert_eq!(
+            Whitelist::new(mints).unwrap().root(),
+            Whitelist::new(shuffled).unwrap().root()
+        );
+    }
+
+    #[test]
+    fn every_v2_proof_verifies_on_chain() {
+        for n in 1..=33 {
+            let mints = mints(n);
+            let whitelist = Whitelist::new_v2(mints.clone()).unwrap();
+            for mint in &mints {
+                let proof = whitelist.proof(mint).unwrap();
+                assert!(proof.len() <= whitelist.max_depth() as usize);
+                assert!(verify_v2(&proof, whitelist.root(), leaf_v2(mint), whitelist.max_depth()), "{} mints", n);
+            }
+        }
+    }
+
+    #[test]
+    fn v2_hashing_matches_the_program() {
+        assert_eq!(LEAF_PREFIX, token_stake_model::MINT_LEAF_PREFIX);
+        assert_eq!(LEAF_PREFIX_V2, merkle_proof::LEAF_PREFIX);
+        assert_eq!(NODE_PREFIX_V2, merkle_proof::NODE_PREFIX);
+        let mint = Pubkey::new_unique();
+        assert_eq!(leaf_v2(&mint), merkle_proof::leaf_hash_v2(&[LEAF_PREFIX, &mint.to_bytes()]));
+    }
+
+    #[test]
+    fn v2_separates_nodes_from_leaves_and_bounds_proofs() {
+        let mints = mints(8);
+        let whitelist = Whitelist::new_v2(mints.clone()).unwrap();
+        let proof = whitelist.proof(&mints[0]).unwrap();
+        assert_eq!(whitelist.max_depth(), 3);
+
+        // the 64 bytes of a pair hash to a node and to a leaf differently
+        let (first, second) = (leaf_v2(&mints[0]).min(proof[0]), leaf_v2(&mints[0]).max(proof[0]));
+        assert_ne!(
+            combined_hash_v2(&first, &second),
+            merkle_proof::leaf_hash_v2(&[&first, &second])
+        );
+        // the v1 verifier does not know the v2 hashing
+        assert!(!verify(proof.clone(), whitelist.root(), leaf(&mints[0])));
+
+        assert!(verify_v2(&proof, whitelist.root(), leaf_v2(&mints[0]), 3));
+        assert!(!verify_v2(&proof, whitelist.root(), leaf_v2(&mints[0]), 2));
+        let mut long_proof = proof.clone();
+        long_proof.resize(merkle_proof::MAX_DEPTH as usize + 1, [0; 32]);
+        assert!(!verify_v2(&long_proof, whitelist.root(), leaf_v2(&mints[0]), u8::MAX));
+    }
+
+    fn merkle(whitelist: &Whitelist) -> token_stake_model::Merkle {
+        token_stake_model::Merkle {
+            root: whitelist.root(),
+            is_hash_v2: whitelist.is_v2,
+            max_depth: whitelist.max_depth(),
+            is_weighted: whitelist.is_weighted(),
+            ..Default::default()
+        }
+    }
+
+    fn with_indices(whitelist: &Whitelist, mints: &[Pubkey], multiproof: &MultiProof) -> Vec<(u32, Pubkey, u16)> {
+        multiproof
+            .indices
+            .iter()
+            .zip(mints)
+            .map(|(index, mint)| (*index, *mint, whitelist.weight(mint).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn every_multiproof_verifies_on_chain() {
+        for n in 1..=9 {
+            let mints = mints(n);
+            for whitelist in [Whitelist::new(mints.clone()).unwrap(), Whitelist::new_v2(mints.clone()).unwrap()] {
+                let merkle = merkle(&whitelist);
+                for subset in 1..(1u32 << n) {
+                    let picked: Vec<Pubkey> = (0..n).filter(|i| subset & (1 << i) != 0).map(|i| mints[i]).collect();
+                    let multiproof = whitelist.multiproof(&picked).unwrap();
+                    assert_eq!(multiproof.num_leaves, n as u32);
+                    assert!(
+                        merkle.verify_mints(&multiproof.proof, &with_indices(&whitelist, &picked, &multiproof), multiproof.num_leaves),
+                        "{} mints, subset {:b}, v2 {}",
+                        n,
+                        subset,
+                        whitelist.is_v2
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn multiproof_shares_the_siblings() {
+        let mints = mints(64);
+        let whitelist = Whitelist::new_v2(mints.clone()).unwrap();
+        let picked = &mints[..20];
+        let multiproof = whitelist.multiproof(picked).unwrap();
+        let single: usize = picked.iter().map(|mint| whitelist.proof(mint).unwrap().len()).sum();
+        assert_eq!(single, 20 * 6);
+        assert!(multiproof.proof.len() < 64 - 20, "{} siblings", multiproof.proof.len());
+
+        let all = whitelist.multiproof(&mints).unwrap();
+        assert!(all.proof.is_empty());
+        assert!(merkle(&whitelist).verify_mints(&[], &with_indices(&whitelist, &mints, &all), 64));
+    }
+
+    #[test]
+    fn rejects_bad_multiproofs() {
+        let mints = mints(10);
+        let whitelist = Whitelist::new(mints.clone()).unwrap();
+        let merkle = merkle(&whitelist);
+        let picked = [mints[1], mints[4], mints[7]];
+        let multiproof = whitelist.multiproof(&picked).unwrap();
+        let leaves = with_indices(&whitelist, &picked, &multiproof);
+        assert!(merkle.verify_mints(&multiproof.proof, &leaves, 10));
+
+        // an unlisted mint, at the index of a listed one
+        let mut other = leaves.clone();
+        other[0].1 = Pubkey::new_unique();
+        assert!(!merkle.verify_mints(&multiproof.proof, &other, 10));
+        // a leaf listed twice
+        let mut twice = leaves.clone();
+        twice.push(leaves[0]);
+        assert!(!merkle.verify_mints(&multiproof.proof, &twice, 10));
+        // siblings left over or missing
+        let mut longer = multiproof.proof.clone();
+        longer.push([0; 32]);
+        assert!(!merkle.verify_mints(&longer, &leaves, 10));
+        assert!(!merkle.verify_mints(&multiproof.proof[1..], &leaves, 10));
+        // an index past the tree
+        assert!(!merkle.verify_mints(&multiproof.proof, &leaves, 2));
+        assert!(!merkle.verify_mints(&multiproof.proof, &[], 10));
+
+        assert!(whitelist.multiproof(&[mints[0], Pubkey::new_unique()]).is_none());
+        assert!(whitelist.multiproof(&[mints[0], mints[0]]).is_none());
+        assert!(whitelist.multiproof(&[]).is_none());
+    }
+
+    #[test]
+    fn v2_multiproof_is_bounded_by_the_root_depth() {
+        let mints = mints(9);
+        let whitelist = Whitelist::new_v2(mints.clone()).unwrap();
+        assert_eq!(whitelist.max_depth(), 4);
+        let multiproof = whitelist.multiproof(&mints[..3]).unwrap();
+        let leaves = with_indices(&whitelist, &mints[..3], &multiproof);
+        let mut merkle = merkle(&whitelist);
+        assert!(merkle.verify_mints(&multiproof.proof, &leaves, 9));
+        merkle.max_depth = 3;
+        assert!(!merkle.verify_mints(&multiproof.proof, &leaves, 9));
+        // v1 hashing of the same mints
+        merkle.is_hash_v2 = false;
+        assert!(!merkle.verify_mints(&multiproof.proof, &leaves, 9));
+    }
+
+    #[test]
+    fn weighted_proofs_verify_on_chain_with_their_weight() {
+        assert_eq!(WEIGHTED_LEAF_PREFIX, token_stake_model::WEIGHTED_LEAF_PREFIX);
+        let entries: Vec<(Pubkey, u16)> = mints(9).into_iter().zip([1, 1, 5, 1, 20, 1, 1, 3, u16::MAX]).collect();
+        let whitelist = Whitelist::new_weighted(entries.clone()).unwrap();
+        let weighted = merkle(&whitelist);
+        for (mint, weight) in &entries {
+            let proof = whitelist.proof(mint).unwrap();
+            assert!(weighted.verify_mint(proof.clone(), mint, *weight));
+            assert!(!weighted.verify_mint(proof, mint, weight.wrapping_add(1)));
+        }
+        let picked: Vec<Pubkey> = entries[2..6].iter().map(|(mint, _)| *mint).collect();
+        let multiproof = whitelist.multiproof(&picked).unwrap();
+        let mut leaves = with_indices(&whitelist, &picked, &multiproof);
+        assert!(weighted.verify_mints(&multiproof.proof, &leaves, 9));
+        leaves[0].2 = 1;
+        assert!(!weighted.verify_mints(&multiproof.proof, &leaves, 9));
+
+        // the same mints staked against an unweighted root
+        let unweighted = Whitelist::new_v2(entries.iter().map(|(mint, _)| *mint).collect()).unwrap();
+        let (mint, weight) = entries[4];
+        assert!(!weighted.verify_mint(unweighted.proof(&mint).unwrap(), &mint, weight));
+        assert!(!weighted.verify_mint(unweighted.proof(&mint).unwrap(), &mint, 1));
+        assert!(merkle(&unweighted).verify_mint(unweighted.proof(&mint).unwrap(), &mint, 1));
+        assert!(!merkle(&unweighted).verify_mint(unweighted.proof(&mint).unwrap(), &mint, weight));
+    }
+
+    #[test]
+    fn weighted_list_rejects_zero_and_repeated_mints() {
+        let mints = mints(2);
+        assert!(matches!(
+            Whitelist::new_weighted(vec![(mints[0], 1), (mints[1], 0)]),
+            Err(WhitelistError::ZeroWeight(_))
+        ));
+        assert!(matches!(
+            Whitelist::new_weighted(vec![(mints[0], 1), (mints[0], 2)]),
+            Err(WhitelistError::DuplicateAccount(_))
+        ));
+        let whitelist = Whitelist::new_weighted(vec![(mints[0], 4)]).unwrap();
+        assert_eq!(whitelist.weight(&mints[0]), Some(4));
+        assert_eq!(whitelist.weight(&mints[1]), None);
+        assert!(whitelist.proof(&mints[1]).is_none());
+        assert_eq!(whitelist.proof_file().weights[&mints[0].to_string()], 4);
+    }
+
+    #[test]
+    fn empty_list_is_an_error() {
+        assert!(matches!(Whitelist::new(vec![]), Err(WhitelistError::Empty)));
+    }
+
+    #[test]
+    fn parses_json_and_csv() {
+        let mints = mints(3);
+        let json = serde_json::to_string(&mints.iter().map(|m| m.to_string()).collect::<Vec<_>>()).unwrap();
+        assert_eq!(parse_json(&json).unwrap(), mints);
+
+        let csv = format!("mint,name\n{},a\n\n\"{}\",b\n{}\n", mints[0], mints[1], mints[2]);
+        assert_eq!(parse_csv(&csv).unwrap(), mints);
+
+        assert!(matches!(
+            parse_csv(&format!("{}\nnot-a-mint\n", mints[0])),
+            Err(WhitelistError::InvalidMint(_, 2))
+        ));
+    }
+
+    #[test]
+    fn parses_weights_json_and_csv() {
+        let entries = vec![(Pubkey::new_unique(), 1), (Pubkey::new_unique(), 25)];
+        let json = format!(
+            r#"[{{"mint":"{}","weight":1}},{{"mint":"{}","weight":25}}]"#,
+            entries[0].0, entries[1].0
+        );
+        assert_eq!(parse_weights_json(&json).unwrap(), entries);
+
+        let csv = format!("mint,weight\n{},1\n\n{},25\n", entries[0].0, entries[1].0);
+        assert_eq!(parse_weights_csv(&csv).unwrap(), entries);
+
+        assert!(matches!(
+            parse_weights_csv(&format!("{},1\n{},70000\n", entries[0].0, entries[1].0)),
+            Err(WhitelistError::InvalidWeight(_, 2))
+        ));
+    }
+}
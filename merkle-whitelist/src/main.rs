@@ -0,0 +1,74 @@
+//! `merkle-whitelist mints.json --out proofs.json` prints the root of the
+//! whitelist and writes the proof of every mint. `--v2` builds a v2 tree,
+//! `--weighted` a v2 tree over a `mint,weight` list.
+//!
+//! `merkle-whitelist --balances balances.json --out claims.json` builds the
+//! balance tree of an epoch distribution instead, and writes the claim of
+//! every account.
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use merkle_whitelist::balance::{parse_balances_csv, parse_balances_json, BalanceTree};
+use merkle_whitelist::{parse_csv, parse_json, parse_weights_csv, parse_weights_json, Whitelist};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Parser)]
+#[clap(name = "merkle-whitelist", version, about = "Builds the nft whitelist Merkle tree")]
+struct Opts {
+    /// Mint list, a json array of mints or a csv file with the mint in the first column
+    input: PathBuf,
+    /// The input lists `account,amount` balances of an epoch distribution
+    #[clap(long)]
+    balances: bool,
+    /// Build a v2 whitelist, with separated leaf and node hashes
+    #[clap(long, conflicts_with = "balances")]
+    v2: bool,
+    /// The input lists `mint,weight` pairs, build a weighted v2 whitelist
+    #[clap(long, conflicts_with_all = &["balances", "v2"])]
+    weighted: bool,
+    /// Proof file to write
+    #[clap(short, long, default_value = "proofs.json")]
+    out: PathBuf,
+}
+
+fn write_json(path: &Path, value: &impl Serialize) -> Result<()> {
+    fs::write(path, serde_json::to_string_pretty(value)?)
+        .with_context(|| format!("cannot write {}", path.display()))
+}
+
+fn main() -> Result<()> {
+    let opts = Opts::parse();
+    let input = fs::read_to_string(&opts.input)
+        .with_context(|| format!("cannot read {}", opts.input.display()))?;
+    let is_json = opts.input.extension() == Some("json".as_ref());
+
+    if opts.balances {
+        let balances = if is_json { parse_balances_json(&input)? } else { parse_balances_csv(&input)? };
+        let claim_file = BalanceTree::new(balances)?.claim_file();
+        write_json(&opts.out, &claim_file)?;
+
+        println!("{} accounts, {} lamports", claim_file.num_nodes, claim_file.total_amount);
+        println!("root {}", claim_file.root);
+        println!("max depth {}", claim_file.max_depth);
+        return Ok(());
+    }
+
+    let whitelist = if opts.weighted {
+        let entries = if is_json { parse_weights_json(&input)? } else { parse_weights_csv(&input)? };
+        Whitelist::new_weighted(entries)?
+    } else {
+        let mints = if is_json { parse_json(&input)? } else { parse_csv(&input)? };
+        if opts.v2 { Whitelist::new_v2(mints)? } else { Whitelist::new(mints)? }
+    };
+    let proof_file = whitelist.proof_file();
+    write_json(&opts.out, &proof_file)?;
+
+    println!("{} mints", proof_file.proofs.len());
+    println!("root {}", proof_file.root);
+    if opts.v2 || opts.weighted {
+        println!("max depth {}", proof_file.max_depth);
+    }
+    Ok(())
+}
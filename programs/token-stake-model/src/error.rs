@@ -23,5 +23,47 @@ pub enum StakeError {
     #[msg("The owner of nft is wrong")]
     WrongOwner,
     #[msg("There is no stakers")]
-    NoStaker
+    NoStaker,
+    #[msg("The pool is sunset")]
+    PoolSunset,
+    #[msg("The pool is not sunset")]
+    PoolNotSunset,
+    #[msg("The sunset grace period is not over")]
+    SunsetGracePeriod,
+    #[msg("There are still stakers or unclaimed rewards")]
+    PoolNotEmpty,
+    #[msg("The user still has staked nfts or rewards")]
+    StakeUserNotEmpty,
+    #[msg("The account version can not be migrated")]
+    AccountVersion,
+    #[msg("The registry page is full")]
+    RegistryFull,
+    #[msg("The nft is not listed in this registry page")]
+    WrongRegistryPage,
+    #[msg("Math overflow")]
+    MathOverflow,
+    #[msg("This is not available in the distribution mode of the pool")]
+    WrongDistributionMode,
+    #[msg("The distribution epoch is wrong")]
+    WrongDistributionEpoch,
+    #[msg("The reward is already claimed")]
+    AlreadyClaimed,
+    #[msg("The distribution has no leaves or too many")]
+    WrongDistributionSize,
+    #[msg("The Merkle depth is over the limit")]
+    MerkleTooDeep,
+    #[msg("The batch accounts are wrong")]
+    WrongBatchAccounts,
+    #[msg("The account is not the metadata of the nft")]
+    WrongMetadata,
+    #[msg("The nft metadata is not whitelisted")]
+    NotWhitelisted,
+    #[msg("The bank can still start the epoch")]
+    CrankTooEarly,
+    #[msg("The crank fee is over the limit")]
+    CrankFeeTooHigh,
+    #[msg("The ended epoch is not credited to every staked nft yet")]
+    EpochNotCredited,
+    #[msg("Stake users of the v1 pool are still to be migrated")]
+    MigrationPending
 }
\ No newline at end of file
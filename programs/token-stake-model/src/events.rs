@@ -0,0 +1,180 @@
+use anchor_lang::prelude::*;
+
+#[event]
+pub struct StakeEvent {
+    pub user_account: Pubkey,
+    pub nft_mint: Pubkey,
+    pub stake_time: i64,
+    pub total_stakers: u16,
+    pub epoch_no: u8,
+    pub day_of_epoch: u8,
+    pub weight: u16,
+}
+
+#[event]
+pub struct UnstakeEvent {
+    pub user_account: Pubkey,
+    pub nft_mint: Pubkey,
+    // pending reward given back to the next epoch
+    pub forfeited_amount: u64,
+    pub total_stakers: u16,
+    pub epoch_no: u8,
+    pub day_of_epoch: u8,
+}
+
+#[event]
+pub struct ClaimEvent {
+    pub user_account: Pubkey,
+    pub amount: u64,
+    pub epoch_no: u8,
+    pub day_of_epoch: u8,
+}
+
+#[event]
+pub struct DailyRewardEvent {
+    pub user_account: Pubkey,
+    pub nft_mint: Pubkey,
+    // days credited, the ones the crank missed included
+    pub days: u8,
+    // credited to the pending amount
+    pub amount: u64,
+    // moved from the pending to the claimable amount
+    pub settled_amount: u64,
+    pub epoch_no: u8,
+    pub day_of_epoch: u8,
+}
+
+#[event]
+pub struct DayAdvanceEvent {
+    pub crank_account: Pubkey,
+    pub crank_fee: u64,
+    pub epoch_no: u8,
+    pub day_of_epoch: u8,
+}
+
+#[event]
+pub struct EpochStartEvent {
+    pub crank_account: Pubkey,
+    pub crank_fee: u64,
+    // the missed epochs are included
+    pub epochs_passed: u8,
+    // moved from the bank to the treasury
+    pub send_amount: u64,
+    // shared by the epoch: the bank transfer, the bonus and the forfeited rewards
+    pub reward_amount: u64,
+    pub reward_per_day: u64,
+    pub epoch_start_time: i64,
+    pub epoch_no: u8,
+}
+
+#[event]
+pub struct EpochRestartEvent {
+    pub treasury_amount: u64,
+    pub reward_per_day: u64,
+    pub epoch_start_time: i64,
+    pub epoch_no: u8,
+}
+
+#[event]
+pub struct EpochBonusEvent {
+    pub bonus_account: Pubkey,
+    pub amount: u64,
+    pub epoch_bonus: u64,
+    pub epoch_no: u8,
+}
+
+#[event]
+pub struct MerkleRootEvent {
+    pub merkle: Pubkey,
+    pub admin_account: Pubkey,
+    pub root: [u8; 32],
+    pub is_hash_v2: bool,
+    pub max_depth: u8,
+    pub is_weighted: bool,
+}
+
+#[event]
+pub struct WhitelistModeEvent {
+    pub merkle: Pubkey,
+    pub admin_account: Pubkey,
+    pub whitelist_mode: u8,
+    pub collection_mint: Pubkey,
+    pub creator: Pubkey,
+    pub update_authority: Pubkey,
+}
+
+#[event]
+pub struct SunsetEvent {
+    pub sunset_time: i64,
+    pub total_owed: u64,
+    pub epoch_no: u8,
+}
+
+#[event]
+pub struct ReclaimTreasuryEvent {
+    pub amount: u64,
+    // left in the treasury for the users
+    pub total_owed: u64,
+}
+
+#[event]
+pub struct PoolCloseEvent {
+    pub bank_account: Pubkey,
+    pub epoch_no: u8,
+}
+
+#[event]
+pub struct StakeUserCloseEvent {
+    pub user_account: Pubkey,
+}
+
+#[event]
+pub struct CrankFeeEvent {
+    pub bank_account: Pubkey,
+    pub crank_fee: u64,
+}
+
+#[event]
+pub struct DistributionModeEvent {
+    pub bank_account: Pubkey,
+    pub distribution_mode: u8,
+}
+
+#[event]
+pub struct RegistryPageEvent {
+    pub registry: Pubkey,
+    pub page: u16,
+}
+
+#[event]
+pub struct MigrationEvent {
+    // the migrated account and the version of its layout now
+    pub account: Pubkey,
+    pub version: u8,
+}
+
+#[event]
+pub struct DistributionPostEvent {
+    pub distribution: Pubkey,
+    pub root: [u8; 32],
+    pub total_amount: u64,
+    pub num_nodes: u32,
+    pub epoch_no: u8,
+}
+
+#[event]
+pub struct DistributionClaimEvent {
+    pub user_account: Pubkey,
+    pub index: u64,
+    pub amount: u64,
+    pub epoch_no: u8,
+}
+
+#[event]
+pub struct DistributionCloseEvent {
+    pub distribution: Pubkey,
+    // no longer owed to the users who never claimed
+    pub unclaimed_amount: u64,
+    pub total_owed: u64,
+    pub epoch_no: u8,
+}
@@ -1,23 +1,42 @@
-use anchor_lang::{prelude::*, solana_program::clock};
-use anchor_spl::token::{self, Mint, TokenAccount, Transfer, Token, CloseAccount};
-use anchor_lang::solana_program::{program::invoke, program::invoke_signed, system_instruction };
-use std::mem::size_of;
+use anchor_lang::{prelude::*, solana_program::clock, Discriminator};
+use anchor_spl::token::{self, Mint, TokenAccount, Transfer, Token, CloseAccount, InitializeAccount};
+use anchor_lang::solana_program::{program::invoke, program::invoke_signed, program::set_return_data, system_instruction };
 pub mod error;
 use crate::{error::StakeError};
-use std::io::{Cursor, Write};
-use std::ops::DerefMut;
-use anchor_lang::__private::CLOSED_ACCOUNT_DISCRIMINATOR;
+pub mod events;
+use crate::events::*;
+pub mod views;
+use crate::views::{UserRewards, PoolStats, EstimatedApr};
 
 pub mod merkle_proof;
+pub mod metadata;
+pub mod migration;
+pub mod rewards;
+use crate::migration::{EpochStateV1, StakeInfoStateV1, StakeNftInfoStateV1, StakeUserStateV1, MerkleV1};
 
 //insert here the program id after anchor deploy
 
-declare_id!("generated_program_id after deploy");
+declare_id!("Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS");
 
 const RATE_BANK_TO_TREASURY: u8 = 100; // 100%
 const DAYS_7_IN_SECONDS: u32 = 604800; // 7 days in seconds
-const EPOCH_DAYS: u8 = 7; // 1 epoch = 7 days
-const TOTAL_EPOCH: u8 = 52; // 1year = 52 epoch
+pub const DAY_IN_SECONDS: u32 = 86400; // 1 day in seconds
+pub const EPOCH_DAYS: u8 = 7; // 1 epoch = 7 days
+pub const TOTAL_EPOCH: u8 = 52; // 1year = 52 epoch
+const SUNSET_GRACE_PERIOD: u32 = 2592000; // 30 days in seconds
+const CRANK_START_GRACE_PERIOD: u32 = 86400; // the bank has 1 day to start the epoch before anyone can
+pub const MAX_CRANK_FEE: u64 = 10_000_000; // 0.01 sol per crank
+pub const REGISTRY_PAGE_SIZE: usize = 128; // staked nfts listed in one registry page
+const MAX_DISTRIBUTION_NODES: u32 = 65536; // leaves of one epoch distribution, 8 KiB of claim bitmap
+pub const DISTRIBUTION_DAILY: u8 = 0; // rewards credited to every staked nft by the daily crank
+pub const DISTRIBUTION_MERKLE: u8 = 1; // rewards claimed against a balance root posted for every epoch
+pub const BALANCE_LEAF_PREFIX: &[u8] = b"nft-staking-balance-tree";
+pub const MINT_LEAF_PREFIX: &[u8] = b"nft-staking-merkle-tree";
+pub const WEIGHTED_LEAF_PREFIX: &[u8] = b"nft-staking-weighted-tree";
+pub const BATCH_ACCOUNTS_PER_NFT: usize = 4; // mint, user token account, vault, stake record
+pub const WHITELIST_MERKLE: u8 = 0; // nfts proved against the Merkle root
+pub const WHITELIST_COLLECTION: u8 = 1; // nfts whose metadata has the verified collection of the pool
+pub const WHITELIST_CREATOR: u8 = 2; // nfts whose metadata lists the verified creator of the pool
 #[program]
 pub mod token_stake_model {
     use super::*;
@@ -58,14 +77,30 @@ pub mod token_stake_model {
 
         let clock = clock::Clock::get().unwrap();
 
+        ctx.accounts.epoch_state.version = EpochState::VERSION;
         ctx.accounts.epoch_state.is_initial = true;
+        ctx.accounts.epoch_state.bank_account = ctx.accounts.bank_account.key();
         ctx.accounts.epoch_state.epoch_no = 1;
         ctx.accounts.epoch_state.epoch_start_time = clock.unix_timestamp;
-        ctx.accounts.epoch_state.cur_epoch_reward_per_day = amount.checked_div(EPOCH_DAYS as u64).unwrap();
+        ctx.accounts.epoch_state.cur_epoch_reward_per_day = rewards::reward_per_day(amount);
         ctx.accounts.epoch_state.epoch_bonus = 0;
+        ctx.accounts.epoch_state.epoch_credited = 0;
 
+        ctx.accounts.stake_info.version = StakeInfoState::VERSION;
         ctx.accounts.stake_info.is_initial = true;
         ctx.accounts.stake_info.day_of_epoch = 0;
+        ctx.accounts.stake_info.days_passed = 0;
+
+        emit!(EpochStartEvent {
+            crank_account: ctx.accounts.bank_account.key(),
+            crank_fee: 0,
+            epochs_passed: 0,
+            send_amount: amount,
+            reward_amount: amount,
+            reward_per_day: ctx.accounts.epoch_state.cur_epoch_reward_per_day,
+            epoch_start_time: ctx.accounts.epoch_state.epoch_start_time,
+            epoch_no: ctx.accounts.epoch_state.epoch_no,
+        });
         Ok(())
     }
 
@@ -83,6 +118,9 @@ pub mod token_stake_model {
         if ctx.accounts.epoch_state.epoch_no > TOTAL_EPOCH {
             return Err(error!(StakeError::EpochEnd));
         }
+        if ctx.accounts.epoch_state.is_sunset {
+            return Err(error!(StakeError::PoolSunset));
+        }
 
         // transfer SOL to treasury account
         invoke(
@@ -98,8 +136,14 @@ pub mod token_stake_model {
             ],
         )?;
 
-        ctx.accounts.epoch_state.epoch_bonus = ctx.accounts.epoch_state.epoch_bonus.checked_add(amount).unwrap();
+        ctx.accounts.epoch_state.epoch_bonus = rewards::add(ctx.accounts.epoch_state.epoch_bonus, amount)?;
 
+        emit!(EpochBonusEvent {
+            bonus_account: ctx.accounts.bonus_account.key(),
+            amount,
+            epoch_bonus: ctx.accounts.epoch_state.epoch_bonus,
+            epoch_no: ctx.accounts.epoch_state.epoch_no,
+        });
         Ok(())
     }
 
@@ -107,7 +151,7 @@ pub mod token_stake_model {
         ctx: Context<StartEpoch>,
     ) -> Result<()> {
 
-        if **ctx.accounts.bank_account.lamports.borrow() <= 0 {
+        if **ctx.accounts.bank_account.lamports.borrow() == 0 {
             return Err(error!(StakeError::NoEnoughSol));
         }
         if !ctx.accounts.epoch_state.is_initial {
@@ -116,28 +160,22 @@ pub mod token_stake_model {
         if !ctx.accounts.stake_info.is_initial {
             return Err(ProgramError::UninitializedAccount.into());
         }
-        if ctx.accounts.epoch_state.epoch_no == 1 {
-            return Err(error!(StakeError::EpochWrongDays));
-        }
         if ctx.accounts.epoch_state.epoch_no > TOTAL_EPOCH {
             return Err(error!(StakeError::EpochEnd));
         }
-        if ctx.accounts.stake_info.day_of_epoch != 0 {
-            return Err(error!(StakeError::EpochEnd));
+        if ctx.accounts.epoch_state.is_sunset {
+            return Err(error!(StakeError::PoolSunset));
         }
         let clock = clock::Clock::get().unwrap();
 
-        if clock.unix_timestamp > ctx.accounts.epoch_state.epoch_start_time.checked_add(DAYS_7_IN_SECONDS as i64).unwrap() {
-            return Err(error!(StakeError::EpochWrongDays));
-        }
+        let epochs_passed = roll_epochs(&mut ctx.accounts.epoch_state, &mut ctx.accounts.stake_info, &mut ctx.accounts.epoch_history, clock.unix_timestamp)?;
 
-        
-        ctx.accounts.epoch_state.epoch_no += 1;
-        ctx.accounts.epoch_state.epoch_start_time = ctx.accounts.epoch_state.epoch_start_time.checked_add(DAYS_7_IN_SECONDS as i64).unwrap();
         let bank_amount = **ctx.accounts.bank_account.lamports.borrow() ;
-        let send_amount = bank_amount.checked_add(ctx.accounts.epoch_state.epoch_bonus).unwrap().checked_add(ctx.accounts.epoch_state.remain_reward).unwrap().checked_mul(RATE_BANK_TO_TREASURY as u64).unwrap().checked_div(100).unwrap();
+        let send_amount = rewards::bank_send_amount(bank_amount)?;
+        // the bonus and the forfeited rewards are already in the treasury
+        let reward_amount = rewards::epoch_reward_amount(send_amount, ctx.accounts.epoch_state.epoch_bonus, ctx.accounts.epoch_state.remain_reward)?;
         
-        ctx.accounts.epoch_state.cur_epoch_reward_per_day = send_amount.checked_div(EPOCH_DAYS as u64).unwrap();
+        ctx.accounts.epoch_state.cur_epoch_reward_per_day = rewards::reward_per_day(reward_amount);
         ctx.accounts.epoch_state.epoch_bonus = 0;
         ctx.accounts.epoch_state.remain_reward = 0;
 
@@ -155,9 +193,133 @@ pub mod token_stake_model {
             ],
         )?;
 
+        emit!(EpochStartEvent {
+            crank_account: ctx.accounts.bank_account.key(),
+            crank_fee: 0,
+            epochs_passed,
+            send_amount,
+            reward_amount,
+            reward_per_day: ctx.accounts.epoch_state.cur_epoch_reward_per_day,
+            epoch_start_time: ctx.accounts.epoch_state.epoch_start_time,
+            epoch_no: ctx.accounts.epoch_state.epoch_no,
+        });
         Ok(())
     }
     
+    pub fn process_crank_start_epoch(
+        ctx: Context<CrankStartEpoch>,
+        treasury_nonce: u8,
+    ) -> Result<()> {
+        if !ctx.accounts.epoch_state.is_initial {
+            return Err(ProgramError::UninitializedAccount.into());
+        }
+        if !ctx.accounts.stake_info.is_initial {
+            return Err(ProgramError::UninitializedAccount.into());
+        }
+        if ctx.accounts.epoch_state.epoch_no > TOTAL_EPOCH {
+            return Err(error!(StakeError::EpochEnd));
+        }
+        if ctx.accounts.epoch_state.is_sunset {
+            return Err(error!(StakeError::PoolSunset));
+        }
+        let clock = clock::Clock::get().unwrap();
+        if !rewards::crank_start_due(ctx.accounts.epoch_state.epoch_start_time, clock.unix_timestamp)? {
+            return Err(error!(StakeError::CrankTooEarly));
+        }
+
+        let epochs_passed = roll_epochs(&mut ctx.accounts.epoch_state, &mut ctx.accounts.stake_info, &mut ctx.accounts.epoch_history, clock.unix_timestamp)?;
+
+        // without the bank only the bonus and the forfeited rewards already in the treasury are shared
+        let reward_amount = rewards::epoch_reward_amount(0, ctx.accounts.epoch_state.epoch_bonus, ctx.accounts.epoch_state.remain_reward)?;
+
+        ctx.accounts.epoch_state.cur_epoch_reward_per_day = rewards::reward_per_day(reward_amount);
+        ctx.accounts.epoch_state.epoch_bonus = 0;
+        ctx.accounts.epoch_state.remain_reward = 0;
+
+        let crank_fee = pay_crank_fee(
+            &ctx.accounts.treasury_account,
+            &ctx.accounts.crank_account.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            &ctx.accounts.epoch_state,
+            treasury_nonce,
+        )?;
+
+        emit!(EpochStartEvent {
+            crank_account: ctx.accounts.crank_account.key(),
+            crank_fee,
+            epochs_passed,
+            send_amount: 0,
+            reward_amount,
+            reward_per_day: ctx.accounts.epoch_state.cur_epoch_reward_per_day,
+            epoch_start_time: ctx.accounts.epoch_state.epoch_start_time,
+            epoch_no: ctx.accounts.epoch_state.epoch_no,
+        });
+        Ok(())
+    }
+
+    pub fn process_set_crank_fee(
+        ctx: Context<SetCrankFee>,
+        crank_fee: u64,
+    ) -> Result<()> {
+        if crank_fee > MAX_CRANK_FEE {
+            return Err(error!(StakeError::CrankFeeTooHigh));
+        }
+        ctx.accounts.epoch_state.crank_fee = crank_fee;
+
+        emit!(CrankFeeEvent {
+            bank_account: ctx.accounts.bank_account.key(),
+            crank_fee,
+        });
+        Ok(())
+    }
+
+    pub fn process_set_distribution_mode(
+        ctx: Context<SetDistributionMode>,
+        distribution_mode: u8,
+    ) -> Result<()> {
+        if distribution_mode != DISTRIBUTION_DAILY && distribution_mode != DISTRIBUTION_MERKLE {
+            return Err(error!(StakeError::WrongDistributionMode));
+        }
+        ctx.accounts.epoch_state.distribution_mode = distribution_mode;
+
+        emit!(DistributionModeEvent {
+            bank_account: ctx.accounts.bank_account.key(),
+            distribution_mode,
+        });
+        Ok(())
+    }
+
+    pub fn process_initialize_epoch_history(
+        ctx: Context<InitializeEpochHistory>,
+    ) -> Result<()> {
+        ctx.accounts.epoch_history.version = EpochHistory::VERSION;
+        ctx.accounts.epoch_history.records = Vec::new();
+        Ok(())
+    }
+
+    // the pages are created one after the other, so they can be enumerated from 0 to registry_pages
+    pub fn process_initialize_registry_page(
+        ctx: Context<InitializeRegistryPage>,
+        page: u16,
+    ) -> Result<()> {
+        if page != ctx.accounts.stake_info.registry_pages {
+            return Err(error!(StakeError::WrongRegistryPage));
+        }
+        let mut registry = ctx.accounts.registry.load_init()?;
+        registry.version = StakeRegistry::VERSION;
+        registry.bump = ctx.bumps.registry;
+        registry.page = page;
+        registry.count = 0;
+
+        ctx.accounts.stake_info.registry_pages += 1;
+
+        emit!(RegistryPageEvent {
+            registry: ctx.accounts.registry.key(),
+            page,
+        });
+        Ok(())
+    }
+
     pub fn process_initialize_merkle(
         ctx: Context<InitializeMerkle>,
         root: [u8; 32],
@@ -166,21 +328,98 @@ pub mod token_stake_model {
             return Err(ProgramError::AccountAlreadyInitialized.into());
         }
         let merkle = &mut ctx.accounts.merkle;
+        merkle.version = Merkle::VERSION;
         merkle.root = root;
         merkle.is_init = true;
         merkle.admin_account = ctx.accounts.admin_account.key();
+
+        emit!(MerkleRootEvent {
+            merkle: merkle.key(),
+            admin_account: merkle.admin_account,
+            root,
+            is_hash_v2: false,
+            max_depth: 0,
+            is_weighted: false,
+        });
         Ok(())
     }
 
     pub fn update_merkle(
         ctx: Context<UpdateMerkle>,
         root: [u8; 32],
+    ) -> Result<()> {
+        if !ctx.accounts.merkle.is_init {
+            return Err(ProgramError::UninitializedAccount.into());
+        }
+        set_merkle_root(&mut ctx.accounts.merkle, root, false, 0, false);
+        Ok(())
+    }
+
+    // a v2 root separates leaf and node hashes and bounds the proof length
+    pub fn update_merkle_v2(
+        ctx: Context<UpdateMerkle>,
+        root: [u8; 32],
+        max_depth: u8,
+    ) -> Result<()> {
+        if !ctx.accounts.merkle.is_init {
+            return Err(ProgramError::UninitializedAccount.into());
+        }
+        if max_depth > merkle_proof::MAX_DEPTH {
+            return Err(error!(StakeError::MerkleTooDeep));
+        }
+        set_merkle_root(&mut ctx.accounts.merkle, root, true, max_depth, false);
+        Ok(())
+    }
+
+    // a weighted root is a v2 root whose leaves also hash the reward weight of
+    // the nft, see Merkle::mint_leaf
+    pub fn update_merkle_weighted(
+        ctx: Context<UpdateMerkle>,
+        root: [u8; 32],
+        max_depth: u8,
+    ) -> Result<()> {
+        if !ctx.accounts.merkle.is_init {
+            return Err(ProgramError::UninitializedAccount.into());
+        }
+        if max_depth > merkle_proof::MAX_DEPTH {
+            return Err(error!(StakeError::MerkleTooDeep));
+        }
+        set_merkle_root(&mut ctx.accounts.merkle, root, true, max_depth, true);
+        Ok(())
+    }
+
+    // whitelists every nft of the verified Metaplex collection `collection_mint`
+    // instead of the root, until a root is posted again
+    pub fn set_whitelist_collection(
+        ctx: Context<UpdateMerkle>,
+        collection_mint: Pubkey,
     ) -> Result<()> {
         if !ctx.accounts.merkle.is_init {
             return Err(ProgramError::UninitializedAccount.into());
         }
         let merkle = &mut ctx.accounts.merkle;
-        merkle.root = root;
+        merkle.whitelist_mode = WHITELIST_COLLECTION;
+        merkle.collection_mint = collection_mint;
+        emit_whitelist_mode(merkle);
+        Ok(())
+    }
+
+    // whitelists every nft listing `creator` as a verified creator, for the
+    // collections without a collection field. With `update_authority` the
+    // metadata must also have that update authority
+    pub fn set_whitelist_creator(
+        ctx: Context<UpdateMerkle>,
+        creator: Pubkey,
+        update_authority: Option<Pubkey>,
+    ) -> Result<()> {
+        if !ctx.accounts.merkle.is_init {
+            return Err(ProgramError::UninitializedAccount.into());
+        }
+        let merkle = &mut ctx.accounts.merkle;
+        merkle.whitelist_mode = WHITELIST_CREATOR;
+        merkle.creator = creator;
+        merkle.update_authority = update_authority.unwrap_or_default();
+        emit_whitelist_mode(merkle);
         Ok(())
     }
 
@@ -190,15 +429,47 @@ pub mod token_stake_model {
         if ctx.accounts.stake_user.is_initial {
             return Err(ProgramError::AccountAlreadyInitialized.into());
         }
+        ctx.accounts.stake_user.version = StakeUserState::VERSION;
         ctx.accounts.stake_user.user_account = ctx.accounts.user_account.key();
         ctx.accounts.stake_user.is_initial = true;
         Ok(())
     }
 
+    pub fn process_close_stake_user(
+        ctx: Context<CloseStakeUser>,
+    ) -> Result<()> {
+        // a migrated count is only trusted once every v1 nft was counted
+        if ctx.accounts.stake_info.v1_stakers > 0 {
+            return Err(error!(StakeError::MigrationPending));
+        }
+        if ctx.accounts.stake_user.staked_count > 0 {
+            return Err(error!(StakeError::StakeUserNotEmpty));
+        }
+        if ctx.accounts.stake_user.reward_amount > 0 || ctx.accounts.stake_user.pending_amount > 0 {
+            return Err(error!(StakeError::StakeUserNotEmpty));
+        }
+
+        emit!(StakeUserCloseEvent {
+            user_account: ctx.accounts.user_account.key(),
+        });
+        Ok(())
+    }
+
     pub fn process_stake_nft(
         ctx: Context<StakeNft>,
         proof: Vec<[u8; 32]>,
     ) -> Result<()> {
+        process_stake_weighted_nft(ctx, proof, 1)
+    }
+
+    // weight: the reward weight of the nft in a weighted whitelist, 1 otherwise
+    // remaining_accounts: the metadata account of the nft, outside of the
+    // Merkle whitelist mode
+    pub fn process_stake_weighted_nft(
+        ctx: Context<StakeNft>,
+        proof: Vec<[u8; 32]>,
+        weight: u16,
+    ) -> Result<()> {
 
         if !ctx.accounts.merkle.is_init {
             return Err(ProgramError::UninitializedAccount.into());
@@ -206,20 +477,19 @@ pub mod token_stake_model {
         if !ctx.accounts.stake_info.is_initial {
             return Err(ProgramError::UninitializedAccount.into());
         }
+        if ctx.accounts.epoch_state.is_sunset {
+            return Err(error!(StakeError::PoolSunset));
+        }
         
         let clock = clock::Clock::get().unwrap();
 
-        let merkle_seed = b"nft-staking-merkle-tree";
-
-        let node = anchor_lang::solana_program::keccak::hashv(&[
-            &merkle_seed.as_ref(),
-            &ctx.accounts.nft_mint.to_account_info().key().to_bytes(),
-        ]);
-
-        let merkle = &ctx.accounts.merkle;
-        
-        if !merkle_proof::verify(proof, merkle.root, node.0) {
-            return Err(error!(StakeError::InvalidProof));
+        if ctx.accounts.merkle.whitelist_mode == WHITELIST_MERKLE {
+            if !ctx.accounts.merkle.verify_mint(proof, &ctx.accounts.nft_mint.key(), weight) {
+                return Err(error!(StakeError::InvalidProof));
+            }
+        } else {
+            let metadata_account = ctx.remaining_accounts.first().ok_or_else(|| error!(StakeError::WrongMetadata))?;
+            ctx.accounts.merkle.verify_metadata(metadata_account, &ctx.accounts.nft_mint.key(), weight)?;
         }
 
         // transfer the nft to vault account
@@ -228,18 +498,51 @@ pub mod token_stake_model {
             1,
         )?;
 
+        ctx.accounts.stake_info_account.version = StakeNftInfoState::VERSION;
         ctx.accounts.stake_info_account.user_account = ctx.accounts.user_account.key();
         ctx.accounts.stake_info_account.nft_mint = ctx.accounts.nft_mint.key();
         ctx.accounts.stake_info_account.stake_time = clock.unix_timestamp;
-
+        ctx.accounts.stake_info_account.weight = weight;
+        // the nft is credited from the next day on
+        ctx.accounts.stake_info_account.credited_epoch_start = ctx.accounts.epoch_state.epoch_start_time;
+        ctx.accounts.stake_info_account.credited_days = ctx.accounts.stake_info.days_passed();
+
+        let mut registry = ctx.accounts.registry.load_mut()?;
+        registry.push(RegistryEntry {
+            nft_mint: ctx.accounts.nft_mint.key(),
+            user_account: ctx.accounts.user_account.key(),
+        })?;
+        ctx.accounts.stake_info_account.registry_page = registry.page;
+        ctx.accounts.stake_info_account.is_registered = true;
+
+        ctx.accounts.stake_info.add_weight(weight)?;
+        ctx.accounts.stake_user.add_weight(weight)?;
         ctx.accounts.stake_info.total_stakers += 1;
-        
+        ctx.accounts.stake_user.staked_count += 1;
+        // staked on the last day, there is nothing left to credit
+        if ctx.accounts.stake_info_account.is_epoch_credited(ctx.accounts.epoch_state.epoch_start_time) {
+            ctx.accounts.stake_info.credited_nfts += 1;
+        }
+        if ctx.accounts.stake_user.first_stake_time == 0 {
+            ctx.accounts.stake_user.first_stake_time = clock.unix_timestamp;
+        }
+
+        emit!(StakeEvent {
+            user_account: ctx.accounts.user_account.key(),
+            nft_mint: ctx.accounts.nft_mint.key(),
+            stake_time: clock.unix_timestamp,
+            total_stakers: ctx.accounts.stake_info.total_stakers,
+            epoch_no: ctx.accounts.epoch_state.epoch_no,
+            day_of_epoch: ctx.accounts.stake_info.day_of_epoch,
+            weight,
+        });
         Ok(())
     }
 
+    // the stake record is the proof that the nft was whitelisted, so the nft
+    // can be unstaked whatever the current whitelist
     pub fn process_unstake_nft(
         ctx: Context<UnStakeNft>,
-        proof: Vec<[u8; 32]>,
     ) -> Result<()> {
 
         if !ctx.accounts.epoch_state.is_initial {
@@ -248,50 +551,321 @@ pub mod token_stake_model {
         if !ctx.accounts.stake_info.is_initial {
             return Err(ProgramError::UninitializedAccount.into());
         }
+
+        release_nft(
+            ctx.program_id,
+            &ctx.accounts.token_program.to_account_info(),
+            &ctx.accounts.nft_vault_account.to_account_info(),
+            &ctx.accounts.user_nft_token_account.to_account_info(),
+            &ctx.accounts.vault_auth,
+            &ctx.accounts.user_account.to_account_info(),
+            &ctx.accounts.stake_info_account,
+            &ctx.accounts.registry,
+        )?;
+
+        let mut forfeited_amount = 0;
+        let weight = ctx.accounts.stake_info_account.weight();
+        if ctx.accounts.epoch_state.is_sunset {
+            credit_sunset_days(&mut ctx.accounts.epoch_state, &ctx.accounts.stake_info, &ctx.accounts.stake_info_account, &mut ctx.accounts.stake_user)?;
+            // the final epoch is settled, so the pending reward stays with the user
+            ctx.accounts.stake_user.reward_amount = rewards::add(ctx.accounts.stake_user.reward_amount, ctx.accounts.stake_user.pending_amount)?;
+        } else {
+            ctx.accounts.stake_info.sub_weight(weight);
+            forfeited_amount = ctx.accounts.stake_user.pending_amount;
+            ctx.accounts.epoch_state.remain_reward = rewards::add(ctx.accounts.epoch_state.remain_reward, ctx.accounts.stake_user.pending_amount)?;
+            // pools migrated from v1 only carry an estimate of what is owed
+            ctx.accounts.epoch_state.total_owed = ctx.accounts.epoch_state.total_owed.saturating_sub(ctx.accounts.stake_user.pending_amount);
+        }
+
+        ctx.accounts.stake_user.pending_amount = 0;
+
+        ctx.accounts.stake_user.sub_weight(weight);
+        ctx.accounts.stake_info.total_stakers -= 1;
+        if ctx.accounts.stake_info_account.is_epoch_credited(ctx.accounts.epoch_state.epoch_start_time) {
+            ctx.accounts.stake_info.credited_nfts -= 1;
+        }
+        // users migrated from v1 may not have counted every staked nft
+        ctx.accounts.stake_user.staked_count = ctx.accounts.stake_user.staked_count.saturating_sub(1);
+        ctx.accounts.stake_user.total_forfeited = rewards::add(ctx.accounts.stake_user.total_forfeited, forfeited_amount)?;
+
+        emit!(UnstakeEvent {
+            user_account: ctx.accounts.user_account.key(),
+            nft_mint: ctx.accounts.nft_mint.key(),
+            forfeited_amount,
+            total_stakers: ctx.accounts.stake_info.total_stakers,
+            epoch_no: ctx.accounts.epoch_state.epoch_no,
+            day_of_epoch: ctx.accounts.stake_info.day_of_epoch,
+        });
+        Ok(())
+    }
+
+    // remaining_accounts: for every nft its mint, the user token account holding
+    // it, its vault and its stake record, the last two not created yet, then
+    // its metadata account outside of the Merkle whitelist mode.
+    // leaf_indices are the whitelist indices of the mints and weights their
+    // reward weights, in the same order. weights is empty unless the whitelist
+    // is weighted, proof and leaf_indices are empty outside of the Merkle mode
+    pub fn process_stake_many<'info>(
+        ctx: Context<'_, '_, 'info, 'info, StakeMany<'info>>,
+        proof: Vec<[u8; 32]>,
+        leaf_indices: Vec<u32>,
+        num_leaves: u32,
+        weights: Vec<u16>,
+    ) -> Result<()> {
+
         if !ctx.accounts.merkle.is_init {
             return Err(ProgramError::UninitializedAccount.into());
         }
+        if !ctx.accounts.stake_info.is_initial {
+            return Err(ProgramError::UninitializedAccount.into());
+        }
+        if ctx.accounts.epoch_state.is_sunset {
+            return Err(error!(StakeError::PoolSunset));
+        }
+        let groups = batch_groups(ctx.remaining_accounts, ctx.accounts.merkle.batch_group_len())?;
+        if !weights.is_empty() && weights.len() != groups.len() {
+            return Err(error!(StakeError::WrongBatchAccounts));
+        }
+        let weights = if weights.is_empty() { vec![1; groups.len()] } else { weights };
 
-        let merkle_seed = b"nft-staking-merkle-tree";
+        if ctx.accounts.merkle.whitelist_mode == WHITELIST_MERKLE {
+            if leaf_indices.len() != groups.len() {
+                return Err(error!(StakeError::WrongBatchAccounts));
+            }
+            let mints: Vec<(u32, Pubkey, u16)> = leaf_indices
+                .iter()
+                .zip(groups.iter())
+                .zip(weights.iter())
+                .map(|((index, group), weight)| (*index, group[0].key(), *weight))
+                .collect();
+            if !ctx.accounts.merkle.verify_mints(&proof, &mints, num_leaves) {
+                return Err(error!(StakeError::InvalidProof));
+            }
+        } else {
+            for (group, weight) in groups.iter().zip(weights.iter()) {
+                ctx.accounts.merkle.verify_metadata(&group[BATCH_ACCOUNTS_PER_NFT], &group[0].key(), *weight)?;
+            }
+        }
 
-        let node = anchor_lang::solana_program::keccak::hashv(&[
-            &merkle_seed.as_ref(),
-            &ctx.accounts.nft_mint.to_account_info().key().to_bytes(),
-        ]);
+        let clock = clock::Clock::get().unwrap();
+        let user_key = ctx.accounts.user_account.key();
+        let user_info = ctx.accounts.user_account.to_account_info();
+        let system_program = ctx.accounts.system_program.to_account_info();
+        let mut registry = ctx.accounts.registry.load_mut()?;
 
-        let merkle = &ctx.accounts.merkle;
-        
-        if !merkle_proof::verify(proof, merkle.root, node.0) {
-            return Err(error!(StakeError::InvalidProof));
-        } 
+        for (group, weight) in groups.into_iter().zip(weights) {
+            let (mint_info, user_nft_token_info, vault_info, stake_info_info) = (&group[0], &group[1], &group[2], &group[3]);
+            let nft_mint = mint_info.key();
+
+            let user_nft_token_account = Account::<TokenAccount>::try_from(user_nft_token_info)?;
+            if user_nft_token_account.amount != 1 || user_nft_token_account.owner != user_key || user_nft_token_account.mint != nft_mint {
+                return Err(error!(StakeError::WrongOwner));
+            }
+
+            let (vault_key, vault_bump) = Pubkey::find_program_address(&[b"vault-stake", nft_mint.as_ref(), user_key.as_ref()], ctx.program_id);
+            let (stake_info_key, stake_info_bump) = Pubkey::find_program_address(&[b"user-stake", nft_mint.as_ref(), user_key.as_ref()], ctx.program_id);
+            if vault_info.key() != vault_key || stake_info_info.key() != stake_info_key {
+                return Err(error!(StakeError::WrongBatchAccounts));
+            }
+
+            // transfer the nft to vault account
+            create_pda_account(
+                &user_info,
+                vault_info,
+                &system_program,
+                TokenAccount::LEN,
+                &token::ID,
+                &[b"vault-stake", nft_mint.as_ref(), user_key.as_ref(), &[vault_bump]],
+            )?;
+            token::initialize_account(CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                InitializeAccount {
+                    account: vault_info.clone(),
+                    mint: mint_info.clone(),
+                    authority: ctx.accounts.nft_authority.to_account_info(),
+                    rent: ctx.accounts.rent.to_account_info(),
+                },
+            ))?;
+            token::transfer(
+                CpiContext::new(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: user_nft_token_info.clone(),
+                        to: vault_info.clone(),
+                        authority: user_info.clone(),
+                    },
+                ),
+                1,
+            )?;
+
+            create_pda_account(
+                &user_info,
+                stake_info_info,
+                &system_program,
+                8 + StakeNftInfoState::LEN,
+                ctx.program_id,
+                &[b"user-stake", nft_mint.as_ref(), user_key.as_ref(), &[stake_info_bump]],
+            )?;
+            registry.push(RegistryEntry {
+                nft_mint,
+                user_account: user_key,
+            })?;
+            let stake_record = StakeNftInfoState {
+                version: StakeNftInfoState::VERSION,
+                user_account: user_key,
+                nft_mint,
+                stake_time: clock.unix_timestamp,
+                registry_page: registry.page,
+                is_registered: true,
+                weight,
+                credited_epoch_start: ctx.accounts.epoch_state.epoch_start_time,
+                credited_days: ctx.accounts.stake_info.days_passed(),
+                reserved: [0; 18],
+            };
+            migration::store(stake_info_info, &stake_record)?;
+            // staked on the last day, there is nothing left to credit
+            if stake_record.is_epoch_credited(ctx.accounts.epoch_state.epoch_start_time) {
+                ctx.accounts.stake_info.credited_nfts += 1;
+            }
+
+            ctx.accounts.stake_info.add_weight(weight)?;
+            ctx.accounts.stake_user.add_weight(weight)?;
+            ctx.accounts.stake_info.total_stakers = ctx.accounts.stake_info.total_stakers.checked_add(1).ok_or(error!(StakeError::MathOverflow))?;
+            ctx.accounts.stake_user.staked_count = ctx.accounts.stake_user.staked_count.checked_add(1).ok_or(error!(StakeError::MathOverflow))?;
+
+            emit!(StakeEvent {
+                user_account: user_key,
+                nft_mint,
+                stake_time: clock.unix_timestamp,
+                total_stakers: ctx.accounts.stake_info.total_stakers,
+                epoch_no: ctx.accounts.epoch_state.epoch_no,
+                day_of_epoch: ctx.accounts.stake_info.day_of_epoch,
+                weight,
+            });
+        }
+
+        if ctx.accounts.stake_user.first_stake_time == 0 {
+            ctx.accounts.stake_user.first_stake_time = clock.unix_timestamp;
+        }
+        Ok(())
+    }
+
+    // remaining_accounts: for every nft its mint, the user token account to
+    // send it back to, its vault and its stake record. The registered nfts
+    // must all be listed in the registry page passed. Like process_unstake_nft,
+    // no proof is needed
+    pub fn process_unstake_many<'info>(
+        ctx: Context<'_, '_, 'info, 'info, UnstakeMany<'info>>,
+    ) -> Result<()> {
+
+        if !ctx.accounts.epoch_state.is_initial {
+            return Err(ProgramError::UninitializedAccount.into());
+        }
+        if !ctx.accounts.stake_info.is_initial {
+            return Err(ProgramError::UninitializedAccount.into());
+        }
+        let groups = batch_groups(ctx.remaining_accounts, BATCH_ACCOUNTS_PER_NFT)?;
 
-        // transfer the nft to vault account
         let (_vault_authority, vault_authority_bump) =
             Pubkey::find_program_address(&[b"vault-stake-auth"], ctx.program_id);
-
         let authority_seeds = &[&b"vault-stake-auth"[..], &[vault_authority_bump]];
 
-        token::transfer(
-            ctx.accounts.into_transfer_to_user_context().with_signer(&[&authority_seeds[..]]),
-            1,
-        )?;
+        // the pending reward is forfeited once, with the first nft
+        let mut forfeited_amount = 0;
+        if ctx.accounts.epoch_state.is_sunset {
+            // the final epoch is settled, so the pending reward stays with the user
+            ctx.accounts.stake_user.reward_amount = rewards::add(ctx.accounts.stake_user.reward_amount, ctx.accounts.stake_user.pending_amount)?;
+        } else {
+            forfeited_amount = ctx.accounts.stake_user.pending_amount;
+            ctx.accounts.epoch_state.remain_reward = rewards::add(ctx.accounts.epoch_state.remain_reward, ctx.accounts.stake_user.pending_amount)?;
+            // pools migrated from v1 only carry an estimate of what is owed
+            ctx.accounts.epoch_state.total_owed = ctx.accounts.epoch_state.total_owed.saturating_sub(ctx.accounts.stake_user.pending_amount);
+        }
+        ctx.accounts.stake_user.pending_amount = 0;
+        ctx.accounts.stake_user.total_forfeited = rewards::add(ctx.accounts.stake_user.total_forfeited, forfeited_amount)?;
 
-        token::close_account(CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            CloseAccount {
-                account: ctx.accounts.nft_vault_account.to_account_info(),
-                destination: ctx.accounts.user_account.to_account_info(),
-                authority: ctx.accounts.vault_auth.to_account_info(),
-            },
-            &[&authority_seeds[..]],
-        ));
+        let user_key = ctx.accounts.user_account.key();
+        let user_info = ctx.accounts.user_account.to_account_info();
+        let mut registry = ctx.accounts.registry.load_mut()?;
+        // closed once every transfer is done
+        let mut stake_info_accounts = Vec::with_capacity(groups.len());
 
-        ctx.accounts.epoch_state.remain_reward = ctx.accounts.epoch_state.remain_reward.checked_add(ctx.accounts.stake_user.pending_amount).unwrap();
+        for group in groups {
+            let (mint_info, user_nft_token_info, vault_info, stake_info_info) = (&group[0], &group[1], &group[2], &group[3]);
+            let nft_mint = mint_info.key();
 
-        ctx.accounts.stake_user.pending_amount = 0;
+            let user_nft_token_account = Account::<TokenAccount>::try_from(user_nft_token_info)?;
+            if user_nft_token_account.owner != user_key || user_nft_token_account.mint != nft_mint {
+                return Err(error!(StakeError::WrongOwner));
+            }
 
-        ctx.accounts.stake_info.total_stakers -= 1;
+            let (vault_key, _) = Pubkey::find_program_address(&[b"vault-stake", nft_mint.as_ref(), user_key.as_ref()], ctx.program_id);
+            let (stake_info_key, _) = Pubkey::find_program_address(&[b"user-stake", nft_mint.as_ref(), user_key.as_ref()], ctx.program_id);
+            if vault_info.key() != vault_key || stake_info_info.key() != stake_info_key {
+                return Err(error!(StakeError::WrongBatchAccounts));
+            }
+            let stake_info_account = Account::<StakeNftInfoState>::try_from(stake_info_info)?;
+
+            // transfer the nft back to the user
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: vault_info.clone(),
+                        to: user_nft_token_info.clone(),
+                        authority: ctx.accounts.vault_auth.to_account_info(),
+                    },
+                    &[&authority_seeds[..]],
+                ),
+                1,
+            )?;
+            token::close_account(CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                CloseAccount {
+                    account: vault_info.clone(),
+                    destination: user_info.clone(),
+                    authority: ctx.accounts.vault_auth.to_account_info(),
+                },
+                &[&authority_seeds[..]],
+            ))?;
+
+            // nfts staked before the registry existed are not listed
+            if stake_info_account.is_registered {
+                if registry.page != stake_info_account.registry_page {
+                    return Err(error!(StakeError::WrongRegistryPage));
+                }
+                registry.swap_remove(nft_mint)?;
+            }
+            let weight = stake_info_account.weight();
+            if stake_info_account.is_epoch_credited(ctx.accounts.epoch_state.epoch_start_time) {
+                ctx.accounts.stake_info.credited_nfts -= 1;
+            }
+            if ctx.accounts.epoch_state.is_sunset {
+                credit_sunset_days(&mut ctx.accounts.epoch_state, &ctx.accounts.stake_info, &stake_info_account, &mut ctx.accounts.stake_user)?;
+            } else {
+                ctx.accounts.stake_info.sub_weight(weight);
+            }
+            stake_info_accounts.push(stake_info_account);
+
+            ctx.accounts.stake_user.sub_weight(weight);
+            ctx.accounts.stake_info.total_stakers = ctx.accounts.stake_info.total_stakers.checked_sub(1).ok_or(error!(StakeError::NoStaker))?;
+            // users migrated from v1 may not have counted every staked nft
+            ctx.accounts.stake_user.staked_count = ctx.accounts.stake_user.staked_count.saturating_sub(1);
+
+            emit!(UnstakeEvent {
+                user_account: user_key,
+                nft_mint,
+                forfeited_amount,
+                total_stakers: ctx.accounts.stake_info.total_stakers,
+                epoch_no: ctx.accounts.epoch_state.epoch_no,
+                day_of_epoch: ctx.accounts.stake_info.day_of_epoch,
+            });
+            forfeited_amount = 0;
+        }
 
+        for stake_info_account in stake_info_accounts {
+            stake_info_account.close(user_info.clone())?;
+        }
         Ok(())
     }
 
@@ -299,27 +873,112 @@ pub mod token_stake_model {
         ctx: Context<ClaimReward>,
         treasury_nonce: u8,
     ) -> Result<()> {
-        let claim_amount = ctx.accounts.stake_user.reward_amount;
+        pay_reward(
+            &ctx.accounts.treasury_account,
+            &ctx.accounts.user_account.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            &mut ctx.accounts.stake_user,
+            &mut ctx.accounts.epoch_state,
+            ctx.accounts.stake_info.day_of_epoch,
+            treasury_nonce,
+        )
+    }
 
-        if claim_amount > 0 {
-            invoke_signed(
-                &system_instruction::transfer(
-                    ctx.accounts.treasury_account.key,
-                    ctx.accounts.user_account.key,
-                    claim_amount,
-                ),
-                &[
-                    ctx.accounts.treasury_account.clone(),
-                    ctx.accounts.user_account.to_account_info().clone(),
-                    ctx.accounts.system_program.to_account_info().clone(),
-                ],
-                &[&[b"epoch-treasury", &[treasury_nonce]]],
-            )?;
+    // the admin posts the rewards of an epoch computed off-chain, the users claim them with a proof
+    pub fn process_post_epoch_distribution(
+        ctx: Context<PostEpochDistribution>,
+        epoch_no: u8,
+        root: [u8; 32],
+        total_amount: u64,
+        num_nodes: u32,
+    ) -> Result<()> {
+        if ctx.accounts.epoch_state.distribution_mode != DISTRIBUTION_MERKLE {
+            return Err(error!(StakeError::WrongDistributionMode));
+        }
+        if epoch_no == 0 || epoch_no > ctx.accounts.epoch_state.epoch_no {
+            return Err(error!(StakeError::WrongDistributionEpoch));
+        }
+        if num_nodes == 0 || num_nodes > MAX_DISTRIBUTION_NODES {
+            return Err(error!(StakeError::WrongDistributionSize));
+        }
+
+        // what is already owed stays in the treasury, and so does its rent
+        let keep_amount = rewards::add(ctx.accounts.epoch_state.total_owed, Rent::get()?.minimum_balance(0))?;
+        if ctx.accounts.treasury_account.lamports() < rewards::add(keep_amount, total_amount)? {
+            return Err(error!(StakeError::NoEnoughSol));
+        }
+
+        let distribution = &mut ctx.accounts.distribution;
+        distribution.version = EpochDistribution::VERSION;
+        distribution.epoch_no = epoch_no;
+        distribution.root = root;
+        distribution.total_amount = total_amount;
+        distribution.num_nodes = num_nodes;
+        distribution.max_depth = merkle_proof::tree_depth(num_nodes) as u8;
+        distribution.claimed_bitmap = vec![0; EpochDistribution::bitmap_len(num_nodes)];
+
+        ctx.accounts.epoch_state.total_owed = rewards::add(ctx.accounts.epoch_state.total_owed, total_amount)?;
+
+        emit!(DistributionPostEvent {
+            distribution: ctx.accounts.distribution.key(),
+            root,
+            total_amount,
+            num_nodes,
+            epoch_no,
+        });
+        Ok(())
+    }
+
+    pub fn process_claim_epoch_distribution(
+        ctx: Context<ClaimEpochDistribution>,
+        epoch_no: u8,
+        treasury_nonce: u8,
+        index: u64,
+        amount: u64,
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        let distribution = &mut ctx.accounts.distribution;
+        if index >= distribution.num_nodes as u64 {
+            return Err(error!(StakeError::InvalidProof));
+        }
+        if distribution.is_claimed(index) {
+            return Err(error!(StakeError::AlreadyClaimed));
+        }
 
-            ctx.accounts.stake_user.reward_amount = ctx.accounts.stake_user.reward_amount.checked_sub(claim_amount).unwrap();
+        if !distribution.verify_balance(proof, index, &ctx.accounts.user_account.key(), amount) {
+            return Err(error!(StakeError::InvalidProof));
+        }
 
+        let total_claimed = rewards::add(distribution.total_claimed, amount)?;
+        if total_claimed > distribution.total_amount {
+            return Err(error!(StakeError::ClaimAmountBig));
         }
-                
+
+        invoke_signed(
+            &system_instruction::transfer(
+                ctx.accounts.treasury_account.key,
+                ctx.accounts.user_account.key,
+                amount,
+            ),
+            &[
+                ctx.accounts.treasury_account.clone(),
+                ctx.accounts.user_account.to_account_info().clone(),
+                ctx.accounts.system_program.to_account_info().clone(),
+            ],
+            &[&[b"epoch-treasury", &[treasury_nonce]]],
+        )?;
+
+        distribution.set_claimed(index);
+        distribution.total_claimed = total_claimed;
+        distribution.num_claimed += 1;
+        ctx.accounts.epoch_state.total_owed = ctx.accounts.epoch_state.total_owed.saturating_sub(amount);
+
+        emit!(DistributionClaimEvent {
+            user_account: ctx.accounts.user_account.key(),
+            index,
+            amount,
+            epoch_no,
+        });
         Ok(())
     }
 
@@ -338,22 +997,57 @@ pub mod token_stake_model {
         if ctx.accounts.epoch_state.epoch_no > TOTAL_EPOCH {
             return Err(error!(StakeError::EpochEnd));
         }
+        // after the sunset the days up to it are still credited, the crank
+        // cannot pass any more of them
 
         if ctx.accounts.stake_info.total_stakers > 0 {
             
-            let reward_per_nft = ctx.accounts.epoch_state.cur_epoch_reward_per_day.checked_div(ctx.accounts.stake_info.total_stakers as u64).unwrap();
+            let (days_due, reward_amount) = nft_days_due(&ctx.accounts.epoch_state, &ctx.accounts.stake_info, &ctx.accounts.stake_nft_info)?;
 
             if ctx.accounts.stake_nft_info.user_account != ctx.accounts.stake_user_account.user_account {
                 return Err(error!(StakeError::WrongOwner));
             }
 
-            if ctx.accounts.stake_info.day_of_epoch == 0 {
-                ctx.accounts.stake_user_account.reward_amount = ctx.accounts.stake_user_account.reward_amount.checked_add(ctx.accounts.stake_user_account.pending_amount).unwrap();
-                ctx.accounts.stake_user_account.pending_amount = 0;
+            let epoch_start_time = ctx.accounts.epoch_state.epoch_start_time;
+            let days_passed = ctx.accounts.stake_info.days_passed();
+            let days = if ctx.accounts.epoch_state.distribution_mode == DISTRIBUTION_MERKLE {
+                // the merkle mode only settles what was credited before the switch
+                if ctx.accounts.stake_info.day_of_epoch != 0 {
+                    return Err(error!(StakeError::WrongDistributionMode));
+                }
+                0
             } else {
-                ctx.accounts.stake_user_account.pending_amount = ctx.accounts.stake_user_account.pending_amount.checked_add(reward_per_nft).unwrap();
+                if days_due == 0 && ctx.accounts.stake_info.day_of_epoch != 0 {
+                    return Err(error!(StakeError::NoDaily));
+                }
+                days_due
+            };
+
+            let was_credited = ctx.accounts.stake_nft_info.is_epoch_credited(epoch_start_time);
+            let credit = rewards::daily_credit(ctx.accounts.stake_info.day_of_epoch, days, reward_amount, ctx.accounts.stake_user_account.pending_amount)?;
+            let stake_user = &mut ctx.accounts.stake_user_account;
+            stake_user.reward_amount = rewards::add(stake_user.reward_amount, credit.settled_amount)?;
+            // the settled amount is the whole pending amount
+            stake_user.pending_amount = rewards::add(stake_user.pending_amount, credit.amount)? - credit.settled_amount;
+            stake_user.total_credited = rewards::add(stake_user.total_credited, credit.amount)?;
+            ctx.accounts.epoch_state.total_owed = rewards::add(ctx.accounts.epoch_state.total_owed, credit.amount)?;
+            ctx.accounts.epoch_state.epoch_credited = rewards::add(ctx.accounts.epoch_state.epoch_credited, credit.amount)?;
+            ctx.accounts.stake_nft_info.credited_epoch_start = epoch_start_time;
+            ctx.accounts.stake_nft_info.credited_days = days_passed;
+            if !was_credited && ctx.accounts.stake_nft_info.is_epoch_credited(epoch_start_time) {
+                ctx.accounts.stake_info.credited_nfts += 1;
             }
 
+            emit!(DailyRewardEvent {
+                user_account: ctx.accounts.stake_user_account.user_account,
+                nft_mint: ctx.accounts.stake_nft_info.nft_mint,
+                days,
+                amount: credit.amount,
+                settled_amount: credit.settled_amount,
+                epoch_no: ctx.accounts.epoch_state.epoch_no,
+                day_of_epoch: ctx.accounts.stake_info.day_of_epoch,
+            });
+
         } else {
             // return Err(StakeError::NoStaker.into());
         }
@@ -364,6 +1058,7 @@ pub mod token_stake_model {
 
     pub fn process_update_day_of_epoch(
         ctx: Context<UpdateDayEpoch>,
+        treasury_nonce: u8,
     ) -> Result<()> {
         if ctx.accounts.stake_info.day_of_epoch > EPOCH_DAYS {
             return Err(error!(StakeError::WrongEpochDay));
@@ -377,49 +1072,615 @@ pub mod token_stake_model {
         if ctx.accounts.epoch_state.epoch_no > TOTAL_EPOCH {
             return Err(error!(StakeError::EpochEnd));
         }
+        if ctx.accounts.epoch_state.is_sunset {
+            return Err(error!(StakeError::PoolSunset));
+        }
 
         let clock = clock::Clock::get().unwrap();
-        let time_range = (clock.unix_timestamp.checked_sub(ctx.accounts.epoch_state.epoch_start_time).unwrap()) % 86400;
-        if time_range > 60 {
-            return Err(error!(StakeError::NoDaily));
-        }
 
-        if ctx.accounts.stake_info.day_of_epoch == EPOCH_DAYS - 1 {
-            ctx.accounts.stake_info.day_of_epoch = 0;
-        } else {
-            ctx.accounts.stake_info.day_of_epoch += 1;
+        // the day is derived from the clock, so one call catches up every
+        // missed day. process_daily_reward credits all of them
+        let days_passed = rewards::days_passed(ctx.accounts.epoch_state.epoch_start_time, clock.unix_timestamp);
+        let day_of_epoch = days_passed % EPOCH_DAYS;
+
+        // nothing is due, so there is nothing to pay for
+        if days_passed == ctx.accounts.stake_info.days_passed() {
+            return Ok(());
         }
+        ctx.accounts.stake_info.day_of_epoch = day_of_epoch;
+        ctx.accounts.stake_info.days_passed = days_passed;
+
+        let crank_fee = pay_crank_fee(
+            &ctx.accounts.treasury_account,
+            &ctx.accounts.crank_account.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            &ctx.accounts.epoch_state,
+            treasury_nonce,
+        )?;
 
+        emit!(DayAdvanceEvent {
+            crank_account: ctx.accounts.crank_account.key(),
+            crank_fee,
+            epoch_no: ctx.accounts.epoch_state.epoch_no,
+            day_of_epoch,
+        });
         Ok(())
     }
 
     pub fn process_restart_epoch(
         ctx: Context<RestartEpoch>,
     ) -> Result<()> {
+        if ctx.accounts.epoch_state.is_sunset {
+            return Err(error!(StakeError::PoolSunset));
+        }
         let amount = **ctx.accounts.treasury_account.lamports.borrow();
         let clock = clock::Clock::get().unwrap();
 
         ctx.accounts.epoch_state.is_initial = true;
         ctx.accounts.epoch_state.epoch_no = 1;
         ctx.accounts.epoch_state.epoch_start_time = clock.unix_timestamp;
-        ctx.accounts.epoch_state.cur_epoch_reward_per_day = amount.checked_div(EPOCH_DAYS as u64).unwrap();
+        ctx.accounts.epoch_state.cur_epoch_reward_per_day = rewards::reward_per_day(amount);
         ctx.accounts.epoch_state.epoch_bonus = 0;
+        ctx.accounts.epoch_state.epoch_credited = 0;
 
         ctx.accounts.stake_info.is_initial = true;
         ctx.accounts.stake_info.day_of_epoch = 0;
+        ctx.accounts.stake_info.days_passed = 0;
+        ctx.accounts.stake_info.credited_nfts = 0;
+
+        emit!(EpochRestartEvent {
+            treasury_amount: amount,
+            reward_per_day: ctx.accounts.epoch_state.cur_epoch_reward_per_day,
+            epoch_start_time: ctx.accounts.epoch_state.epoch_start_time,
+            epoch_no: ctx.accounts.epoch_state.epoch_no,
+        });
         Ok(())
     }
 
-    // pub fn back_treasury (
-    //     ctx: Context<BackTreasury>,
-    //     treasury_nonce: u8
-    // ) -> Result<()> {
-    //     let dest_starting_lamports = ctx.accounts.admin_account.lamports();
+    pub fn get_user_rewards(
+        ctx: Context<GetUserRewards>,
+    ) -> Result<()> {
+        let epoch_state = &ctx.accounts.epoch_state;
+        let stake_info = &ctx.accounts.stake_info;
+        let stake_user = &ctx.accounts.stake_user;
+
+        let mut user_rewards = UserRewards {
+            claimable_amount: stake_user.reward_amount,
+            pending_amount: stake_user.pending_amount,
+            staked_count: stake_user.staked_count,
+            daily_amount: 0,
+            projected_pending_amount: stake_user.pending_amount,
+        };
 
-    //     let account = ctx.accounts.treasury_account.to_account_info();
-    //     **ctx.accounts.admin_account.lamports.borrow_mut() = dest_starting_lamports
-    //         .checked_add(account.lamports())
-    //         .unwrap();
+        if epoch_state.is_sunset {
+            // the final epoch is settled on claim
+            user_rewards.claimable_amount = rewards::add(user_rewards.claimable_amount, stake_user.pending_amount)?;
+            user_rewards.pending_amount = 0;
+            user_rewards.projected_pending_amount = 0;
+        } else if stake_info.total_stakers > 0 && epoch_state.epoch_no <= TOTAL_EPOCH {
+            user_rewards.daily_amount = rewards::weighted_reward(epoch_state.cur_epoch_reward_per_day, stake_user.staked_weight(), stake_info.total_weight())?;
+            user_rewards.projected_pending_amount = rewards::projected_pending_amount(stake_user.pending_amount, user_rewards.daily_amount, stake_info.day_of_epoch)?;
+        }
+
+        set_return_data(&user_rewards.try_to_vec()?);
+        Ok(())
+    }
+
+    pub fn get_pool_stats(
+        ctx: Context<GetPoolStats>,
+    ) -> Result<()> {
+        let epoch_state = &ctx.accounts.epoch_state;
+        let stake_info = &ctx.accounts.stake_info;
+
+        let stats = PoolStats {
+            epoch_no: epoch_state.epoch_no,
+            day_of_epoch: stake_info.day_of_epoch,
+            epoch_start_time: epoch_state.epoch_start_time,
+            total_stakers: stake_info.total_stakers,
+            cur_epoch_reward_per_day: epoch_state.cur_epoch_reward_per_day,
+            reward_per_nft: rewards::weighted_reward(epoch_state.cur_epoch_reward_per_day, 1, stake_info.total_weight())?,
+            epoch_bonus: epoch_state.epoch_bonus,
+            remain_reward: epoch_state.remain_reward,
+            total_owed: epoch_state.total_owed,
+            treasury_amount: ctx.accounts.treasury_account.lamports(),
+            is_sunset: epoch_state.is_sunset,
+            total_weight: stake_info.total_weight(),
+        };
+
+        set_return_data(&stats.try_to_vec()?);
+        Ok(())
+    }
+
+    // nft_value: the value of one nft in lamports, e.g. the floor price
+    pub fn get_estimated_apr(
+        ctx: Context<GetPoolStats>,
+        nft_value: u64,
+    ) -> Result<()> {
+        let epoch_state = &ctx.accounts.epoch_state;
+        let stake_info = &ctx.accounts.stake_info;
+
+        // nothing is credited anymore after the sunset
+        let apr = if epoch_state.is_sunset {
+            EstimatedApr::default()
+        } else {
+            rewards::estimated_apr(epoch_state.cur_epoch_reward_per_day, stake_info.total_weight(), nft_value)?
+        };
+
+        set_return_data(&apr.try_to_vec()?);
+        Ok(())
+    }
+
+    pub fn process_sunset_pool(
+        ctx: Context<SunsetPool>,
+    ) -> Result<()> {
+        if !ctx.accounts.epoch_state.is_initial {
+            return Err(ProgramError::UninitializedAccount.into());
+        }
+        if ctx.accounts.epoch_state.is_sunset {
+            return Err(error!(StakeError::PoolSunset));
+        }
+        let clock = clock::Clock::get().unwrap();
+
+        ctx.accounts.epoch_state.is_sunset = true;
+        ctx.accounts.epoch_state.sunset_time = clock.unix_timestamp;
+
+        // the days up to the sunset are still credited, split by the weight
+        // staked at the sunset whoever unstakes after it
+        if ctx.accounts.epoch_state.epoch_no <= TOTAL_EPOCH {
+            let days_passed = rewards::days_passed(ctx.accounts.epoch_state.epoch_start_time, clock.unix_timestamp);
+            ctx.accounts.stake_info.days_passed = days_passed;
+            ctx.accounts.stake_info.day_of_epoch = days_passed % EPOCH_DAYS;
+        }
+        ctx.accounts.stake_info.total_weight = ctx.accounts.stake_info.total_weight();
+
+        emit!(SunsetEvent {
+            sunset_time: clock.unix_timestamp,
+            total_owed: ctx.accounts.epoch_state.total_owed,
+            epoch_no: ctx.accounts.epoch_state.epoch_no,
+        });
+        Ok(())
+    }
+
+    pub fn process_reclaim_treasury(
+        ctx: Context<ReclaimTreasury>,
+        treasury_nonce: u8,
+    ) -> Result<()> {
+        if !ctx.accounts.epoch_state.is_sunset {
+            return Err(error!(StakeError::PoolNotSunset));
+        }
+        let clock = clock::Clock::get().unwrap();
+
+        if !rewards::sunset_grace_period_over(ctx.accounts.epoch_state.sunset_time, clock.unix_timestamp)? {
+            return Err(error!(StakeError::SunsetGracePeriod));
+        }
+        // what v1 users are owed is only counted once they are migrated
+        if ctx.accounts.stake_info.v1_stakers > 0 {
+            return Err(error!(StakeError::MigrationPending));
+        }
+
+        // everything credited to the users stays in the treasury, and the treasury
+        // must stay rent exempt while anything is still owed
+        let treasury_amount = **ctx.accounts.treasury_account.lamports.borrow();
+        let reclaim_amount = rewards::reclaim_amount(treasury_amount, ctx.accounts.epoch_state.total_owed, Rent::get()?.minimum_balance(0))?;
+
+        if reclaim_amount > 0 {
+            invoke_signed(
+                &system_instruction::transfer(
+                    ctx.accounts.treasury_account.key,
+                    ctx.accounts.bank_account.key,
+                    reclaim_amount,
+                ),
+                &[
+                    ctx.accounts.treasury_account.clone(),
+                    ctx.accounts.bank_account.to_account_info().clone(),
+                    ctx.accounts.system_program.to_account_info().clone(),
+                ],
+                &[&[b"epoch-treasury", &[treasury_nonce]]],
+            )?;
+        }
+
+        emit!(ReclaimTreasuryEvent {
+            amount: reclaim_amount,
+            total_owed: ctx.accounts.epoch_state.total_owed,
+        });
+        Ok(())
+    }
+
+    // once the grace period is over the bank returns the nfts left in the pool
+    // to their owners, with what they are owed still to claim
+    pub fn process_force_unstake_nft(
+        ctx: Context<ForceUnstakeNft>,
+    ) -> Result<()> {
+        if !ctx.accounts.epoch_state.is_sunset {
+            return Err(error!(StakeError::PoolNotSunset));
+        }
+        let clock = clock::Clock::get().unwrap();
+
+        if !rewards::sunset_grace_period_over(ctx.accounts.epoch_state.sunset_time, clock.unix_timestamp)? {
+            return Err(error!(StakeError::SunsetGracePeriod));
+        }
+
+        release_nft(
+            ctx.program_id,
+            &ctx.accounts.token_program.to_account_info(),
+            &ctx.accounts.nft_vault_account.to_account_info(),
+            &ctx.accounts.user_nft_token_account.to_account_info(),
+            &ctx.accounts.vault_auth,
+            &ctx.accounts.user_account,
+            &ctx.accounts.stake_info_account,
+            &ctx.accounts.registry,
+        )?;
+
+        credit_sunset_days(&mut ctx.accounts.epoch_state, &ctx.accounts.stake_info, &ctx.accounts.stake_info_account, &mut ctx.accounts.stake_user)?;
+        // the final epoch is settled, so the pending reward stays with the user
+        ctx.accounts.stake_user.reward_amount = rewards::add(ctx.accounts.stake_user.reward_amount, ctx.accounts.stake_user.pending_amount)?;
+        ctx.accounts.stake_user.pending_amount = 0;
+
+        // the pool weight stays as it was at the sunset
+        let weight = ctx.accounts.stake_info_account.weight();
+        ctx.accounts.stake_user.sub_weight(weight);
+        ctx.accounts.stake_info.total_stakers -= 1;
+        if ctx.accounts.stake_info_account.is_epoch_credited(ctx.accounts.epoch_state.epoch_start_time) {
+            ctx.accounts.stake_info.credited_nfts -= 1;
+        }
+        ctx.accounts.stake_user.staked_count = ctx.accounts.stake_user.staked_count.saturating_sub(1);
+
+        emit!(UnstakeEvent {
+            user_account: ctx.accounts.user_account.key(),
+            nft_mint: ctx.accounts.nft_mint.key(),
+            forfeited_amount: 0,
+            total_stakers: ctx.accounts.stake_info.total_stakers,
+            epoch_no: ctx.accounts.epoch_state.epoch_no,
+            day_of_epoch: ctx.accounts.stake_info.day_of_epoch,
+        });
+        Ok(())
+    }
+
+    // once the grace period is over the bank pays out what a user left
+    // unclaimed, so that nothing is owed when the pool closes
+    pub fn process_settle_stake_user(
+        ctx: Context<SettleStakeUser>,
+        treasury_nonce: u8,
+    ) -> Result<()> {
+        if !ctx.accounts.epoch_state.is_sunset {
+            return Err(error!(StakeError::PoolNotSunset));
+        }
+        let clock = clock::Clock::get().unwrap();
+
+        if !rewards::sunset_grace_period_over(ctx.accounts.epoch_state.sunset_time, clock.unix_timestamp)? {
+            return Err(error!(StakeError::SunsetGracePeriod));
+        }
+
+        pay_reward(
+            &ctx.accounts.treasury_account,
+            &ctx.accounts.user_account,
+            &ctx.accounts.system_program.to_account_info(),
+            &mut ctx.accounts.stake_user,
+            &mut ctx.accounts.epoch_state,
+            ctx.accounts.stake_info.day_of_epoch,
+            treasury_nonce,
+        )
+    }
+
+    // once the grace period is over the bank closes a distribution, the leaves
+    // never claimed are no longer owed
+    pub fn process_close_epoch_distribution(
+        ctx: Context<CloseEpochDistribution>,
+        epoch_no: u8,
+    ) -> Result<()> {
+        if !ctx.accounts.epoch_state.is_sunset {
+            return Err(error!(StakeError::PoolNotSunset));
+        }
+        let clock = clock::Clock::get().unwrap();
+
+        if !rewards::sunset_grace_period_over(ctx.accounts.epoch_state.sunset_time, clock.unix_timestamp)? {
+            return Err(error!(StakeError::SunsetGracePeriod));
+        }
+
+        let unclaimed_amount = ctx.accounts.distribution.total_amount.saturating_sub(ctx.accounts.distribution.total_claimed);
+        ctx.accounts.epoch_state.total_owed = ctx.accounts.epoch_state.total_owed.saturating_sub(unclaimed_amount);
+
+        emit!(DistributionCloseEvent {
+            distribution: ctx.accounts.distribution.key(),
+            unclaimed_amount,
+            total_owed: ctx.accounts.epoch_state.total_owed,
+            epoch_no,
+        });
+        Ok(())
+    }
+
+    pub fn process_close_pool(
+        ctx: Context<ClosePool>,
+    ) -> Result<()> {
+        if !ctx.accounts.epoch_state.is_sunset {
+            return Err(error!(StakeError::PoolNotSunset));
+        }
+        let clock = clock::Clock::get().unwrap();
+
+        if !rewards::sunset_grace_period_over(ctx.accounts.epoch_state.sunset_time, clock.unix_timestamp)? {
+            return Err(error!(StakeError::SunsetGracePeriod));
+        }
+        // the users claim and unstake, or the bank does it for them
+        if ctx.accounts.stake_info.total_stakers > 0 || ctx.accounts.epoch_state.total_owed > 0 {
+            return Err(error!(StakeError::PoolNotEmpty));
+        }
+
+        emit!(PoolCloseEvent {
+            bank_account: ctx.accounts.bank_account.key(),
+            epoch_no: ctx.accounts.epoch_state.epoch_no,
+        });
+        Ok(())
+    }
+
+    pub fn process_migrate_epoch_state(
+        ctx: Context<MigrateEpochState>,
+    ) -> Result<()> {
+        let epoch_state_info = ctx.accounts.epoch_state.to_account_info();
+        let old = migration::load_v1::<EpochStateV1>(&epoch_state_info, EpochState::discriminator())?;
+
+        migration::realloc_account(
+            &epoch_state_info,
+            &ctx.accounts.admin_account.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            8 + EpochState::LEN,
+        )?;
+
+        // v1 pools have no bank account on chain, so it comes from the upgrade
+        // authority: the admin of the whitelist the pool already stakes
+        // against. What is owed adds up as the stake users are migrated
+        let epoch_state = EpochState {
+            version: EpochState::VERSION,
+            is_initial: old.is_initial,
+            epoch_no: old.epoch_no,
+            epoch_start_time: old.epoch_start_time,
+            cur_epoch_reward_per_day: old.cur_epoch_reward_per_day,
+            epoch_bonus: old.epoch_bonus,
+            remain_reward: old.remain_reward,
+            bank_account: ctx.accounts.bank_account.key(),
+            is_sunset: false,
+            sunset_time: 0,
+            total_owed: 0,
+            crank_fee: 0,
+            distribution_mode: DISTRIBUTION_DAILY,
+            epoch_credited: 0,
+            reserved: [0; 15],
+        };
+        migration::store(&epoch_state_info, &epoch_state)?;
+
+        emit!(MigrationEvent {
+            account: epoch_state_info.key(),
+            version: EpochState::VERSION,
+        });
+        Ok(())
+    }
+
+    pub fn process_migrate_stake_info(
+        ctx: Context<MigrateStakeInfo>,
+    ) -> Result<()> {
+        let stake_info_info = ctx.accounts.stake_info.to_account_info();
+        let old = migration::load_v1::<StakeInfoStateV1>(&stake_info_info, StakeInfoState::discriminator())?;
+
+        migration::realloc_account(
+            &stake_info_info,
+            &ctx.accounts.admin_account.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            8 + StakeInfoState::LEN,
+        )?;
+
+        let stake_info = StakeInfoState {
+            version: StakeInfoState::VERSION,
+            is_initial: old.is_initial,
+            total_stakers: old.total_stakers,
+            day_of_epoch: old.day_of_epoch,
+            registry_pages: 0,
+            total_weight: 0,
+            days_passed: old.day_of_epoch,
+            credited_nfts: 0,
+            // every v1 nft is counted again by the migration of its stake user
+            v1_stakers: old.total_stakers,
+            reserved: [0; 17],
+        };
+        migration::store(&stake_info_info, &stake_info)?;
+
+        emit!(MigrationEvent {
+            account: stake_info_info.key(),
+            version: StakeInfoState::VERSION,
+        });
+        Ok(())
+    }
+
+    pub fn process_migrate_merkle(
+        ctx: Context<MigrateMerkle>,
+    ) -> Result<()> {
+        let merkle_info = ctx.accounts.merkle.to_account_info();
+
+        if !migration::is_v1::<MerkleV1>(&merkle_info, Merkle::discriminator()) {
+            // v2 roots read the Merkle whitelist mode once grown
+            migration::realloc_versioned(
+                &merkle_info,
+                &ctx.accounts.admin_account.to_account_info(),
+                &ctx.accounts.system_program.to_account_info(),
+                8 + Merkle::LEN,
+            )?;
+
+            let mut merkle = Merkle::try_deserialize(&mut &merkle_info.data.borrow()[..])?;
+            if merkle.admin_account != ctx.accounts.admin_account.key() {
+                return Err(error!(StakeError::WrongOwner));
+            }
+            merkle.version = Merkle::VERSION;
+            migration::store(&merkle_info, &merkle)?;
+
+            emit!(MigrationEvent {
+                account: merkle_info.key(),
+                version: Merkle::VERSION,
+            });
+            return Ok(());
+        }
+        let old = migration::load_v1::<MerkleV1>(&merkle_info, Merkle::discriminator())?;
+
+        if old.admin_account != ctx.accounts.admin_account.key() {
+            return Err(error!(StakeError::WrongOwner));
+        }
+
+        migration::realloc_account(
+            &merkle_info,
+            &ctx.accounts.admin_account.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            8 + Merkle::LEN,
+        )?;
+
+        let merkle = Merkle {
+            version: Merkle::VERSION,
+            root: old.root,
+            admin_account: old.admin_account,
+            is_init: old.is_init,
+            is_hash_v2: false,
+            max_depth: 0,
+            is_weighted: false,
+            whitelist_mode: WHITELIST_MERKLE,
+            collection_mint: Pubkey::default(),
+            creator: Pubkey::default(),
+            update_authority: Pubkey::default(),
+            reserved: [0; 64],
+        };
+        migration::store(&merkle_info, &merkle)?;
+
+        emit!(MigrationEvent {
+            account: merkle_info.key(),
+            version: Merkle::VERSION,
+        });
+        Ok(())
+    }
+
+    pub fn process_migrate_stake_nft_info(
+        ctx: Context<MigrateStakeNftInfo>,
+    ) -> Result<()> {
+        let stake_info_account_info = ctx.accounts.stake_info_account.to_account_info();
+        let old = migration::load_v1::<StakeNftInfoStateV1>(&stake_info_account_info, StakeNftInfoState::discriminator())?;
+
+        if old.user_account != ctx.accounts.user_account.key() {
+            return Err(error!(StakeError::WrongOwner));
+        }
+
+        migration::realloc_account(
+            &stake_info_account_info,
+            &ctx.accounts.user_account.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            8 + StakeNftInfoState::LEN,
+        )?;
+
+        let stake_info_account = StakeNftInfoState {
+            version: StakeNftInfoState::VERSION,
+            user_account: old.user_account,
+            nft_mint: old.nft_mint,
+            stake_time: old.stake_time,
+            registry_page: 0,
+            is_registered: false,
+            weight: 0,
+            credited_epoch_start: 0,
+            credited_days: 0,
+            reserved: [0; 18],
+        };
+        migration::store(&stake_info_account_info, &stake_info_account)?;
+
+        emit!(MigrationEvent {
+            account: stake_info_account_info.key(),
+            version: StakeNftInfoState::VERSION,
+        });
+        Ok(())
+    }
+
+    // remaining_accounts: every StakeNftInfoState of the user, to count the staked nfts of a v1 user.
+    // The program cannot list them, so the bank does, and close_stake_user trusts the count
+    pub fn process_migrate_stake_user<'info>(
+        ctx: Context<'_, '_, 'info, 'info, MigrateStakeUser<'info>>,
+    ) -> Result<()> {
+        let stake_user_info = ctx.accounts.stake_user.to_account_info();
+
+        if !migration::is_v1::<StakeUserStateV1>(&stake_user_info, StakeUserState::discriminator()) {
+            migration::realloc_versioned(
+                &stake_user_info,
+                &ctx.accounts.bank_account.to_account_info(),
+                &ctx.accounts.system_program.to_account_info(),
+                8 + StakeUserState::LEN,
+            )?;
+
+            let mut stake_user = StakeUserState::try_deserialize(&mut &stake_user_info.data.borrow()[..])?;
+            stake_user.version = StakeUserState::VERSION;
+            migration::store(&stake_user_info, &stake_user)?;
+
+            emit!(MigrationEvent {
+                account: stake_user_info.key(),
+                version: StakeUserState::VERSION,
+            });
+            return Ok(());
+        }
+        let old = migration::load_v1::<StakeUserStateV1>(&stake_user_info, StakeUserState::discriminator())?;
+
+        let mut staked_count: u16 = 0;
+        for (i, account) in ctx.remaining_accounts.iter().enumerate() {
+            if ctx.remaining_accounts[..i].iter().any(|prev| prev.key == account.key) {
+                return Err(error!(StakeError::WrongOwner));
+            }
+            let user_account = if migration::is_v1::<StakeNftInfoStateV1>(account, StakeNftInfoState::discriminator()) {
+                migration::load_v1::<StakeNftInfoStateV1>(account, StakeNftInfoState::discriminator())?.user_account
+            } else {
+                Account::<StakeNftInfoState>::try_from(account)?.user_account
+            };
+            if user_account != ctx.accounts.user_account.key() {
+                return Err(error!(StakeError::WrongOwner));
+            }
+            staked_count += 1;
+        }
+        // the records of every user add up to the v1 nfts only if no user
+        // left one out
+        ctx.accounts.stake_info.v1_stakers = ctx.accounts.stake_info.v1_stakers.checked_sub(staked_count).ok_or(error!(StakeError::WrongOwner))?;
+        ctx.accounts.epoch_state.total_owed = rewards::add(
+            ctx.accounts.epoch_state.total_owed,
+            rewards::add(old.reward_amount, old.pending_amount)?,
+        )?;
+
+        migration::realloc_account(
+            &stake_user_info,
+            &ctx.accounts.bank_account.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            8 + StakeUserState::LEN,
+        )?;
+
+        let stake_user = StakeUserState {
+            version: StakeUserState::VERSION,
+            is_initial: old.is_initial,
+            user_account: old.user_account,
+            reward_amount: old.reward_amount,
+            pending_amount: old.pending_amount,
+            staked_count,
+            total_claimed: 0,
+            total_forfeited: 0,
+            total_credited: 0,
+            first_stake_time: 0,
+            last_claim_time: 0,
+            staked_weight: 0,
+            reserved: [0; 24],
+        };
+        migration::store(&stake_user_info, &stake_user)?;
+
+        emit!(MigrationEvent {
+            account: stake_user_info.key(),
+            version: StakeUserState::VERSION,
+        });
+        Ok(())
+    }
+
+    // pub fn back_treasury (
+    //     ctx: Context<BackTreasury>,
+    //     treasury_nonce: u8
+    // ) -> Result<()> {
+    //     let dest_starting_lamports = ctx.accounts.admin_account.lamports();
+
+    //     let account = ctx.accounts.treasury_account.to_account_info();
+    //     **ctx.accounts.admin_account.lamports.borrow_mut() = dest_starting_lamports
+    //         .checked_add(account.lamports())
+    //         .unwrap();
     //     **account.lamports.borrow_mut() = 0;
 
     //     let mut data = account.try_borrow_mut_data()?;
@@ -434,6 +1695,288 @@ pub mod token_stake_model {
     // }
 }
 
+// books the ended epoch and every missed one in history and moves the epoch
+// forward, keeping the start time aligned to the original schedule. Returns
+// the number of epochs passed
+fn roll_epochs(
+    epoch_state: &mut EpochState,
+    stake_info: &mut StakeInfoState,
+    history: &mut EpochHistory,
+    now: i64,
+) -> Result<u8> {
+    let roll = rewards::roll_epochs(epoch_state.epoch_no, epoch_state.epoch_start_time, now)?;
+    // the days of the ended epoch can not be credited once it rolls
+    if epoch_state.distribution_mode != DISTRIBUTION_MERKLE && stake_info.credited_nfts < stake_info.total_stakers {
+        return Err(error!(StakeError::EpochNotCredited));
+    }
+
+    for i in 0..roll.epochs_passed {
+        if history.records.len() >= TOTAL_EPOCH as usize {
+            break;
+        }
+        // only the ended epoch was running, the missed ones are booked empty
+        history.records.push(EpochRecord {
+            epoch_no: epoch_state.epoch_no + i,
+            epoch_start_time: rewards::epoch_start_after(epoch_state.epoch_start_time, i)?,
+            reward_per_day: if i == 0 { epoch_state.cur_epoch_reward_per_day } else { 0 },
+            total_stakers: stake_info.total_stakers,
+            is_empty: i > 0,
+        });
+    }
+
+    epoch_state.epoch_no = roll.epoch_no;
+    epoch_state.epoch_start_time = roll.epoch_start_time;
+    epoch_state.epoch_credited = 0;
+    stake_info.day_of_epoch = 0;
+    stake_info.days_passed = 0;
+    stake_info.credited_nfts = 0;
+    Ok(roll.epochs_passed)
+}
+
+fn set_merkle_root(merkle: &mut Account<Merkle>, root: [u8; 32], is_hash_v2: bool, max_depth: u8, is_weighted: bool) {
+    merkle.root = root;
+    merkle.is_hash_v2 = is_hash_v2;
+    merkle.max_depth = max_depth;
+    merkle.is_weighted = is_weighted;
+    merkle.whitelist_mode = WHITELIST_MERKLE;
+
+    emit!(MerkleRootEvent {
+        merkle: merkle.key(),
+        admin_account: merkle.admin_account,
+        root,
+        is_hash_v2,
+        max_depth,
+        is_weighted,
+    });
+}
+
+fn emit_whitelist_mode(merkle: &Account<Merkle>) {
+    emit!(WhitelistModeEvent {
+        merkle: merkle.key(),
+        admin_account: merkle.admin_account,
+        whitelist_mode: merkle.whitelist_mode,
+        collection_mint: merkle.collection_mint,
+        creator: merkle.creator,
+        update_authority: merkle.update_authority,
+    });
+}
+
+// the days the crank passed that `stake_nft_info` was not credited for yet,
+// and its reward for each of them
+fn nft_days_due(epoch_state: &EpochState, stake_info: &StakeInfoState, stake_nft_info: &StakeNftInfoState) -> Result<(u8, u64)> {
+    let reward_amount = rewards::weighted_reward(epoch_state.cur_epoch_reward_per_day, stake_nft_info.weight(), stake_info.total_weight())?;
+    let days_passed = stake_info.days_passed();
+    let days = rewards::days_due(stake_nft_info.credited_days(epoch_state.epoch_start_time, days_passed), days_passed);
+    Ok((days, reward_amount))
+}
+
+// an nft leaving a sunset pool is paid the days up to the sunset the crank
+// did not credit, straight to the claimable amount
+fn credit_sunset_days(
+    epoch_state: &mut EpochState,
+    stake_info: &StakeInfoState,
+    stake_nft_info: &StakeNftInfoState,
+    stake_user: &mut StakeUserState,
+) -> Result<()> {
+    if epoch_state.distribution_mode == DISTRIBUTION_MERKLE || epoch_state.epoch_no > TOTAL_EPOCH {
+        return Ok(());
+    }
+    let (days, reward_amount) = nft_days_due(epoch_state, stake_info, stake_nft_info)?;
+    let amount = rewards::mul(reward_amount, days as u64)?;
+    stake_user.reward_amount = rewards::add(stake_user.reward_amount, amount)?;
+    stake_user.total_credited = rewards::add(stake_user.total_credited, amount)?;
+    epoch_state.total_owed = rewards::add(epoch_state.total_owed, amount)?;
+    epoch_state.epoch_credited = rewards::add(epoch_state.epoch_credited, amount)?;
+    Ok(())
+}
+
+// splits the remaining accounts of a batch into the `group_len` accounts of every nft
+fn batch_groups<'a, 'info>(remaining_accounts: &'a [AccountInfo<'info>], group_len: usize) -> Result<Vec<&'a [AccountInfo<'info>]>> {
+    let groups = remaining_accounts.chunks_exact(group_len);
+    if remaining_accounts.is_empty() || !groups.remainder().is_empty() {
+        return Err(error!(StakeError::WrongBatchAccounts));
+    }
+    Ok(groups.collect())
+}
+
+// creates the program derived `account` with `space` bytes owned by `owner`,
+// the rent paid by `payer`. Lamports sent to the address beforehand are kept
+fn create_pda_account<'info>(
+    payer: &AccountInfo<'info>,
+    account: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    space: usize,
+    owner: &Pubkey,
+    seeds: &[&[u8]],
+) -> Result<()> {
+    let rent_amount = Rent::get()?.minimum_balance(space);
+    let lamports = account.lamports();
+    if lamports == 0 {
+        invoke_signed(
+            &system_instruction::create_account(payer.key, account.key, rent_amount, space as u64, owner),
+            &[payer.clone(), account.clone(), system_program.clone()],
+            &[seeds],
+        )?;
+        return Ok(());
+    }
+
+    if rent_amount > lamports {
+        invoke(
+            &system_instruction::transfer(payer.key, account.key, rent_amount - lamports),
+            &[payer.clone(), account.clone(), system_program.clone()],
+        )?;
+    }
+    invoke_signed(
+        &system_instruction::allocate(account.key, space as u64),
+        &[account.clone(), system_program.clone()],
+        &[seeds],
+    )?;
+    invoke_signed(
+        &system_instruction::assign(account.key, owner),
+        &[account.clone(), system_program.clone()],
+        &[seeds],
+    )?;
+    Ok(())
+}
+
+// returns the staked nft from its vault to `user_nft_token_account`, closes
+// the vault to `user_account` and unlists the nft
+#[allow(clippy::too_many_arguments)]
+fn release_nft<'info>(
+    program_id: &Pubkey,
+    token_program: &AccountInfo<'info>,
+    nft_vault_account: &AccountInfo<'info>,
+    user_nft_token_account: &AccountInfo<'info>,
+    vault_auth: &AccountInfo<'info>,
+    user_account: &AccountInfo<'info>,
+    stake_record: &StakeNftInfoState,
+    registry: &AccountLoader<'info, StakeRegistry>,
+) -> Result<()> {
+    let (_vault_authority, vault_authority_bump) =
+        Pubkey::find_program_address(&[b"vault-stake-auth"], program_id);
+    let authority_seeds = &[&b"vault-stake-auth"[..], &[vault_authority_bump]];
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            token_program.clone(),
+            Transfer {
+                from: nft_vault_account.clone(),
+                to: user_nft_token_account.clone(),
+                authority: vault_auth.clone(),
+            },
+            &[&authority_seeds[..]],
+        ),
+        1,
+    )?;
+
+    token::close_account(CpiContext::new_with_signer(
+        token_program.clone(),
+        CloseAccount {
+            account: nft_vault_account.clone(),
+            destination: user_account.clone(),
+            authority: vault_auth.clone(),
+        },
+        &[&authority_seeds[..]],
+    ))?;
+
+    // nfts staked before the registry existed are not listed
+    if stake_record.is_registered {
+        let mut registry = registry.load_mut()?;
+        if registry.page != stake_record.registry_page {
+            return Err(error!(StakeError::WrongRegistryPage));
+        }
+        registry.swap_remove(stake_record.nft_mint)?;
+    }
+    Ok(())
+}
+
+// pays everything `stake_user` can claim from the treasury to `user_account`.
+// After the sunset the pending reward is claimable too
+fn pay_reward<'info>(
+    treasury_account: &AccountInfo<'info>,
+    user_account: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    stake_user: &mut StakeUserState,
+    epoch_state: &mut EpochState,
+    day_of_epoch: u8,
+    treasury_nonce: u8,
+) -> Result<()> {
+    if epoch_state.is_sunset {
+        stake_user.reward_amount = rewards::add(stake_user.reward_amount, stake_user.pending_amount)?;
+        stake_user.pending_amount = 0;
+    }
+
+    let claim_amount = stake_user.reward_amount;
+    if claim_amount == 0 {
+        return Ok(());
+    }
+
+    invoke_signed(
+        &system_instruction::transfer(
+            treasury_account.key,
+            user_account.key,
+            claim_amount,
+        ),
+        &[
+            treasury_account.clone(),
+            user_account.clone(),
+            system_program.clone(),
+        ],
+        &[&[b"epoch-treasury", &[treasury_nonce]]],
+    )?;
+
+    let clock = clock::Clock::get().unwrap();
+
+    stake_user.reward_amount = 0;
+    stake_user.total_claimed = rewards::add(stake_user.total_claimed, claim_amount)?;
+    stake_user.last_claim_time = clock.unix_timestamp;
+    epoch_state.total_owed = epoch_state.total_owed.saturating_sub(claim_amount);
+
+    emit!(ClaimEvent {
+        user_account: user_account.key(),
+        amount: claim_amount,
+        epoch_no: epoch_state.epoch_no,
+        day_of_epoch,
+    });
+    Ok(())
+}
+
+// pays the crank fee from the treasury, unless it would touch what is owed to the users,
+// what the running epoch still credits or what waits for the next epoch. Returns the fee paid
+fn pay_crank_fee<'info>(
+    treasury_account: &AccountInfo<'info>,
+    crank_account: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    epoch_state: &EpochState,
+    treasury_nonce: u8,
+) -> Result<u64> {
+    let reserved_amount = rewards::treasury_reserve(
+        epoch_state.total_owed,
+        epoch_state.cur_epoch_reward_per_day,
+        epoch_state.epoch_credited,
+        epoch_state.epoch_bonus,
+        epoch_state.remain_reward,
+    )?;
+    let crank_fee = rewards::crank_fee_due(treasury_account.lamports(), reserved_amount, Rent::get()?.minimum_balance(0), epoch_state.crank_fee)?;
+    if crank_fee == 0 {
+        return Ok(0);
+    }
+
+    invoke_signed(
+        &system_instruction::transfer(
+            treasury_account.key,
+            crank_account.key,
+            crank_fee,
+        ),
+        &[
+            treasury_account.clone(),
+            crank_account.clone(),
+            system_program.clone(),
+        ],
+        &[&[b"epoch-treasury", &[treasury_nonce]]],
+    )?;
+    Ok(crank_fee)
+}
 
 #[derive(Accounts)]
 pub struct Initialize<'info> {
@@ -448,7 +1991,7 @@ pub struct Initialize<'info> {
         ],
         bump,
         payer = bank_account,
-        space = 8 + size_of::<EpochState>()
+        space = 8 + EpochState::LEN
     )]
     pub epoch_state: Box<Account<'info, EpochState>>,
     #[account(
@@ -458,7 +2001,7 @@ pub struct Initialize<'info> {
         ],
         bump,
         payer = bank_account,
-        space = 8 + size_of::<StakeInfoState>()
+        space = 8 + StakeInfoState::LEN
     )]
     pub stake_info: Box<Account<'info, StakeInfoState>>,
     
@@ -490,13 +2033,37 @@ pub struct StakeUser<'info> {
         ],
         bump,
         payer = user_account,
-        space = 8 + size_of::<StakeUserState>()
+        space = 8 + StakeUserState::LEN
     )]
     pub stake_user: Box<Account<'info, StakeUserState>>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
 }
 
+#[derive(Accounts)]
+pub struct CloseStakeUser<'info> {
+    #[account(mut)]
+    pub user_account: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"stake_user".as_ref(),
+            user_account.key().as_ref(),
+        ],
+        bump,
+        has_one = user_account,
+        close = user_account
+    )]
+    pub stake_user: Box<Account<'info, StakeUserState>>,
+    #[account(
+        seeds = [
+            b"stake-info".as_ref(),
+        ],
+        bump,
+    )]
+    pub stake_info: Box<Account<'info, StakeInfoState>>,
+}
+
 #[derive(Accounts)]
 pub struct EpochBonus<'info> {
     // The account which have the fee from other contracts fee + implementation fix fee
@@ -554,30 +2121,239 @@ pub struct StartEpoch<'info> {
         bump,
     )]
     pub stake_info: Box<Account<'info, StakeInfoState>>,
-    pub system_program: Program<'info, System>,
-}
-
-#[derive(Accounts)]
-pub struct InitializeMerkle<'info> {
-    #[account(mut)]
-    pub admin_account: Signer<'info>,
     #[account(
-        init,
+        mut,
         seeds = [
-            b"Epoch-Merkle-Whitelist".as_ref(),
-            b"Solluminati-NFT-List".as_ref(),
-            admin_account.key().to_bytes().as_ref()
+            b"epoch-history".as_ref(),
         ],
         bump,
-        payer = admin_account,
-        space = 8 + size_of::<Merkle>()
     )]
-    pub merkle: Box<Account<'info, Merkle>>,
+    pub epoch_history: Box<Account<'info, EpochHistory>>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct UpdateMerkle<'info> {
+pub struct CrankStartEpoch<'info> {
+    // anyone can start a due epoch and get the crank fee
+    #[account(mut)]
+    pub crank_account: Signer<'info>,
+        /// CHECK: Safe account
+    #[account(
+        mut,
+        seeds = [
+            b"epoch-treasury".as_ref(),
+        ],
+        bump,
+    )]
+    pub treasury_account: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"epoch-state".as_ref(),
+        ],
+        bump,
+    )]
+    pub epoch_state: Box<Account<'info, EpochState>>,
+    #[account(
+        mut,
+        seeds = [
+            b"stake-info".as_ref(),
+        ],
+        bump,
+    )]
+    pub stake_info: Box<Account<'info, StakeInfoState>>,
+    #[account(
+        mut,
+        seeds = [
+            b"epoch-history".as_ref(),
+        ],
+        bump,
+    )]
+    pub epoch_history: Box<Account<'info, EpochHistory>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetCrankFee<'info> {
+    #[account(mut)]
+    pub bank_account: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"epoch-state".as_ref(),
+        ],
+        bump,
+        has_one = bank_account
+    )]
+    pub epoch_state: Box<Account<'info, EpochState>>,
+}
+
+#[derive(Accounts)]
+pub struct SetDistributionMode<'info> {
+    #[account(mut)]
+    pub bank_account: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"epoch-state".as_ref(),
+        ],
+        bump,
+        has_one = bank_account
+    )]
+    pub epoch_state: Box<Account<'info, EpochState>>,
+}
+
+#[derive(Accounts)]
+#[instruction(epoch_no: u8, root: [u8; 32], total_amount: u64, num_nodes: u32)]
+pub struct PostEpochDistribution<'info> {
+    #[account(mut)]
+    pub bank_account: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"epoch-state".as_ref(),
+        ],
+        bump,
+        has_one = bank_account
+    )]
+    pub epoch_state: Box<Account<'info, EpochState>>,
+    #[account(
+        init,
+        seeds = [
+            b"epoch-distribution".as_ref(),
+            &[epoch_no],
+        ],
+        bump,
+        payer = bank_account,
+        space = 8 + EpochDistribution::LEN + EpochDistribution::bitmap_len(num_nodes)
+    )]
+    pub distribution: Box<Account<'info, EpochDistribution>>,
+        /// CHECK: Safe account
+    #[account(
+        seeds = [
+            b"epoch-treasury".as_ref(),
+        ],
+        bump,
+    )]
+    pub treasury_account: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(epoch_no: u8)]
+pub struct ClaimEpochDistribution<'info> {
+    #[account(mut)]
+    pub user_account: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"epoch-distribution".as_ref(),
+            &[epoch_no],
+        ],
+        bump,
+    )]
+    pub distribution: Box<Account<'info, EpochDistribution>>,
+        /// CHECK: Safe account
+    #[account(
+        mut,
+        seeds = [
+            b"epoch-treasury".as_ref(),
+        ],
+        bump,
+    )]
+    pub treasury_account: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"epoch-state".as_ref(),
+        ],
+        bump,
+    )]
+    pub epoch_state: Box<Account<'info, EpochState>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeEpochHistory<'info> {
+    #[account(mut)]
+    pub bank_account: Signer<'info>,
+    #[account(
+        seeds = [
+            b"epoch-state".as_ref(),
+        ],
+        bump,
+        has_one = bank_account
+    )]
+    pub epoch_state: Box<Account<'info, EpochState>>,
+    #[account(
+        init,
+        seeds = [
+            b"epoch-history".as_ref(),
+        ],
+        bump,
+        payer = bank_account,
+        space = 8 + EpochHistory::LEN
+    )]
+    pub epoch_history: Box<Account<'info, EpochHistory>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(page: u16)]
+pub struct InitializeRegistryPage<'info> {
+    #[account(mut)]
+    pub bank_account: Signer<'info>,
+    #[account(
+        seeds = [
+            b"epoch-state".as_ref(),
+        ],
+        bump,
+        has_one = bank_account
+    )]
+    pub epoch_state: Box<Account<'info, EpochState>>,
+    #[account(
+        mut,
+        seeds = [
+            b"stake-info".as_ref(),
+        ],
+        bump,
+    )]
+    pub stake_info: Box<Account<'info, StakeInfoState>>,
+    #[account(
+        init,
+        seeds = [
+            b"stake-registry".as_ref(),
+            page.to_le_bytes().as_ref(),
+        ],
+        bump,
+        payer = bank_account,
+        space = 8 + StakeRegistry::LEN
+    )]
+    pub registry: AccountLoader<'info, StakeRegistry>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeMerkle<'info> {
+    #[account(mut)]
+    pub admin_account: Signer<'info>,
+    #[account(
+        init,
+        seeds = [
+            b"Epoch-Merkle-Whitelist".as_ref(),
+            b"Solluminati-NFT-List".as_ref(),
+            admin_account.key().to_bytes().as_ref()
+        ],
+        bump,
+        payer = admin_account,
+        space = 8 + Merkle::LEN
+    )]
+    pub merkle: Box<Account<'info, Merkle>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateMerkle<'info> {
     #[account(mut)]
     pub admin_account: Signer<'info>,
     #[account(
@@ -612,7 +2388,7 @@ pub struct StakeNft<'info> {
         ],
         bump,
         payer = user_account,
-        space = 8 + size_of::<StakeNftInfoState>()
+        space = 8 + StakeNftInfoState::LEN
     )]
     pub stake_info_account: Box<Account<'info, StakeNftInfoState>>,
     #[account(
@@ -636,6 +2412,15 @@ pub struct StakeNft<'info> {
         bump,
     )]
     pub nft_authority: AccountInfo<'info>,
+    // the whitelist of the pool, not one posted by any other wallet
+    #[account(
+        seeds = [
+            b"Epoch-Merkle-Whitelist".as_ref(),
+            b"Solluminati-NFT-List".as_ref(),
+            epoch_state.bank_account.as_ref(),
+        ],
+        bump,
+    )]
     pub merkle: Box<Account<'info, Merkle>>,
     #[account(
         mut,
@@ -645,6 +2430,26 @@ pub struct StakeNft<'info> {
         bump,
     )]
     pub stake_info: Box<Account<'info, StakeInfoState>>,
+    #[account(
+        seeds = [
+            b"epoch-state".as_ref(),
+        ],
+        bump,
+    )]
+    pub epoch_state: Box<Account<'info, EpochState>>,
+    #[account(
+        mut,
+        seeds = [
+            b"stake_user".as_ref(),
+            user_account.key().as_ref(),
+        ],
+        bump,
+        has_one = user_account
+    )]
+    pub stake_user: Box<Account<'info, StakeUserState>>,
+    // any registry page with room left
+    #[account(mut)]
+    pub registry: AccountLoader<'info, StakeRegistry>,
     
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
@@ -667,22 +2472,450 @@ pub struct UnStakeNft<'info> {
     pub nft_mint: Box<Account<'info, Mint>>,
     #[account(
         mut,
+        seeds = [
+            b"vault-stake".as_ref(),
+            nft_mint.key().as_ref(),
+            user_account.key().as_ref(),
+        ],
+        bump,
+        constraint = nft_vault_account.owner == vault_auth.key(),
+        constraint = nft_vault_account.mint == nft_mint.key()
+    )]
+    pub nft_vault_account: Box<Account<'info, TokenAccount>>,
+    // created when the nft was staked, it stands for the whitelist proof
+    #[account(
+        mut, 
+        seeds = [
+            b"user-stake".as_ref(),
+            nft_mint.key().as_ref(),
+            user_account.key().as_ref(),
+        ],
+        bump,
+        has_one = user_account,
+        has_one = nft_mint,
+        close = user_account
+    )]
+    pub stake_info_account: Box<Account<'info, StakeNftInfoState>>,
+    /// CHECK: Safe account
+    #[account(
+        seeds = [
+            b"vault-stake-auth".as_ref(),
+        ],
+        bump,
+    )]
+    pub vault_auth: AccountInfo<'info>,
+    #[account(
+        mut,
+        has_one = user_account
+    )]
+    pub stake_user: Box<Account<'info, StakeUserState>>,
+    #[account(
+        mut,
+        seeds = [
+            b"epoch-state".as_ref(),
+        ],
+        bump,
+    )]
+    pub epoch_state: Box<Account<'info, EpochState>>,
+    #[account(
+        mut,
+        seeds = [
+            b"stake-info".as_ref(),
+        ],
+        bump,
+    )]
+    pub stake_info: Box<Account<'info, StakeInfoState>>,
+    // the registry page listing the nft, any page if it is not listed
+    #[account(mut)]
+    pub registry: AccountLoader<'info, StakeRegistry>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+
+}
+
+#[derive(Accounts)]
+pub struct StakeMany<'info> {
+    // user who stakes the nfts
+    #[account(mut)]
+    pub user_account: Signer<'info>,
+    /// CHECK: Safe account
+    #[account(
+        seeds = [
+            b"vault-stake-auth".as_ref(),
+        ],
+        bump,
+    )]
+    pub nft_authority: AccountInfo<'info>,
+    // the whitelist of the pool, not one posted by any other wallet
+    #[account(
+        seeds = [
+            b"Epoch-Merkle-Whitelist".as_ref(),
+            b"Solluminati-NFT-List".as_ref(),
+            epoch_state.bank_account.as_ref(),
+        ],
+        bump,
+    )]
+    pub merkle: Box<Account<'info, Merkle>>,
+    #[account(
+        mut,
+        seeds = [
+            b"stake-info".as_ref(),
+        ],
+        bump,
+    )]
+    pub stake_info: Box<Account<'info, StakeInfoState>>,
+    #[account(
+        seeds = [
+            b"epoch-state".as_ref(),
+        ],
+        bump,
+    )]
+    pub epoch_state: Box<Account<'info, EpochState>>,
+    #[account(
+        mut,
+        seeds = [
+            b"stake_user".as_ref(),
+            user_account.key().as_ref(),
+        ],
+        bump,
+        has_one = user_account
+    )]
+    pub stake_user: Box<Account<'info, StakeUserState>>,
+    // any registry page with room for all the nfts
+    #[account(mut)]
+    pub registry: AccountLoader<'info, StakeRegistry>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct UnstakeMany<'info> {
+    // user who unstakes the nfts
+    #[account(mut)]
+    pub user_account: Signer<'info>,
+    /// CHECK: Safe account
+    #[account(
+        seeds = [
+            b"vault-stake-auth".as_ref(),
+        ],
+        bump,
+    )]
+    pub vault_auth: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"stake_user".as_ref(),
+            user_account.key().as_ref(),
+        ],
+        bump,
+        has_one = user_account
+    )]
+    pub stake_user: Box<Account<'info, StakeUserState>>,
+    #[account(
+        mut,
+        seeds = [
+            b"epoch-state".as_ref(),
+        ],
+        bump,
+    )]
+    pub epoch_state: Box<Account<'info, EpochState>>,
+    #[account(
+        mut,
+        seeds = [
+            b"stake-info".as_ref(),
+        ],
+        bump,
+    )]
+    pub stake_info: Box<Account<'info, StakeInfoState>>,
+    // the registry page listing the nfts, any page if none is listed
+    #[account(mut)]
+    pub registry: AccountLoader<'info, StakeRegistry>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimReward<'info> {
+    // user account who stack NFT
+    #[account(mut)]
+    pub user_account: Signer<'info>,
+        /// CHECK: Safe account
+    #[account(
+        mut,
+        seeds = [
+            b"epoch-treasury".as_ref(),
+        ],
+        bump,
+    )]
+    pub treasury_account: AccountInfo<'info>,
+    #[account(
+        mut,
+        has_one = user_account
+    )]
+    pub stake_user: Box<Account<'info, StakeUserState>>,
+    #[account(
+        mut,
+        seeds = [
+            b"epoch-state".as_ref(),
+        ],
+        bump,
+    )]
+    pub epoch_state: Box<Account<'info, EpochState>>,
+    #[account(
+        seeds = [
+            b"stake-info".as_ref(),
+        ],
+        bump,
+    )]
+    pub stake_info: Box<Account<'info, StakeInfoState>>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct DailyReward<'info> {
+    #[account(mut)]
+    pub admin_account: Signer<'info>,
+    #[account(mut)]
+    pub stake_nft_info: Box<Account<'info, StakeNftInfoState>>,
+    #[account(mut)]
+    pub stake_user_account: Box<Account<'info, StakeUserState>>,
+    #[account(
+        mut,
+        seeds = [
+            b"stake-info".as_ref(),
+        ],
+        bump,
+    )]
+    pub stake_info: Box<Account<'info, StakeInfoState>>,
+    #[account(
+        mut,
+        seeds = [
+            b"epoch-state".as_ref(),
+        ],
+        bump,
+    )]
+    pub epoch_state: Box<Account<'info, EpochState>>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateDayEpoch<'info> {
+    // anyone can tick a due day and get the crank fee
+    #[account(mut)]
+    pub crank_account: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"stake-info".as_ref(),
+        ],
+        bump,
+    )]
+    pub stake_info: Box<Account<'info, StakeInfoState>>,
+    #[account(
+        seeds = [
+            b"epoch-state".as_ref(),
+        ],
+        bump,
+    )]
+    pub epoch_state: Box<Account<'info, EpochState>>,
+        /// CHECK: Safe account
+    #[account(
+        mut,
+        seeds = [
+            b"epoch-treasury".as_ref(),
+        ],
+        bump,
+    )]
+    pub treasury_account: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RestartEpoch<'info> {
+    #[account(mut)]
+    pub bank_account: Signer<'info>,
+    // Create Epoch Account
+    #[account(
+        mut,
+        seeds = [
+            b"epoch-state".as_ref(),
+        ],
+        bump
+    )]
+    pub epoch_state: Box<Account<'info, EpochState>>,
+    #[account(
+        mut,
+        seeds = [
+            b"stake-info".as_ref(),
+        ],
+        bump,
+    )]
+    pub stake_info: Box<Account<'info, StakeInfoState>>,
+        /// CHECK: Safe account
+    #[account(
+        mut,
+        seeds = [
+            b"epoch-treasury".as_ref(),
+        ],
+        bump,
+    )]
+    pub treasury_account: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct GetUserRewards<'info> {
+    pub stake_user: Box<Account<'info, StakeUserState>>,
+    #[account(
+        seeds = [
+            b"epoch-state".as_ref(),
+        ],
+        bump,
+    )]
+    pub epoch_state: Box<Account<'info, EpochState>>,
+    #[account(
+        seeds = [
+            b"stake-info".as_ref(),
+        ],
+        bump,
+    )]
+    pub stake_info: Box<Account<'info, StakeInfoState>>,
+}
+
+#[derive(Accounts)]
+pub struct GetPoolStats<'info> {
+    #[account(
+        seeds = [
+            b"epoch-state".as_ref(),
+        ],
+        bump,
+    )]
+    pub epoch_state: Box<Account<'info, EpochState>>,
+    #[account(
+        seeds = [
+            b"stake-info".as_ref(),
+        ],
+        bump,
+    )]
+    pub stake_info: Box<Account<'info, StakeInfoState>>,
+        /// CHECK: Safe account
+    #[account(
+        seeds = [
+            b"epoch-treasury".as_ref(),
+        ],
+        bump,
+    )]
+    pub treasury_account: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SunsetPool<'info> {
+    #[account(mut)]
+    pub bank_account: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"epoch-state".as_ref(),
+        ],
+        bump,
+        has_one = bank_account
+    )]
+    pub epoch_state: Box<Account<'info, EpochState>>,
+    #[account(
+        mut,
+        seeds = [
+            b"stake-info".as_ref(),
+        ],
+        bump,
+    )]
+    pub stake_info: Box<Account<'info, StakeInfoState>>,
+}
+
+#[derive(Accounts)]
+pub struct ReclaimTreasury<'info> {
+    #[account(mut)]
+    pub bank_account: Signer<'info>,
+    #[account(
+        seeds = [
+            b"epoch-state".as_ref(),
+        ],
+        bump,
+        has_one = bank_account
+    )]
+    pub epoch_state: Box<Account<'info, EpochState>>,
+    #[account(
+        seeds = [
+            b"stake-info".as_ref(),
+        ],
+        bump,
+    )]
+    pub stake_info: Box<Account<'info, StakeInfoState>>,
+        /// CHECK: Safe account
+    #[account(
+        mut,
+        seeds = [
+            b"epoch-treasury".as_ref(),
+        ],
+        bump,
+    )]
+    pub treasury_account: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ForceUnstakeNft<'info> {
+    #[account(mut)]
+    pub bank_account: Signer<'info>,
+    /// CHECK: the owner of the stake record, it gets the nft and the rent back
+    #[account(mut)]
+    pub user_account: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = user_nft_token_account.owner == user_account.key(),
+        constraint = user_nft_token_account.mint == nft_mint.key()
+    )]
+    pub user_nft_token_account: Box<Account<'info, TokenAccount>>,
+    pub nft_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [
+            b"vault-stake".as_ref(),
+            nft_mint.key().as_ref(),
+            user_account.key().as_ref(),
+        ],
+        bump,
         constraint = nft_vault_account.owner == vault_auth.key(),
         constraint = nft_vault_account.mint == nft_mint.key()
     )]
     pub nft_vault_account: Box<Account<'info, TokenAccount>>,
-    
     #[account(
-        mut, 
+        mut,
+        seeds = [
+            b"user-stake".as_ref(),
+            nft_mint.key().as_ref(),
+            user_account.key().as_ref(),
+        ],
+        bump,
         has_one = user_account,
+        has_one = nft_mint,
         close = user_account
     )]
     pub stake_info_account: Box<Account<'info, StakeNftInfoState>>,
     /// CHECK: Safe account
+    #[account(
+        seeds = [
+            b"vault-stake-auth".as_ref(),
+        ],
+        bump,
+    )]
     pub vault_auth: AccountInfo<'info>,
-    pub merkle: Box<Account<'info, Merkle>>,
     #[account(
         mut,
+        seeds = [
+            b"stake_user".as_ref(),
+            user_account.key().as_ref(),
+        ],
+        bump,
         has_one = user_account
     )]
     pub stake_user: Box<Account<'info, StakeUserState>>,
@@ -692,6 +2925,7 @@ pub struct UnStakeNft<'info> {
             b"epoch-state".as_ref(),
         ],
         bump,
+        has_one = bank_account
     )]
     pub epoch_state: Box<Account<'info, EpochState>>,
     #[account(
@@ -702,16 +2936,20 @@ pub struct UnStakeNft<'info> {
         bump,
     )]
     pub stake_info: Box<Account<'info, StakeInfoState>>,
+    // the registry page listing the nft, any page if it is not listed
+    #[account(mut)]
+    pub registry: AccountLoader<'info, StakeRegistry>,
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
-
 }
 
 #[derive(Accounts)]
-pub struct ClaimReward<'info> {
-    // user account who stack NFT
+pub struct SettleStakeUser<'info> {
     #[account(mut)]
-    pub user_account: Signer<'info>,
+    pub bank_account: Signer<'info>,
+    /// CHECK: the owner of the stake user, it gets the reward
+    #[account(mut)]
+    pub user_account: AccountInfo<'info>,
         /// CHECK: Safe account
     #[account(
         mut,
@@ -723,88 +2961,191 @@ pub struct ClaimReward<'info> {
     pub treasury_account: AccountInfo<'info>,
     #[account(
         mut,
+        seeds = [
+            b"stake_user".as_ref(),
+            user_account.key().as_ref(),
+        ],
+        bump,
         has_one = user_account
     )]
     pub stake_user: Box<Account<'info, StakeUserState>>,
+    #[account(
+        mut,
+        seeds = [
+            b"epoch-state".as_ref(),
+        ],
+        bump,
+        has_one = bank_account
+    )]
+    pub epoch_state: Box<Account<'info, EpochState>>,
+    #[account(
+        seeds = [
+            b"stake-info".as_ref(),
+        ],
+        bump,
+    )]
+    pub stake_info: Box<Account<'info, StakeInfoState>>,
     pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
 }
 
 #[derive(Accounts)]
-pub struct DailyReward<'info> {
-    #[account(mut)]
-    pub admin_account: Signer<'info>,
-    pub stake_nft_info: Box<Account<'info, StakeNftInfoState>>,
+#[instruction(epoch_no: u8)]
+pub struct CloseEpochDistribution<'info> {
     #[account(mut)]
-    pub stake_user_account: Box<Account<'info, StakeUserState>>,
+    pub bank_account: Signer<'info>,
     #[account(
         mut,
         seeds = [
-            b"stake-info".as_ref(),
+            b"epoch-state".as_ref(),
         ],
         bump,
+        has_one = bank_account
     )]
-    pub stake_info: Box<Account<'info, StakeInfoState>>,
+    pub epoch_state: Box<Account<'info, EpochState>>,
     #[account(
+        mut,
         seeds = [
-            b"epoch-state".as_ref(),
+            b"epoch-distribution".as_ref(),
+            &[epoch_no],
         ],
         bump,
+        close = bank_account
     )]
-    pub epoch_state: Box<Account<'info, EpochState>>,
+    pub distribution: Box<Account<'info, EpochDistribution>>,
 }
 
 #[derive(Accounts)]
-pub struct UpdateDayEpoch<'info> {
+pub struct ClosePool<'info> {
     #[account(mut)]
-    pub admin_account: Signer<'info>,
+    pub bank_account: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [
+            b"epoch-state".as_ref(),
+        ],
+        bump,
+        has_one = bank_account,
+        close = bank_account
+    )]
+    pub epoch_state: Box<Account<'info, EpochState>>,
     #[account(
         mut,
         seeds = [
             b"stake-info".as_ref(),
         ],
         bump,
+        close = bank_account
     )]
     pub stake_info: Box<Account<'info, StakeInfoState>>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateEpochState<'info> {
+    #[account(mut)]
+    pub admin_account: Signer<'info>,
+    /// CHECK: v1 layout, checked by migration::load_v1
     #[account(
+        mut,
         seeds = [
             b"epoch-state".as_ref(),
         ],
         bump,
     )]
-    pub epoch_state: Box<Account<'info, EpochState>>,
+    pub epoch_state: AccountInfo<'info>,
+    /// CHECK: the admin of the whitelist the pool stakes against
+    pub bank_account: AccountInfo<'info>,
+    /// CHECK: v1 or v2 layout, only its address and owner matter
+    #[account(
+        seeds = [
+            b"Epoch-Merkle-Whitelist".as_ref(),
+            b"Solluminati-NFT-List".as_ref(),
+            bank_account.key().as_ref(),
+        ],
+        bump,
+        owner = crate::ID
+    )]
+    pub merkle: AccountInfo<'info>,
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::TokenStakeModel>,
+    #[account(constraint = program_data.upgrade_authority_address == Some(admin_account.key()))]
+    pub program_data: Account<'info, ProgramData>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct RestartEpoch<'info> {
+pub struct MigrateStakeInfo<'info> {
+    #[account(mut)]
+    pub admin_account: Signer<'info>,
+    /// CHECK: v1 layout, checked by migration::load_v1
+    #[account(
+        mut,
+        seeds = [
+            b"stake-info".as_ref(),
+        ],
+        bump,
+    )]
+    pub stake_info: AccountInfo<'info>,
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::TokenStakeModel>,
+    #[account(constraint = program_data.upgrade_authority_address == Some(admin_account.key()))]
+    pub program_data: Account<'info, ProgramData>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateMerkle<'info> {
+    #[account(mut)]
+    pub admin_account: Signer<'info>,
+    /// CHECK: v1 or v2 layout, checked by the handler
+    #[account(mut)]
+    pub merkle: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateStakeNftInfo<'info> {
+    #[account(mut)]
+    pub user_account: Signer<'info>,
+    /// CHECK: v1 layout, checked by migration::load_v1
+    #[account(mut)]
+    pub stake_info_account: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateStakeUser<'info> {
     #[account(mut)]
     pub bank_account: Signer<'info>,
-    // Create Epoch Account
+    /// CHECK: the owner of the stake user
+    pub user_account: AccountInfo<'info>,
+    /// CHECK: v1 layout, checked by migration::load_v1
     #[account(
         mut,
         seeds = [
-            b"epoch-state".as_ref(),
+            b"stake_user".as_ref(),
+            user_account.key().as_ref(),
         ],
-        bump
+        bump,
     )]
-    pub epoch_state: Box<Account<'info, EpochState>>,
+    pub stake_user: AccountInfo<'info>,
     #[account(
         mut,
         seeds = [
-            b"stake-info".as_ref(),
+            b"epoch-state".as_ref(),
         ],
         bump,
+        has_one = bank_account
     )]
-    pub stake_info: Box<Account<'info, StakeInfoState>>,
-        /// CHECK: Safe account
+    pub epoch_state: Box<Account<'info, EpochState>>,
     #[account(
         mut,
         seeds = [
-            b"epoch-treasury".as_ref(),
+            b"stake-info".as_ref(),
         ],
         bump,
     )]
-    pub treasury_account: AccountInfo<'info>,
+    pub stake_info: Box<Account<'info, StakeInfoState>>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
@@ -824,6 +3165,7 @@ pub struct BackTreasury<'info> {
 }
 
 impl<'info> StakeNft<'info> {
+    #[allow(clippy::wrong_self_convention)]
     fn into_transfer_to_pda_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
         let cpi_accounts = Transfer {
             from: self
@@ -837,58 +3179,420 @@ impl<'info> StakeNft<'info> {
     }
 }
 
-impl<'info> UnStakeNft<'info> {
-    fn into_transfer_to_user_context(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
-        let cpi_accounts = Transfer {
-            from: self
-                .nft_vault_account
-                .to_account_info()
-                .clone(),
-            to: self.user_nft_token_account.to_account_info().clone(),
-            authority: self.vault_auth.clone(),
-        };
-        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
-    }
-}
-
 #[account]
 #[derive(Default)]
 pub struct EpochState {
+    pub version: u8,
     pub is_initial: bool,
     pub epoch_no: u8,
     pub epoch_start_time: i64,
     pub cur_epoch_reward_per_day: u64,
     pub epoch_bonus: u64,
-    pub remain_reward: u64
+    pub remain_reward: u64,
+    pub bank_account: Pubkey,
+    pub is_sunset: bool,
+    pub sunset_time: i64,
+    // rewards credited to the users and not claimed yet
+    pub total_owed: u64,
+    // paid from the treasury to whoever ticks a day or starts an epoch
+    pub crank_fee: u64,
+    // DISTRIBUTION_DAILY or DISTRIBUTION_MERKLE
+    pub distribution_mode: u8,
+    // credited by the daily crank since the running epoch started
+    pub epoch_credited: u64,
+    pub reserved: [u8; 15]
+}
+
+impl EpochState {
+    pub const VERSION: u8 = 2;
+    pub const LEN: usize = 1 + 1 + 1 + 8 + 8 + 8 + 8 + 32 + 1 + 8 + 8 + 8 + 1 + 8 + 15;
+}
+
+#[account]
+pub struct EpochDistribution {
+    pub version: u8,
+    pub epoch_no: u8,
+    /// The root of the `(index, user, amount)` leaves of the epoch.
+    pub root: [u8; 32],
+    // what the leaves add up to, reserved in the treasury when posted
+    pub total_amount: u64,
+    pub num_nodes: u32,
+    pub total_claimed: u64,
+    pub num_claimed: u32,
+    // longest proof accepted
+    pub max_depth: u8,
+    pub reserved: [u8; 31],
+    // one bit per leaf index, set once claimed
+    pub claimed_bitmap: Vec<u8>
+}
+
+impl EpochDistribution {
+    pub const VERSION: u8 = 2;
+    pub const LEN: usize = 1 + 1 + 32 + 8 + 4 + 8 + 4 + 1 + 31 + 4;
+
+    /// Checks that `user_account` was given `amount` at leaf `index`.
+    pub fn verify_balance(&self, proof: Vec<[u8; 32]>, index: u64, user_account: &Pubkey, amount: u64) -> bool {
+        let data: [&[u8]; 4] = [
+            BALANCE_LEAF_PREFIX,
+            &index.to_le_bytes(),
+            &user_account.to_bytes(),
+            &amount.to_le_bytes(),
+        ];
+        merkle_proof::verify_v2(&proof, self.root, merkle_proof::leaf_hash_v2(&data), self.max_depth)
+    }
+
+    pub fn bitmap_len(num_nodes: u32) -> usize {
+        (num_nodes as usize).div_ceil(8)
+    }
+
+    pub fn is_claimed(&self, index: u64) -> bool {
+        self.claimed_bitmap[index as usize / 8] & (1 << (index % 8)) != 0
+    }
+
+    pub fn set_claimed(&mut self, index: u64) {
+        self.claimed_bitmap[index as usize / 8] |= 1 << (index % 8);
+    }
+}
+
+#[account]
+pub struct EpochHistory {
+    pub version: u8,
+    pub reserved: [u8; 32],
+    // one record for every ended epoch
+    pub records: Vec<EpochRecord>
+}
+
+impl EpochHistory {
+    pub const VERSION: u8 = 1;
+    pub const LEN: usize = 1 + 32 + 4 + EpochRecord::LEN * TOTAL_EPOCH as usize;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct EpochRecord {
+    pub epoch_no: u8,
+    pub epoch_start_time: i64,
+    pub reward_per_day: u64,
+    pub total_stakers: u16,
+    // the epoch ended without ever being started
+    pub is_empty: bool
+}
+
+impl EpochRecord {
+    pub const LEN: usize = 1 + 8 + 8 + 2 + 1;
 }
 
 #[account]
 pub struct StakeInfoState {
+    pub version: u8,
     pub is_initial: bool,
     pub total_stakers: u16,
-    pub day_of_epoch: u8
+    pub day_of_epoch: u8,
+    // number of StakeRegistry pages created
+    pub registry_pages: u16,
+    // sum of the weights of the staked nfts, 0 until the first stake after weights were added
+    pub total_weight: u64,
+    // days of the running epoch the day crank has passed, EPOCH_DAYS once it is over
+    pub days_passed: u8,
+    // staked nfts credited for every day of the running epoch
+    pub credited_nfts: u16,
+    // nfts staked in the v1 pool that no stake user migration counted yet
+    pub v1_stakers: u16,
+    pub reserved: [u8; 17]
+}
+
+impl StakeInfoState {
+    pub const VERSION: u8 = 2;
+    pub const LEN: usize = 1 + 1 + 2 + 1 + 2 + 8 + 1 + 2 + 2 + 17;
+
+    /// Pools ticked before the days passed were tracked only have the day.
+    pub fn days_passed(&self) -> u8 {
+        self.days_passed.max(self.day_of_epoch)
+    }
+
+    /// The weight the daily reward is split by. Pools that staked before
+    /// weights were added only hold nfts of weight 1.
+    pub fn total_weight(&self) -> u64 {
+        if self.total_weight == 0 {
+            self.total_stakers as u64
+        } else {
+            self.total_weight
+        }
+    }
+
+    pub fn add_weight(&mut self, weight: u16) -> Result<()> {
+        self.total_weight = rewards::add(self.total_weight(), weight as u64)?;
+        Ok(())
+    }
+
+    pub fn sub_weight(&mut self, weight: u64) {
+        self.total_weight = self.total_weight().saturating_sub(weight);
+    }
 }
 
 #[account]
 pub struct StakeNftInfoState {
+    pub version: u8,
     pub user_account: Pubkey,
     pub nft_mint: Pubkey,
     pub stake_time: i64,
+    // the StakeRegistry page listing the nft
+    pub registry_page: u16,
+    pub is_registered: bool,
+    // reward weight proved by the whitelist, 0 for nfts staked before weights were added
+    pub weight: u16,
+    // the epoch, by its start time, and its days the nft was last credited for
+    pub credited_epoch_start: i64,
+    pub credited_days: u8,
+    pub reserved: [u8; 18],
+}
+
+impl StakeNftInfoState {
+    pub const VERSION: u8 = 2;
+    pub const LEN: usize = 1 + 32 + 32 + 8 + 2 + 1 + 2 + 8 + 1 + 18;
+
+    pub fn weight(&self) -> u64 {
+        self.weight.max(1) as u64
+    }
+
+    /// Whether the nft was credited for every day of the epoch started at
+    /// `epoch_start_time`.
+    pub fn is_epoch_credited(&self, epoch_start_time: i64) -> bool {
+        self.credited_epoch_start == epoch_start_time && self.credited_days >= EPOCH_DAYS - 1
+    }
+
+    /// Days of the epoch started at `epoch_start_time` the nft was credited
+    /// for. Nfts staked before the days were tracked were credited once per
+    /// crank call, up to the day before.
+    pub fn credited_days(&self, epoch_start_time: i64, days_passed: u8) -> u8 {
+        if self.credited_epoch_start == 0 {
+            days_passed.saturating_sub(1)
+        } else if self.credited_epoch_start == epoch_start_time {
+            self.credited_days
+        } else {
+            0
+        }
+    }
+}
+
+#[account(zero_copy)]
+pub struct StakeRegistry {
+    pub version: u8,
+    pub bump: u8,
+    pub page: u16,
+    pub count: u16,
+    pub reserved: [u8; 26],
+    // the first `count` entries are the staked nfts, in no particular order
+    pub entries: [RegistryEntry; REGISTRY_PAGE_SIZE],
+}
+
+impl StakeRegistry {
+    pub const VERSION: u8 = 1;
+    pub const LEN: usize = 1 + 1 + 2 + 2 + 26 + RegistryEntry::LEN * REGISTRY_PAGE_SIZE;
+
+    pub fn push(&mut self, entry: RegistryEntry) -> Result<()> {
+        if self.count as usize >= REGISTRY_PAGE_SIZE {
+            return Err(error!(StakeError::RegistryFull));
+        }
+        self.entries[self.count as usize] = entry;
+        self.count += 1;
+        Ok(())
+    }
+
+    // moves the last entry into the removed one, so the listed entries stay contiguous
+    pub fn swap_remove(&mut self, nft_mint: Pubkey) -> Result<()> {
+        let count = self.count as usize;
+        let index = self.entries[..count]
+            .iter()
+            .position(|entry| entry.nft_mint == nft_mint)
+            .ok_or(error!(StakeError::WrongRegistryPage))?;
+
+        self.entries[index] = self.entries[count - 1];
+        self.entries[count - 1] = RegistryEntry::default();
+        self.count -= 1;
+        Ok(())
+    }
+}
+
+#[zero_copy]
+#[derive(Default)]
+pub struct RegistryEntry {
+    pub nft_mint: Pubkey,
+    pub user_account: Pubkey,
+}
+
+impl RegistryEntry {
+    pub const LEN: usize = 32 + 32;
 }
 
 #[account]
 pub struct StakeUserState {
+    pub version: u8,
     pub is_initial: bool,
     pub user_account: Pubkey,
     pub reward_amount: u64,
     pub pending_amount: u64,
+    // number of nfts staked by the user right now
+    pub staked_count: u16,
+    // lifetime statistics, since v3
+    pub total_claimed: u64,
+    pub total_forfeited: u64,
+    pub total_credited: u64,
+    pub first_stake_time: i64,
+    pub last_claim_time: i64,
+    // sum of the weights of the staked nfts, 0 until the first stake after weights were added
+    pub staked_weight: u64,
+    pub reserved: [u8; 24],
+}
+
+impl StakeUserState {
+    pub const VERSION: u8 = 3;
+    pub const LEN: usize = 1 + 1 + 32 + 8 + 8 + 2 + 8 + 8 + 8 + 8 + 8 + 8 + 24;
+
+    /// Like `StakeInfoState::total_weight`, for the nfts of the user.
+    pub fn staked_weight(&self) -> u64 {
+        if self.staked_weight == 0 {
+            self.staked_count as u64
+        } else {
+            self.staked_weight
+        }
+    }
+
+    pub fn add_weight(&mut self, weight: u16) -> Result<()> {
+        self.staked_weight = rewards::add(self.staked_weight(), weight as u64)?;
+        Ok(())
+    }
+
+    pub fn sub_weight(&mut self, weight: u64) {
+        self.staked_weight = self.staked_weight().saturating_sub(weight);
+    }
 }
 
 #[account]
-#[derive(Default)]
 pub struct Merkle {
+    pub version: u8,
     /// The 256-bit merkle root.
     pub root: [u8; 32],
     pub admin_account: Pubkey,
-    pub is_init: bool
+    pub is_init: bool,
+    // the root is hashed with merkle_proof::verify_v2, v1 roots read false
+    pub is_hash_v2: bool,
+    // longest proof accepted by a v2 root
+    pub max_depth: u8,
+    // the leaves of the v2 root hash the reward weight of every mint
+    pub is_weighted: bool,
+    // WHITELIST_MERKLE or a mode checking the metadata of the nft instead
+    pub whitelist_mode: u8,
+    // the verified collection of the WHITELIST_COLLECTION mode
+    pub collection_mint: Pubkey,
+    // the verified creator of the WHITELIST_CREATOR mode
+    pub creator: Pubkey,
+    // update authority required by the WHITELIST_CREATOR mode, any if default
+    pub update_authority: Pubkey,
+    pub reserved: [u8; 64]
+}
+
+// arrays over 32 bytes have no Default
+impl Default for Merkle {
+    fn default() -> Self {
+        Merkle {
+            version: 0,
+            root: [0; 32],
+            admin_account: Pubkey::default(),
+            is_init: false,
+            is_hash_v2: false,
+            max_depth: 0,
+            is_weighted: false,
+            whitelist_mode: WHITELIST_MERKLE,
+            collection_mint: Pubkey::default(),
+            creator: Pubkey::default(),
+            update_authority: Pubkey::default(),
+            reserved: [0; 64],
+        }
+    }
+}
+
+impl Merkle {
+    pub const VERSION: u8 = 3;
+    pub const LEN: usize = 1 + 32 + 32 + 1 + 1 + 1 + 1 + 1 + 32 + 32 + 32 + 64;
+
+    /// Accounts of every nft of a `process_stake_many` batch.
+    pub fn batch_group_len(&self) -> usize {
+        if self.whitelist_mode == WHITELIST_MERKLE {
+            BATCH_ACCOUNTS_PER_NFT
+        } else {
+            BATCH_ACCOUNTS_PER_NFT + 1
+        }
+    }
+
+    /// Checks the Metaplex `metadata_account` of `nft_mint` against the
+    /// whitelist mode. Nfts whitelisted by their metadata have weight 1.
+    pub fn verify_metadata(&self, metadata_account: &AccountInfo, nft_mint: &Pubkey, weight: u16) -> Result<()> {
+        let metadata = metadata::load(metadata_account, nft_mint)?;
+        let is_whitelisted = match self.whitelist_mode {
+            WHITELIST_COLLECTION => metadata.verified_collection() == Some(self.collection_mint),
+            WHITELIST_CREATOR => {
+                metadata.has_verified_creator(&self.creator)
+                    && (self.update_authority == Pubkey::default() || metadata.update_authority == self.update_authority)
+            }
+            _ => false,
+        };
+        if !is_whitelisted || weight != 1 {
+            return Err(error!(StakeError::NotWhitelisted));
+        }
+        Ok(())
+    }
+
+    /// The leaf of `nft_mint` with `weight`, hashed the way of the root. The
+    /// weight of an unweighted whitelist is 1, and no weight is 0.
+    pub fn mint_leaf(&self, nft_mint: &Pubkey, weight: u16) -> Option<[u8; 32]> {
+        let mint = nft_mint.to_bytes();
+        if weight == 0 || (!self.is_weighted && weight != 1) {
+            return None;
+        }
+        let leaf = if self.is_weighted {
+            merkle_proof::leaf_hash_v2(&[WEIGHTED_LEAF_PREFIX, &mint, &weight.to_le_bytes()])
+        } else if self.is_hash_v2 {
+            merkle_proof::leaf_hash_v2(&[MINT_LEAF_PREFIX, &mint])
+        } else {
+            anchor_lang::solana_program::keccak::hashv(&[MINT_LEAF_PREFIX, &mint]).0
+        };
+        Some(leaf)
+    }
+
+    /// Checks that `nft_mint` with `weight` is a leaf of the whitelist.
+    pub fn verify_mint(&self, proof: Vec<[u8; 32]>, nft_mint: &Pubkey, weight: u16) -> bool {
+        let leaf = match self.mint_leaf(nft_mint, weight) {
+            Some(leaf) => leaf,
+            None => return false,
+        };
+        if self.is_hash_v2 {
+            return merkle_proof::verify_v2(&proof, self.root, leaf, self.max_depth);
+        }
+        if proof.len() > merkle_proof::MAX_DEPTH as usize {
+            return false;
+        }
+        merkle_proof::verify(proof, self.root, leaf)
+    }
+
+    /// Checks all the mints against the whitelist with one multiproof. Every
+    /// mint comes with its leaf index and weight, in any order, and is listed once.
+    pub fn verify_mints(&self, proof: &[[u8; 32]], mints: &[(u32, Pubkey, u16)], num_leaves: u32) -> bool {
+        let leaves: Option<Vec<(u32, [u8; 32])>> = mints
+            .iter()
+            .map(|(index, nft_mint, weight)| Some((*index, self.mint_leaf(nft_mint, *weight)?)))
+            .collect();
+        let mut leaves = match leaves {
+            Some(leaves) => leaves,
+            None => return false,
+        };
+        leaves.sort_unstable_by_key(|(index, _)| *index);
+
+        if self.is_hash_v2 {
+            return merkle_proof::verify_multi_v2(proof, self.root, &leaves, num_leaves, self.max_depth);
+        }
+        if merkle_proof::tree_depth(num_leaves) > merkle_proof::MAX_DEPTH as u32 {
+            return false;
+        }
+        merkle_proof::verify_multi(proof, self.root, &leaves, num_leaves)
+    }
 }
\ No newline at end of file
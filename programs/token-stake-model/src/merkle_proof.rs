@@ -21,4 +21,132 @@ pub fn verify(proof: Vec<[u8; 32]>, root: [u8; 32], leaf: [u8; 32]) -> bool {
     }
     // Check if the computed hash (root) is equal to the provided root
     computed_hash == root
-}
\ No newline at end of file
+}
+/// Prefix of the leaf hashes of v2 trees.
+pub const LEAF_PREFIX: &[u8] = &[0];
+/// Prefix of the internal node hashes of v2 trees, so that no node can be
+/// passed off as a leaf.
+pub const NODE_PREFIX: &[u8] = &[1];
+/// Deepest proof accepted, whatever the depth stored with the root.
+pub const MAX_DEPTH: u8 = 32;
+
+/// Hash of a v2 leaf over the concatenation of `data`.
+pub fn leaf_hash_v2(data: &[&[u8]]) -> [u8; 32] {
+    let mut input = Vec::with_capacity(data.len() + 1);
+    input.push(LEAF_PREFIX);
+    input.extend_from_slice(data);
+    anchor_lang::solana_program::keccak::hashv(&input).0
+}
+
+/// Hash of a v2 internal node, over the sorted pair.
+pub fn node_hash_v2(first: &[u8; 32], second: &[u8; 32]) -> [u8; 32] {
+    if first <= second {
+        anchor_lang::solana_program::keccak::hashv(&[NODE_PREFIX, first, second]).0
+    } else {
+        anchor_lang::solana_program::keccak::hashv(&[NODE_PREFIX, second, first]).0
+    }
+}
+
+/// Like `verify`, for trees hashed with `leaf_hash_v2` and `node_hash_v2`.
+/// A proof longer than `max_depth` is rejected before any hashing.
+pub fn verify_v2(proof: &[[u8; 32]], root: [u8; 32], leaf: [u8; 32], max_depth: u8) -> bool {
+    if proof.len() > max_depth.min(MAX_DEPTH) as usize {
+        return false;
+    }
+    let computed_hash = proof
+        .iter()
+        .fold(leaf, |computed_hash, proof_element| node_hash_v2(&computed_hash, proof_element));
+    computed_hash == root
+}
+
+/// Returns true if all `leaves` can be proved to be part of the Merkle tree
+/// defined by `root` with a single `proof` that shares the siblings of the
+/// branches. Each leaf comes with its index among the `num_leaves` sorted
+/// leaves, the indexes strictly increasing. The tree pairs the nodes of a
+/// layer two by two and moves the last node of an odd layer up unhashed; the
+/// proof holds the siblings that are not computed from `leaves`, layer by
+/// layer from the leaves up and left to right, and must be used up entirely.
+pub fn verify_multi(
+    proof: &[[u8; 32]],
+    root: [u8; 32],
+    leaves: &[(u32, [u8; 32])],
+    num_leaves: u32,
+) -> bool {
+    multi_root(proof, leaves, num_leaves, hash_pair) == Some(root)
+}
+
+/// Like `verify_multi`, for trees hashed with `leaf_hash_v2` and
+/// `node_hash_v2`. A tree deeper than `max_depth` is rejected before any
+/// hashing.
+pub fn verify_multi_v2(
+    proof: &[[u8; 32]],
+    root: [u8; 32],
+    leaves: &[(u32, [u8; 32])],
+    num_leaves: u32,
+    max_depth: u8,
+) -> bool {
+    if tree_depth(num_leaves) > max_depth.min(MAX_DEPTH) as u32 {
+        return false;
+    }
+    multi_root(proof, leaves, num_leaves, node_hash_v2) == Some(root)
+}
+
+/// Number of layers above the leaves, the length of the longest single proof.
+pub fn tree_depth(num_leaves: u32) -> u32 {
+    let mut width = num_leaves;
+    let mut depth = 0;
+    while width > 1 {
+        width = width.div_ceil(2);
+        depth += 1;
+    }
+    depth
+}
+
+fn hash_pair(first: &[u8; 32], second: &[u8; 32]) -> [u8; 32] {
+    if first <= second {
+        anchor_lang::solana_program::keccak::hashv(&[first, second]).0
+    } else {
+        anchor_lang::solana_program::keccak::hashv(&[second, first]).0
+    }
+}
+
+fn multi_root(
+    proof: &[[u8; 32]],
+    leaves: &[(u32, [u8; 32])],
+    num_leaves: u32,
+    combine: fn(&[u8; 32], &[u8; 32]) -> [u8; 32],
+) -> Option<[u8; 32]> {
+    let (last, _) = leaves.last()?;
+    if *last >= num_leaves || leaves.windows(2).any(|pair| pair[0].0 >= pair[1].0) {
+        return None;
+    }
+
+    let mut proof = proof.iter();
+    let mut layer = leaves.to_vec();
+    let mut width = num_leaves;
+    while width > 1 {
+        let mut next = Vec::with_capacity(layer.len());
+        let mut i = 0;
+        while i < layer.len() {
+            let (index, hash) = layer[i];
+            let sibling = index ^ 1;
+            let parent = if sibling >= width {
+                // last node of an odd layer
+                hash
+            } else if i + 1 < layer.len() && layer[i + 1].0 == sibling {
+                i += 1;
+                combine(&hash, &layer[i].1)
+            } else {
+                combine(&hash, proof.next()?)
+            };
+            next.push((index / 2, parent));
+            i += 1;
+        }
+        layer = next;
+        width = width.div_ceil(2);
+    }
+    if proof.next().is_some() {
+        return None;
+    }
+    Some(layer[0].1)
+}
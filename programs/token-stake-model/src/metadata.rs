@@ -0,0 +1,185 @@
+//! Reads the Metaplex token metadata of an nft, without depending on the
+//! Metaplex crates. Only the fields the whitelist modes look at are kept.
+//!
+//! The account is the borsh layout of `mpl_token_metadata::state::Metadata`,
+//! zero padded to its maximum size. The fields added after the first version
+//! read as `None` on the accounts created before them.
+
+use anchor_lang::prelude::*;
+use crate::error::StakeError;
+
+declare_id!("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+
+/// `Key::MetadataV1`, the first byte of every metadata account.
+pub const KEY_METADATA_V1: u8 = 4;
+pub const SEED: &[u8] = b"metadata";
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct Creator {
+    pub address: Pubkey,
+    pub verified: bool,
+    pub share: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct Collection {
+    pub verified: bool,
+    pub key: Pubkey,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Metadata {
+    pub update_authority: Pubkey,
+    pub mint: Pubkey,
+    pub creators: Vec<Creator>,
+    pub collection: Option<Collection>,
+}
+
+impl Metadata {
+    /// The collection mint, if the collection is verified.
+    pub fn verified_collection(&self) -> Option<Pubkey> {
+        match &self.collection {
+            Some(collection) if collection.verified => Some(collection.key),
+            _ => None,
+        }
+    }
+
+    /// Whether `creator` is listed and has signed the metadata.
+    pub fn has_verified_creator(&self, creator: &Pubkey) -> bool {
+        self.creators.iter().any(|listed| listed.address == *creator && listed.verified)
+    }
+}
+
+/// Address of the metadata account of `mint`.
+pub fn find_address(mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[SEED, ID.as_ref(), mint.as_ref()], &ID)
+}
+
+// skips a borsh string, the metadata strings are zero padded
+fn skip_string(data: &mut &[u8]) -> Option<()> {
+    let len = u32::deserialize(data).ok()? as usize;
+    if data.len() < len {
+        return None;
+    }
+    *data = &data[len..];
+    Some(())
+}
+
+/// Parses the data of a metadata account, `None` if it is not one.
+pub fn parse(mut data: &[u8]) -> Option<Metadata> {
+    let data = &mut data;
+    if u8::deserialize(data).ok()? != KEY_METADATA_V1 {
+        return None;
+    }
+    let update_authority = Pubkey::deserialize(data).ok()?;
+    let mint = Pubkey::deserialize(data).ok()?;
+    // name, symbol, uri
+    for _ in 0..3 {
+        skip_string(data)?;
+    }
+    let _seller_fee_basis_points = u16::deserialize(data).ok()?;
+    let creators = Option::<Vec<Creator>>::deserialize(data).ok()?;
+    let _primary_sale_happened = bool::deserialize(data).ok()?;
+    let _is_mutable = bool::deserialize(data).ok()?;
+
+    // edition_nonce and token_standard come before the collection
+    let collection = Option::<u8>::deserialize(data)
+        .and_then(|_| Option::<u8>::deserialize(data))
+        .and_then(|_| Option::<Collection>::deserialize(data))
+        .unwrap_or(None);
+
+    Some(Metadata {
+        update_authority,
+        mint,
+        creators: creators.unwrap_or_default(),
+        collection,
+    })
+}
+
+/// Loads the metadata of `mint` from `account`, which must be its metadata
+/// account.
+pub fn load(account: &AccountInfo, mint: &Pubkey) -> Result<Metadata> {
+    if account.owner != &ID || account.key() != find_address(mint).0 {
+        return Err(error!(StakeError::WrongMetadata));
+    }
+    match parse(&account.data.borrow()) {
+        Some(metadata) if metadata.mint == *mint => Ok(metadata),
+        _ => Err(error!(StakeError::WrongMetadata)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the layout written by the metadata program, `tail` after is_mutable
+    fn metadata_data(mint: &Pubkey, creators: Option<Vec<Creator>>, tail: &[u8]) -> Vec<u8> {
+        let mut data = vec![KEY_METADATA_V1];
+        data.extend_from_slice(Pubkey::new_unique().as_ref());
+        data.extend_from_slice(mint.as_ref());
+        for field in [&b"Solluminati #1"[..], b"SOL", b"https://example.com/1.json"] {
+            let mut padded = field.to_vec();
+            padded.resize(32, 0);
+            padded.serialize(&mut data).unwrap();
+        }
+        500u16.serialize(&mut data).unwrap();
+        creators.serialize(&mut data).unwrap();
+        data.extend_from_slice(&[1, 1]);
+        data.extend_from_slice(tail);
+        data
+    }
+
+    fn collection_tail(collection: &Option<Collection>) -> Vec<u8> {
+        // edition_nonce, token_standard
+        let mut tail = vec![1, 255, 1, 0];
+        collection.serialize(&mut tail).unwrap();
+        tail
+    }
+
+    #[test]
+    fn parses_creators_and_collection() {
+        let mint = Pubkey::new_unique();
+        let creators = vec![
+            Creator { address: Pubkey::new_unique(), verified: true, share: 0 },
+            Creator { address: Pubkey::new_unique(), verified: false, share: 100 },
+        ];
+        let collection_mint = Pubkey::new_unique();
+        let collection = Some(Collection { verified: true, key: collection_mint });
+        let mut data = metadata_data(&mint, Some(creators.clone()), &collection_tail(&collection));
+        data.resize(679, 0);
+
+        let metadata = parse(&data).unwrap();
+        assert_eq!(metadata.mint, mint);
+        assert_eq!(metadata.creators, creators);
+        assert!(metadata.has_verified_creator(&creators[0].address));
+        assert!(!metadata.has_verified_creator(&creators[1].address));
+        assert_eq!(metadata.collection, collection);
+        assert_eq!(metadata.verified_collection(), Some(collection_mint));
+    }
+
+    #[test]
+    fn unverified_and_missing_collections() {
+        let mint = Pubkey::new_unique();
+        let unverified = Some(Collection { verified: false, key: Pubkey::new_unique() });
+        let metadata = parse(&metadata_data(&mint, None, &collection_tail(&unverified))).unwrap();
+        assert!(metadata.creators.is_empty());
+        assert_eq!(metadata.verified_collection(), None);
+
+        // created before the collection field, zero padded or not
+        let mut padded = metadata_data(&mint, None, &[]);
+        let old = parse(&padded).unwrap();
+        assert_eq!(old.collection, None);
+        padded.resize(679, 0);
+        assert_eq!(parse(&padded).unwrap(), old);
+    }
+
+    #[test]
+    fn rejects_other_accounts() {
+        let mint = Pubkey::new_unique();
+        let mut data = metadata_data(&mint, None, &[]);
+        assert!(parse(&data[..60]).is_none());
+        data[0] = 6;
+        assert!(parse(&data).is_none());
+        assert!(parse(&[]).is_none());
+    }
+}
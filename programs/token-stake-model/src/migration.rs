@@ -0,0 +1,122 @@
+//! Layouts of the accounts before they were versioned and the helpers used by
+//! the `process_migrate_*` instructions to upgrade them in place.
+//!
+//! The v1 accounts were sized with `size_of`, so they are recognised by their
+//! data length. Every later layout starts with a `version` byte.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{program::invoke, system_instruction};
+use std::io::Cursor;
+use std::mem::size_of;
+use crate::error::StakeError;
+
+#[derive(AnchorDeserialize)]
+pub struct EpochStateV1 {
+    pub is_initial: bool,
+    pub epoch_no: u8,
+    pub epoch_start_time: i64,
+    pub cur_epoch_reward_per_day: u64,
+    pub epoch_bonus: u64,
+    pub remain_reward: u64
+}
+
+#[derive(AnchorDeserialize)]
+pub struct StakeInfoStateV1 {
+    pub is_initial: bool,
+    pub total_stakers: u16,
+    pub day_of_epoch: u8
+}
+
+#[derive(AnchorDeserialize)]
+pub struct StakeNftInfoStateV1 {
+    pub user_account: Pubkey,
+    pub nft_mint: Pubkey,
+    pub stake_time: i64,
+}
+
+#[derive(AnchorDeserialize)]
+pub struct StakeUserStateV1 {
+    pub is_initial: bool,
+    pub user_account: Pubkey,
+    pub reward_amount: u64,
+    pub pending_amount: u64,
+}
+
+#[derive(AnchorDeserialize)]
+pub struct MerkleV1 {
+    pub root: [u8; 32],
+    pub admin_account: Pubkey,
+    pub is_init: bool
+}
+
+/// Returns true if `account` is a program account of type `discriminator`
+/// still using the v1 layout `T`.
+pub fn is_v1<T>(account: &AccountInfo, discriminator: [u8; 8]) -> bool {
+    if account.owner != &crate::ID {
+        return false;
+    }
+    let data = account.data.borrow();
+    data.len() == 8 + size_of::<T>() && data[..8] == discriminator
+}
+
+/// Deserializes the v1 layout `T` of `account`.
+pub fn load_v1<T: AnchorDeserialize>(account: &AccountInfo, discriminator: [u8; 8]) -> Result<T> {
+    if !is_v1::<T>(account, discriminator) {
+        return Err(error!(StakeError::AccountVersion));
+    }
+    let data = account.data.borrow();
+    let state = T::deserialize(&mut &data[8..])?;
+    Ok(state)
+}
+
+/// Grows `account` to `new_len` bytes, topping up its rent from `payer`.
+pub fn realloc_account<'info>(
+    account: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    new_len: usize,
+) -> Result<()> {
+    let rent_amount = Rent::get()?.minimum_balance(new_len);
+    let lamports = account.lamports();
+
+    if rent_amount > lamports {
+        invoke(
+            &system_instruction::transfer(
+                payer.key,
+                account.key,
+                rent_amount.checked_sub(lamports).unwrap(),
+            ),
+            &[
+                payer.clone(),
+                account.clone(),
+                system_program.clone(),
+            ],
+        )?;
+    }
+
+    account.realloc(new_len, true)?;
+    Ok(())
+}
+
+/// Grows a versioned account of an older version to `new_len` bytes. Later
+/// versions only add fields in front of the zeroed reserved bytes, so the
+/// layout is kept and every new field starts at zero.
+pub fn realloc_versioned<'info>(
+    account: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    new_len: usize,
+) -> Result<()> {
+    if account.owner != &crate::ID || account.data_len() >= new_len {
+        return Err(error!(StakeError::AccountVersion));
+    }
+    realloc_account(account, payer, system_program, new_len)
+}
+
+/// Writes the discriminator and the current layout of `state` to `account`.
+pub fn store<T: AccountSerialize>(account: &AccountInfo, state: &T) -> Result<()> {
+    let mut data = account.try_borrow_mut_data()?;
+    let dst: &mut [u8] = &mut data;
+    let mut cursor = Cursor::new(dst);
+    state.try_serialize(&mut cursor)
+}
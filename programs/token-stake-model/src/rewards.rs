@@ -0,0 +1,339 @@
+//! Epoch, day and reward computations of the handlers. Everything is passed
+//! in explicitly (timestamps, balances, counts), so the math runs with a plain
+//! `cargo test`, without a validator or a clock sysvar.
+
+use anchor_lang::prelude::*;
+use crate::error::StakeError;
+use crate::views::EstimatedApr;
+use std::convert::TryFrom;
+use crate::{CRANK_START_GRACE_PERIOD, DAYS_7_IN_SECONDS, DAY_IN_SECONDS, EPOCH_DAYS, RATE_BANK_TO_TREASURY, SUNSET_GRACE_PERIOD, TOTAL_EPOCH};
+
+pub fn add(a: u64, b: u64) -> Result<u64> {
+    a.checked_add(b).ok_or_else(|| error!(StakeError::MathOverflow))
+}
+
+pub fn mul(a: u64, b: u64) -> Result<u64> {
+    a.checked_mul(b).ok_or_else(|| error!(StakeError::MathOverflow))
+}
+
+/// What is shared every day of an epoch funded with `send_amount`.
+pub fn reward_per_day(send_amount: u64) -> u64 {
+    send_amount / EPOCH_DAYS as u64
+}
+
+/// Amount moved from the bank to the treasury by `process_start_epoch`.
+pub fn bank_send_amount(bank_amount: u64) -> Result<u64> {
+    Ok(mul(bank_amount, RATE_BANK_TO_TREASURY as u64)? / 100)
+}
+
+/// Reward shared by the next epoch: what the bank sent, plus the bonus and
+/// the forfeited rewards already in the treasury.
+pub fn epoch_reward_amount(send_amount: u64, epoch_bonus: u64, remain_reward: u64) -> Result<u64> {
+    add(add(send_amount, epoch_bonus)?, remain_reward)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct EpochRoll {
+    // the missed epochs are included
+    pub epochs_passed: u8,
+    pub epoch_no: u8,
+    pub epoch_start_time: i64,
+}
+
+/// Start time of the epoch `epochs` epochs after the one started at `epoch_start_time`.
+pub fn epoch_start_after(epoch_start_time: i64, epochs: u8) -> Result<i64> {
+    epoch_start_time
+        .checked_add(epochs as i64 * DAYS_7_IN_SECONDS as i64)
+        .ok_or_else(|| error!(StakeError::MathOverflow))
+}
+
+/// Moves to the epoch running at `now`. Errors if the current one has not
+/// ended or if that would go past the last epoch.
+pub fn roll_epochs(epoch_no: u8, epoch_start_time: i64, now: i64) -> Result<EpochRoll> {
+    let elapsed = now.saturating_sub(epoch_start_time).max(0);
+    let epochs_passed = elapsed / DAYS_7_IN_SECONDS as i64;
+    if epochs_passed == 0 {
+        return Err(error!(StakeError::EpochWrongDays));
+    }
+    if epoch_no as i64 + epochs_passed > TOTAL_EPOCH as i64 {
+        return Err(error!(StakeError::EpochEnd));
+    }
+    let epochs_passed = epochs_passed as u8;
+
+    Ok(EpochRoll {
+        epochs_passed,
+        epoch_no: epoch_no + epochs_passed,
+        epoch_start_time: epoch_start_after(epoch_start_time, epochs_passed)?,
+    })
+}
+
+/// Whether anyone can start the next epoch: the running one has ended and the
+/// bank let the grace period pass.
+pub fn crank_start_due(epoch_start_time: i64, now: i64) -> Result<bool> {
+    let due_time = epoch_start_after(epoch_start_time, 1)?
+        .checked_add(CRANK_START_GRACE_PERIOD as i64)
+        .ok_or_else(|| error!(StakeError::MathOverflow))?;
+    Ok(now >= due_time)
+}
+
+/// Days passed since `epoch_start_time`, `EPOCH_DAYS` once the epoch is over.
+pub fn days_passed(epoch_start_time: i64, now: i64) -> u8 {
+    let days_passed = now.saturating_sub(epoch_start_time).max(0) / DAY_IN_SECONDS as i64;
+    days_passed.min(EPOCH_DAYS as i64) as u8
+}
+
+/// Day of the epoch started at `epoch_start_time`. After 7 days the epoch
+/// waits on day 0 for the next start.
+pub fn day_of_epoch(epoch_start_time: i64, now: i64) -> u8 {
+    days_passed(epoch_start_time, now) % EPOCH_DAYS
+}
+
+/// Days an nft credited for the first `credited_days` of the epoch is owed
+/// once `days_passed` days have passed. Days 1 to 6 are credited, the 7th only
+/// settles the epoch.
+pub fn days_due(credited_days: u8, days_passed: u8) -> u8 {
+    let last_day = EPOCH_DAYS - 1;
+    days_passed.min(last_day).saturating_sub(credited_days.min(last_day))
+}
+
+/// Daily reward of staked nfts of `weight` out of `total_weight`. With nothing
+/// staked, the first nft of weight 1 would get the whole daily reward.
+pub fn weighted_reward(reward_per_day: u64, weight: u64, total_weight: u64) -> Result<u64> {
+    let amount = reward_per_day as u128 * weight as u128 / total_weight.max(1) as u128;
+    u64::try_from(amount).map_err(|_| error!(StakeError::MathOverflow))
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct DailyCredit {
+    // added to the pending amount
+    pub amount: u64,
+    // moved from the pending to the claimable amount
+    pub settled_amount: u64,
+}
+
+/// Credits `reward_amount` for each of `days`. Day 0 also settles the pending
+/// amount of the epoch, the days just credited included.
+pub fn daily_credit(day_of_epoch: u8, days: u8, reward_amount: u64, pending_amount: u64) -> Result<DailyCredit> {
+    let amount = mul(reward_amount, days as u64)?;
+    let settled_amount = if day_of_epoch == 0 {
+        add(pending_amount, amount)?
+    } else {
+        0
+    };
+    Ok(DailyCredit {
+        amount,
+        settled_amount,
+    })
+}
+
+/// Rewards of the running epoch still to be credited: its six credited days,
+/// less what the crank has credited so far.
+pub fn uncredited_amount(reward_per_day: u64, epoch_credited: u64) -> Result<u64> {
+    Ok(mul(reward_per_day, (EPOCH_DAYS - 1) as u64)?.saturating_sub(epoch_credited))
+}
+
+/// What the treasury must keep: the amount owed to the users, the rewards the
+/// running epoch still credits, and the bonus and forfeited rewards waiting
+/// for the next epoch.
+pub fn treasury_reserve(total_owed: u64, reward_per_day: u64, epoch_credited: u64, epoch_bonus: u64, remain_reward: u64) -> Result<u64> {
+    let reserved_amount = add(total_owed, uncredited_amount(reward_per_day, epoch_credited)?)?;
+    add(add(reserved_amount, epoch_bonus)?, remain_reward)
+}
+
+/// Crank fee paid from the treasury, 0 if it would touch the reserve of the
+/// treasury or its rent.
+pub fn crank_fee_due(treasury_amount: u64, reserved_amount: u64, rent_minimum: u64, crank_fee: u64) -> Result<u64> {
+    if crank_fee == 0 {
+        return Ok(0);
+    }
+    let keep_amount = add(reserved_amount, rent_minimum)?;
+    if treasury_amount < add(keep_amount, crank_fee)? {
+        return Ok(0);
+    }
+    Ok(crank_fee)
+}
+
+/// What the bank can take back after the sunset. Everything credited to the
+/// users stays, and the treasury stays rent exempt while anything is owed.
+pub fn reclaim_amount(treasury_amount: u64, total_owed: u64, rent_minimum: u64) -> Result<u64> {
+    let keep_amount = if total_owed > 0 { add(total_owed, rent_minimum)? } else { 0 };
+    Ok(treasury_amount.saturating_sub(keep_amount))
+}
+
+pub fn sunset_grace_period_over(sunset_time: i64, now: i64) -> Result<bool> {
+    let end = sunset_time
+        .checked_add(SUNSET_GRACE_PERIOD as i64)
+        .ok_or_else(|| error!(StakeError::MathOverflow))?;
+    Ok(now >= end)
+}
+
+/// The pending amount at the end of the epoch if the crank keeps running.
+/// Day 0 settles the epoch, every other day is credited.
+pub fn projected_pending_amount(pending_amount: u64, daily_amount: u64, day_of_epoch: u8) -> Result<u64> {
+    let remaining_days = (EPOCH_DAYS - 1).saturating_sub(day_of_epoch);
+    add(pending_amount, mul(daily_amount, remaining_days as u64)?)
+}
+
+/// Yearly reward of one nft of weight 1 at the current rate, against `nft_value`.
+pub fn estimated_apr(reward_per_day: u64, total_weight: u64, nft_value: u64) -> Result<EstimatedApr> {
+    let mut apr = EstimatedApr::default();
+    apr.reward_per_nft_per_day = weighted_reward(reward_per_day, 1, total_weight)?;
+    apr.reward_per_nft_per_epoch = mul(apr.reward_per_nft_per_day, (EPOCH_DAYS - 1) as u64)?;
+    apr.reward_per_nft_per_year = mul(apr.reward_per_nft_per_epoch, TOTAL_EPOCH as u64)?;
+    if nft_value > 0 {
+        apr.apr_bps = (apr.reward_per_nft_per_year as u128 * 10000 / nft_value as u128) as u64;
+    }
+    Ok(apr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = DAY_IN_SECONDS as i64;
+    const WEEK: i64 = DAYS_7_IN_SECONDS as i64;
+
+    #[test]
+    fn start_amounts() {
+        assert_eq!(bank_send_amount(700).unwrap(), 700);
+        assert_eq!(epoch_reward_amount(700, 70, 7).unwrap(), 777);
+        assert_eq!(epoch_reward_amount(0, 70, 7).unwrap(), 77);
+        assert_eq!(epoch_reward_amount(u64::MAX, 1, 0).unwrap_err(), StakeError::MathOverflow.into());
+        assert_eq!(reward_per_day(700), 100);
+        assert_eq!(reward_per_day(6), 0);
+    }
+
+    #[test]
+    fn roll_one_epoch() {
+        let roll = roll_epochs(1, 1000, 1000 + WEEK).unwrap();
+        assert_eq!(roll, EpochRoll { epochs_passed: 1, epoch_no: 2, epoch_start_time: 1000 + WEEK });
+    }
+
+    #[test]
+    fn roll_keeps_the_week_grid_over_missed_epochs() {
+        let roll = roll_epochs(3, 0, 3 * WEEK + 2 * DAY).unwrap();
+        assert_eq!(roll, EpochRoll { epochs_passed: 3, epoch_no: 6, epoch_start_time: 3 * WEEK });
+    }
+
+    #[test]
+    fn roll_before_the_epoch_ends() {
+        assert_eq!(roll_epochs(1, 0, WEEK - 1).unwrap_err(), StakeError::EpochWrongDays.into());
+        // a clock behind the start time is not an epoch
+        assert_eq!(roll_epochs(1, 0, -WEEK).unwrap_err(), StakeError::EpochWrongDays.into());
+    }
+
+    #[test]
+    fn roll_past_the_last_epoch() {
+        assert!(roll_epochs(TOTAL_EPOCH - 1, 0, WEEK).is_ok());
+        assert_eq!(roll_epochs(TOTAL_EPOCH, 0, WEEK).unwrap_err(), StakeError::EpochEnd.into());
+        assert_eq!(roll_epochs(50, 0, 3 * WEEK).unwrap_err(), StakeError::EpochEnd.into());
+    }
+
+    #[test]
+    fn crank_start_waits_for_the_grace_period() {
+        assert!(!crank_start_due(0, WEEK).unwrap());
+        assert!(!crank_start_due(0, WEEK + DAY - 1).unwrap());
+        assert!(crank_start_due(0, WEEK + DAY).unwrap());
+        // missed epochs are long past the grace period
+        assert!(crank_start_due(0, 3 * WEEK).unwrap());
+    }
+
+    #[test]
+    fn days_of_the_epoch() {
+        assert_eq!(day_of_epoch(0, 0), 0);
+        assert_eq!(day_of_epoch(0, DAY - 1), 0);
+        assert_eq!(day_of_epoch(0, DAY), 1);
+        assert_eq!(day_of_epoch(0, 6 * DAY + 5), 6);
+        // the ended epoch waits on day 0
+        assert_eq!(day_of_epoch(0, 7 * DAY), 0);
+        assert_eq!(day_of_epoch(0, 30 * DAY), 0);
+        assert_eq!(day_of_epoch(DAY, 0), 0);
+    }
+
+    #[test]
+    fn daily_reward_per_nft() {
+        assert_eq!(weighted_reward(1000, 1, 3).unwrap(), 333);
+        assert_eq!(weighted_reward(1000, 1, 0).unwrap(), 1000);
+        assert_eq!(daily_credit(0, 0, 333, 999).unwrap(), DailyCredit { amount: 0, settled_amount: 999 });
+        assert_eq!(daily_credit(4, 1, 333, 999).unwrap(), DailyCredit { amount: 333, settled_amount: 0 });
+    }
+
+    #[test]
+    fn missed_days_are_credited_together() {
+        assert_eq!(days_passed(0, 3 * DAY), 3);
+        assert_eq!(days_passed(0, 30 * DAY), 7);
+        assert_eq!(days_due(1, 4), 3);
+        assert_eq!(days_due(4, 4), 0);
+        // staked on day 0, after an epoch nobody cranked
+        assert_eq!(days_due(0, 7), 6);
+        // staked once the epoch was over
+        assert_eq!(days_due(7, 7), 0);
+        assert_eq!(daily_credit(4, 3, 333, 333).unwrap(), DailyCredit { amount: 999, settled_amount: 0 });
+        // the end of the epoch credits the last days and settles them
+        assert_eq!(daily_credit(0, 2, 333, 999).unwrap(), DailyCredit { amount: 666, settled_amount: 1665 });
+        assert_eq!(daily_credit(1, 1, u64::MAX, 0).unwrap(), DailyCredit { amount: u64::MAX, settled_amount: 0 });
+        assert_eq!(daily_credit(1, 2, u64::MAX, 0).unwrap_err(), StakeError::MathOverflow.into());
+    }
+
+    #[test]
+    fn daily_reward_by_weight() {
+        // a legendary of weight 5 next to two commons
+        assert_eq!(weighted_reward(700, 5, 7).unwrap(), 500);
+        assert_eq!(weighted_reward(700, 1, 7).unwrap(), 100);
+        // the shares never add up to more than the daily reward
+        assert_eq!(weighted_reward(1000, 1, 3).unwrap() * 3, 999);
+        assert_eq!(weighted_reward(u64::MAX, u16::MAX as u64, u16::MAX as u64).unwrap(), u64::MAX);
+        assert_eq!(weighted_reward(u64::MAX, 2, 1).unwrap_err(), StakeError::MathOverflow.into());
+    }
+
+    #[test]
+    fn crank_fee_never_touches_what_is_owed() {
+        assert_eq!(crank_fee_due(1000, 0, 0, 0).unwrap(), 0);
+        assert_eq!(crank_fee_due(1000, 800, 100, 100).unwrap(), 100);
+        assert_eq!(crank_fee_due(1000, 801, 100, 100).unwrap(), 0);
+    }
+
+    #[test]
+    fn treasury_reserve_covers_the_rest_of_the_epoch() {
+        assert_eq!(uncredited_amount(100, 0).unwrap(), 600);
+        assert_eq!(uncredited_amount(100, 300).unwrap(), 300);
+        // weights changing during a day can credit a little more than the day
+        assert_eq!(uncredited_amount(100, 601).unwrap(), 0);
+        assert_eq!(treasury_reserve(50, 100, 300, 20, 7).unwrap(), 50 + 300 + 20 + 7);
+        // a fee before the first credit must leave the six credited days in the treasury
+        let reserved_amount = treasury_reserve(0, 100, 0, 0, 0).unwrap();
+        assert_eq!(crank_fee_due(700, reserved_amount, 0, 100).unwrap(), 100);
+        assert_eq!(crank_fee_due(699, reserved_amount, 0, 100).unwrap(), 0);
+    }
+
+    #[test]
+    fn reclaim_keeps_what_is_owed() {
+        assert_eq!(reclaim_amount(1000, 0, 100).unwrap(), 1000);
+        assert_eq!(reclaim_amount(1000, 500, 100).unwrap(), 400);
+        assert_eq!(reclaim_amount(550, 500, 100).unwrap(), 0);
+    }
+
+    #[test]
+    fn sunset_grace_period() {
+        let grace = SUNSET_GRACE_PERIOD as i64;
+        assert!(!sunset_grace_period_over(100, 100 + grace - 1).unwrap());
+        assert!(sunset_grace_period_over(100, 100 + grace).unwrap());
+        assert_eq!(sunset_grace_period_over(i64::MAX, 0).unwrap_err(), StakeError::MathOverflow.into());
+    }
+
+    #[test]
+    fn projected_pending() {
+        assert_eq!(projected_pending_amount(10, 5, 0).unwrap(), 40);
+        assert_eq!(projected_pending_amount(10, 5, 6).unwrap(), 10);
+    }
+
+    #[test]
+    fn apr() {
+        let apr = estimated_apr(700, 7, 10_000).unwrap();
+        assert_eq!(apr.reward_per_nft_per_day, 100);
+        assert_eq!(apr.reward_per_nft_per_epoch, 600);
+        assert_eq!(apr.reward_per_nft_per_year, 600 * 52);
+        assert_eq!(apr.apr_bps, 31_200);
+        assert_eq!(estimated_apr(700, 7, 0).unwrap().apr_bps, 0);
+    }
+}
@@ -0,0 +1,44 @@
+//! Data returned with `set_return_data` by the read-only instructions. They
+//! are meant to be simulated, so clients get the same numbers as the program.
+
+use anchor_lang::prelude::*;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct UserRewards {
+    // can be claimed right now
+    pub claimable_amount: u64,
+    // credited in the current epoch, claimable after it is settled
+    pub pending_amount: u64,
+    pub staked_count: u16,
+    // credited every day for the staked nfts at the current weights
+    pub daily_amount: u64,
+    // the pending amount at the end of the epoch, if the crank keeps running
+    pub projected_pending_amount: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct PoolStats {
+    pub epoch_no: u8,
+    pub day_of_epoch: u8,
+    pub epoch_start_time: i64,
+    pub total_stakers: u16,
+    pub cur_epoch_reward_per_day: u64,
+    // daily reward of an nft of weight 1
+    pub reward_per_nft: u64,
+    pub epoch_bonus: u64,
+    pub remain_reward: u64,
+    pub total_owed: u64,
+    pub treasury_amount: u64,
+    pub is_sunset: bool,
+    pub total_weight: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+// for an nft of weight 1
+pub struct EstimatedApr {
+    pub reward_per_nft_per_day: u64,
+    pub reward_per_nft_per_epoch: u64,
+    pub reward_per_nft_per_year: u64,
+    // yearly reward against the given nft value, in basis points
+    pub apr_bps: u64,
+}
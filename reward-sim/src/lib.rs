@@ -0,0 +1,600 @@
+//! Replays a script of pool actions through the `token_stake_model::rewards`
+//! functions the program handlers call, and reports what every user was paid
+//! and what the treasury held, epoch by epoch. Amounts are lamports, time
+//! moves one day per `TickDay`.
+//!
+//! The daily reward is credited once per staked nft, the way the admin crank
+//! is expected to call `process_daily_reward`, split by the weight of the nft
+//! out of the total staked weight. Days the crank missed are credited with
+//! the next call.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use thiserror::Error;
+use token_stake_model::error::StakeError;
+use token_stake_model::{rewards, DAY_IN_SECONDS, EPOCH_DAYS, TOTAL_EPOCH};
+
+pub type User = String;
+/// Names an nft, e.g. its mint.
+pub type Nft = String;
+
+/// What the pool sets at run time, the other constants are the program's.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Params {
+    pub crank_fee: u64,
+    // rent exempt minimum the crank fee never touches
+    pub treasury_rent: u64,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Params {
+            crank_fee: 0,
+            treasury_rent: 890_880,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    /// Revenue landing in the bank wallet.
+    Deposit { amount: u64 },
+    Initialize { amount: u64 },
+    SendBonus { amount: u64 },
+    Stake {
+        user: User,
+        nft: Nft,
+        /// Weight of the nft in a weighted whitelist.
+        #[serde(default = "unit_weight")]
+        weight: u16,
+    },
+    Unstake { user: User, nft: Nft },
+    Claim { user: User },
+    /// One day passes and the day crank runs.
+    TickDay,
+    /// Credits every staked nft the days it is due.
+    DailyReward,
+    StartEpoch,
+    CrankStartEpoch,
+    RestartEpoch,
+}
+
+fn unit_weight() -> u16 {
+    1
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum SimError {
+    #[error("the pool is not initialized")]
+    NotInitialized,
+    #[error("the pool is already initialized")]
+    AlreadyInitialized,
+    #[error("not enough sol")]
+    NoEnoughSol,
+    #[error("no epoch has ended yet")]
+    EpochWrongDays,
+    #[error("the bank can still start the epoch")]
+    CrankTooEarly,
+    #[error("the last epoch has ended")]
+    EpochEnd,
+    #[error("an nft can not have a zero weight")]
+    ZeroWeight,
+    #[error("{0} is already staked")]
+    AlreadyStaked(Nft),
+    #[error("{user} has not staked {nft}")]
+    NotStaked { user: User, nft: Nft },
+    #[error("arithmetic overflow")]
+    Overflow,
+    #[error("{0}")]
+    Program(String),
+}
+
+impl From<anchor_lang::error::Error> for SimError {
+    fn from(err: anchor_lang::error::Error) -> Self {
+        if err == StakeError::MathOverflow.into() {
+            SimError::Overflow
+        } else if err == StakeError::EpochWrongDays.into() {
+            SimError::EpochWrongDays
+        } else if err == StakeError::EpochEnd.into() {
+            SimError::EpochEnd
+        } else {
+            SimError::Program(err.to_string())
+        }
+    }
+}
+
+/// A staked nft, with the days of an epoch it was credited for.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct StakedNft {
+    pub weight: u16,
+    pub credited_epoch: u64,
+    pub credited_days: u8,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct UserState {
+    pub staked_count: u16,
+    // every staked nft by name
+    pub staked: BTreeMap<Nft, StakedNft>,
+    pub reward_amount: u64,
+    pub pending_amount: u64,
+    pub total_claimed: u64,
+    pub total_forfeited: u64,
+    pub total_credited: u64,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct EpochReport {
+    pub epoch_no: u8,
+    pub reward_per_day: u64,
+    pub total_stakers: u16,
+    pub total_weight: u64,
+    pub credited: BTreeMap<User, u64>,
+    pub claimed: BTreeMap<User, u64>,
+    pub forfeited: u64,
+    pub crank_fees: u64,
+    // balances when the epoch is closed
+    pub bank_balance: u64,
+    pub treasury_balance: u64,
+    pub total_owed: u64,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Report {
+    pub epochs: Vec<EpochReport>,
+    pub users: BTreeMap<User, UserState>,
+    pub bank_balance: u64,
+    pub treasury_balance: u64,
+    pub total_owed: u64,
+}
+
+#[derive(Default)]
+pub struct Simulator {
+    pub params: Params,
+    pub is_initial: bool,
+    pub epoch_no: u8,
+    // days since the running epoch started
+    pub days_passed: u64,
+    // days the day crank passed, stake_info.days_passed
+    pub ticked_days: u8,
+    pub day_of_epoch: u8,
+    // counts the epochs started, it stands for the epoch start time
+    pub epoch_id: u64,
+    pub epoch_credited: u64,
+    pub cur_epoch_reward_per_day: u64,
+    pub epoch_bonus: u64,
+    pub remain_reward: u64,
+    pub total_owed: u64,
+    pub total_stakers: u16,
+    pub total_weight: u64,
+    pub bank: u64,
+    pub treasury: u64,
+    pub users: BTreeMap<User, UserState>,
+    epochs: Vec<EpochReport>,
+    current: EpochReport,
+}
+
+fn add(a: u64, b: u64) -> Result<u64, SimError> {
+    Ok(rewards::add(a, b)?)
+}
+
+fn sub(a: u64, b: u64) -> Result<u64, SimError> {
+    a.checked_sub(b).ok_or(SimError::NoEnoughSol)
+}
+
+impl Simulator {
+    pub fn new(params: Params) -> Self {
+        Simulator {
+            params,
+            ..Default::default()
+        }
+    }
+
+    /// Runs `actions` in order, stopping at the first one the program would
+    /// reject. The index of that action is returned with the error.
+    pub fn run(&mut self, actions: &[Action]) -> Result<(), (usize, SimError)> {
+        for (i, action) in actions.iter().enumerate() {
+            self.apply(action).map_err(|err| (i, err))?;
+        }
+        Ok(())
+    }
+
+    pub fn apply(&mut self, action: &Action) -> Result<(), SimError> {
+        match action {
+            Action::Deposit { amount } => {
+                self.bank = add(self.bank, *amount)?;
+                Ok(())
+            }
+            Action::Initialize { amount } => self.initialize(*amount),
+            Action::SendBonus { amount } => self.send_bonus(*amount),
+            Action::Stake { user, nft, weight } => self.stake(user, nft, *weight),
+            Action::Unstake { user, nft } => self.unstake(user, nft),
+            Action::Claim { user } => self.claim(user),
+            Action::TickDay => self.tick_day(),
+            Action::DailyReward => self.daily_reward(),
+            Action::StartEpoch => self.start_epoch(),
+            Action::CrankStartEpoch => self.crank_start_epoch(),
+            Action::RestartEpoch => self.restart_epoch(),
+        }
+    }
+
+    fn check_running(&self) -> Result<(), SimError> {
+        if !self.is_initial {
+            return Err(SimError::NotInitialized);
+        }
+        if self.epoch_no > TOTAL_EPOCH {
+            return Err(SimError::EpochEnd);
+        }
+        Ok(())
+    }
+
+    fn open_epoch(&mut self) {
+        self.current = EpochReport {
+            epoch_no: self.epoch_no,
+            reward_per_day: self.cur_epoch_reward_per_day,
+            ..Default::default()
+        };
+    }
+
+    fn close_epoch(&mut self) {
+        let mut report = std::mem::take(&mut self.current);
+        report.total_stakers = self.total_stakers;
+        report.total_weight = self.total_weight;
+        report.bank_balance = self.bank;
+        report.treasury_balance = self.treasury;
+        report.total_owed = self.total_owed;
+        self.epochs.push(report);
+    }
+
+    // process_initialize
+    fn initialize(&mut self, amount: u64) -> Result<(), SimError> {
+        if self.is_initial {
+            return Err(SimError::AlreadyInitialized);
+        }
+        self.bank = sub(self.bank, amount)?;
+        self.treasury = add(self.treasury, amount)?;
+        self.is_initial = true;
+        self.epoch_no = 1;
+        self.days_passed = 0;
+        self.new_epoch();
+        self.cur_epoch_reward_per_day = rewards::reward_per_day(amount);
+        self.epoch_bonus = 0;
+        self.open_epoch();
+        Ok(())
+    }
+
+    fn new_epoch(&mut self) {
+        self.epoch_id += 1;
+        self.ticked_days = 0;
+        self.day_of_epoch = 0;
+        self.epoch_credited = 0;
+    }
+
+    // the clock as seconds since the start of the running epoch
+    fn now(&self) -> i64 {
+        self.days_passed as i64 * DAY_IN_SECONDS as i64
+    }
+
+    // process_send_epoch_bonus, the bonus is sent by the bank
+    fn send_bonus(&mut self, amount: u64) -> Result<(), SimError> {
+        self.check_running()?;
+        self.bank = sub(self.bank, amount)?;
+        self.treasury = add(self.treasury, amount)?;
+        self.epoch_bonus = add(self.epoch_bonus, amount)?;
+        Ok(())
+    }
+
+    // process_stake_nft
+    fn stake(&mut self, user: &str, nft: &str, weight: u16) -> Result<(), SimError> {
+        if !self.is_initial {
+            return Err(SimError::NotInitialized);
+        }
+        if weight == 0 {
+            return Err(SimError::ZeroWeight);
+        }
+        if self.users.values().any(|state| state.staked.contains_key(nft)) {
+            return Err(SimError::AlreadyStaked(nft.to_string()));
+        }
+        let state = self.users.entry(user.to_string()).or_default();
+        state.staked_count += 1;
+        // the nft is credited from the next day on
+        state.staked.insert(
+            nft.to_string(),
+            StakedNft {
+                weight,
+                credited_epoch: self.epoch_id,
+                credited_days: self.ticked_days,
+            },
+        );
+        self.total_stakers += 1;
+        self.total_weight = add(self.total_weight, weight as u64)?;
+        Ok(())
+    }
+
+    // process_unstake_nft, the pending reward goes back to the next epoch
+    fn unstake(&mut self, user: &str, nft: &str) -> Result<(), SimError> {
+        let not_staked = || SimError::NotStaked {
+            user: user.to_string(),
+            nft: nft.to_string(),
+        };
+        let state = self.users.get_mut(user).ok_or_else(not_staked)?;
+        // each nft was credited on its own days
+        let weight = state.staked.remove(nft).ok_or_else(not_staked)?.weight;
+        let forfeited = state.pending_amount;
+        state.pending_amount = 0;
+        state.staked_count -= 1;
+        state.total_forfeited = add(state.total_forfeited, forfeited)?;
+
+        self.remain_reward = add(self.remain_reward, forfeited)?;
+        self.total_owed = self.total_owed.saturating_sub(forfeited);
+        self.total_stakers -= 1;
+        self.total_weight -= weight as u64;
+        self.current.forfeited = add(self.current.forfeited, forfeited)?;
+        Ok(())
+    }
+
+    // process_claim_reward
+    fn claim(&mut self, user: &str) -> Result<(), SimError> {
+        let state = self.users.entry(user.to_string()).or_default();
+        let amount = state.reward_amount;
+        if amount == 0 {
+            return Ok(());
+        }
+        self.treasury = sub(self.treasury, amount)?;
+        state.reward_amount = 0;
+        state.total_claimed = add(state.total_claimed, amount)?;
+        self.total_owed = self.total_owed.saturating_sub(amount);
+        let claimed = self.current.claimed.entry(user.to_string()).or_default();
+        *claimed = add(*claimed, amount)?;
+        Ok(())
+    }
+
+    // process_update_day_of_epoch, after a day has passed
+    fn tick_day(&mut self) -> Result<(), SimError> {
+        self.check_running()?;
+        self.days_passed += 1;
+        let days_passed = rewards::days_passed(0, self.now());
+        if days_passed != self.ticked_days {
+            self.ticked_days = days_passed;
+            self.day_of_epoch = days_passed % EPOCH_DAYS;
+            self.pay_crank_fee()?;
+        }
+        Ok(())
+    }
+
+    // process_daily_reward for every staked nft with days due, the program
+    // rejects the others
+    fn daily_reward(&mut self) -> Result<(), SimError> {
+        self.check_running()?;
+        if self.total_stakers == 0 {
+            return Ok(());
+        }
+
+        for (user, state) in self.users.iter_mut() {
+            for nft in state.staked.values_mut() {
+                let credited_days = if nft.credited_epoch == self.epoch_id { nft.credited_days } else { 0 };
+                let days = rewards::days_due(credited_days, self.ticked_days);
+                if days == 0 && self.day_of_epoch != 0 {
+                    continue;
+                }
+                let reward_amount = rewards::weighted_reward(self.cur_epoch_reward_per_day, nft.weight as u64, self.total_weight)?;
+                let credit = rewards::daily_credit(self.day_of_epoch, days, reward_amount, state.pending_amount)?;
+                state.pending_amount -= credit.settled_amount;
+                state.reward_amount = add(state.reward_amount, credit.settled_amount)?;
+                state.pending_amount = add(state.pending_amount, credit.amount)?;
+                state.total_credited = add(state.total_credited, credit.amount)?;
+                self.total_owed = add(self.total_owed, credit.amount)?;
+                self.epoch_credited = add(self.epoch_credited, credit.amount)?;
+                nft.credited_epoch = self.epoch_id;
+                nft.credited_days = self.ticked_days;
+                if credit.amount > 0 {
+                    let credited = self.current.credited.entry(user.clone()).or_default();
+                    *credited = add(*credited, credit.amount)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // roll_epochs
+    fn roll_epochs(&mut self) -> Result<u8, SimError> {
+        let roll = rewards::roll_epochs(self.epoch_no, 0, self.now())?;
+        self.close_epoch();
+        self.epoch_no = roll.epoch_no;
+        self.days_passed -= (roll.epoch_start_time / DAY_IN_SECONDS as i64) as u64;
+        self.new_epoch();
+        Ok(roll.epochs_passed)
+    }
+
+    // process_start_epoch, the bank balance joins the bonus and the forfeited
+    // rewards already in the treasury
+    fn start_epoch(&mut self) -> Result<(), SimError> {
+        self.check_running()?;
+        if self.bank == 0 {
+            return Err(SimError::NoEnoughSol);
+        }
+        let send_amount = rewards::bank_send_amount(self.bank)?;
+        self.roll_epochs()?;
+
+        let reward_amount = rewards::epoch_reward_amount(send_amount, self.epoch_bonus, self.remain_reward)?;
+        self.cur_epoch_reward_per_day = rewards::reward_per_day(reward_amount);
+        self.epoch_bonus = 0;
+        self.remain_reward = 0;
+        self.bank -= send_amount;
+        self.treasury = add(self.treasury, send_amount)?;
+        self.open_epoch();
+        Ok(())
+    }
+
+    // process_crank_start_epoch, shares what is already in the treasury once
+    // the bank had a day to start the epoch
+    fn crank_start_epoch(&mut self) -> Result<(), SimError> {
+        self.check_running()?;
+        if !rewards::crank_start_due(0, self.now())? {
+            return Err(SimError::CrankTooEarly);
+        }
+        self.roll_epochs()?;
+
+        let reward_amount = rewards::epoch_reward_amount(0, self.epoch_bonus, self.remain_reward)?;
+        self.cur_epoch_reward_per_day = rewards::reward_per_day(reward_amount);
+        self.epoch_bonus = 0;
+        self.remain_reward = 0;
+        self.open_epoch();
+        self.pay_crank_fee()
+    }
+
+    // process_restart_epoch, the whole treasury is shared again
+    fn restart_epoch(&mut self) -> Result<(), SimError> {
+        if self.is_initial {
+            self.close_epoch();
+        }
+        self.is_initial = true;
+        self.epoch_no = 1;
+        self.days_passed = 0;
+        self.new_epoch();
+        self.cur_epoch_reward_per_day = rewards::reward_per_day(self.treasury);
+        self.epoch_bonus = 0;
+        self.open_epoch();
+        Ok(())
+    }
+
+    // pay_crank_fee
+    fn pay_crank_fee(&mut self) -> Result<(), SimError> {
+        let reserved_amount = rewards::treasury_reserve(
+            self.total_owed,
+            self.cur_epoch_reward_per_day,
+            self.epoch_credited,
+            self.epoch_bonus,
+            self.remain_reward,
+        )?;
+        let fee = rewards::crank_fee_due(self.treasury, reserved_amount, self.params.treasury_rent, self.params.crank_fee)?;
+        if fee == 0 {
+            return Ok(());
+        }
+        self.treasury -= fee;
+        self.current.crank_fees = add(self.current.crank_fees, fee)?;
+        Ok(())
+    }
+
+    /// The closed epochs, then the running one.
+    pub fn report(&self) -> Report {
+        let mut epochs = self.epochs.clone();
+        if self.is_initial {
+            let mut current = self.current.clone();
+            current.total_stakers = self.total_stakers;
+            current.total_weight = self.total_weight;
+            current.bank_balance = self.bank;
+            current.treasury_balance = self.treasury;
+            current.total_owed = self.total_owed;
+            epochs.push(current);
+        }
+        Report {
+            epochs,
+            users: self.users.clone(),
+            bank_balance: self.bank,
+            treasury_balance: self.treasury,
+            total_owed: self.total_owed,
+        }
+    }
+}
+
+/// A script file: the params and the actions to replay.
+#[derive(Deserialize)]
+pub struct Script {
+    #[serde(default)]
+    pub params: Params,
+    pub actions: Vec<Action>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOL: u64 = 1_000_000_000;
+
+    // a pool sharing 1 SOL a day
+    fn pool() -> Simulator {
+        let mut sim = Simulator::new(Params::default());
+        sim.run(&[Action::Deposit { amount: 7 * SOL }, Action::Initialize { amount: 7 * SOL }]).unwrap();
+        sim
+    }
+
+    fn stake(user: &str, nft: &str) -> Action {
+        Action::Stake {
+            user: user.to_string(),
+            nft: nft.to_string(),
+            weight: 1,
+        }
+    }
+
+    fn unstake(user: &str, nft: &str) -> Action {
+        Action::Unstake {
+            user: user.to_string(),
+            nft: nft.to_string(),
+        }
+    }
+
+    fn next_day(sim: &mut Simulator) {
+        sim.run(&[Action::TickDay, Action::DailyReward]).unwrap();
+    }
+
+    #[test]
+    fn short_epoch_pays_the_days_staked() {
+        let mut sim = pool();
+        sim.apply(&stake("alice", "a1")).unwrap();
+        for _ in 0..3 {
+            next_day(&mut sim);
+        }
+        // bob only has the last three credited days of the epoch
+        sim.apply(&stake("bob", "b1")).unwrap();
+        for _ in 0..4 {
+            next_day(&mut sim);
+        }
+        assert_eq!(sim.day_of_epoch, 0);
+        assert_eq!(sim.users["alice"].reward_amount, 3 * SOL + 3 * SOL / 2);
+        assert_eq!(sim.users["bob"].reward_amount, 3 * SOL / 2);
+        assert_eq!(sim.users["bob"].pending_amount, 0);
+        assert_eq!(sim.total_owed, 6 * SOL);
+    }
+
+    #[test]
+    fn skipped_day_is_credited_with_the_next_call() {
+        let mut sim = pool();
+        sim.apply(&stake("alice", "a1")).unwrap();
+        next_day(&mut sim);
+        sim.apply(&Action::TickDay).unwrap();
+        next_day(&mut sim);
+        assert_eq!(sim.users["alice"].pending_amount, 3 * SOL);
+        assert_eq!(sim.users["alice"].total_credited, 3 * SOL);
+        assert_eq!(sim.epoch_credited, 3 * SOL);
+    }
+
+    #[test]
+    fn mid_epoch_unstake_removes_the_nft_named() {
+        let mut sim = pool();
+        sim.run(&[stake("alice", "a1"), stake("bob", "b1")]).unwrap();
+        next_day(&mut sim);
+        // day 2 is ticked but not credited when alice swaps her nfts
+        sim.apply(&Action::TickDay).unwrap();
+        sim.run(&[stake("alice", "a2"), unstake("alice", "a1")]).unwrap();
+        assert_eq!(sim.users["alice"].total_forfeited, SOL / 2);
+        assert_eq!(sim.remain_reward, SOL / 2);
+
+        // a2 is only due day 3, b1 days 2 and 3
+        next_day(&mut sim);
+        assert_eq!(sim.users["alice"].pending_amount, SOL / 2);
+        assert_eq!(sim.users["bob"].pending_amount, SOL / 2 + SOL);
+        assert_eq!(sim.total_owed, 2 * SOL);
+
+        assert_eq!(
+            sim.apply(&unstake("alice", "a1")).unwrap_err(),
+            SimError::NotStaked {
+                user: "alice".to_string(),
+                nft: "a1".to_string(),
+            }
+        );
+        assert_eq!(sim.apply(&stake("alice", "b1")).unwrap_err(), SimError::AlreadyStaked("b1".to_string()));
+    }
+}
@@ -0,0 +1,22 @@
+//! `reward-sim script.json` replays the script and prints the report as json.
+
+use anyhow::{anyhow, Context, Result};
+use reward_sim::{Script, Simulator};
+use std::fs;
+
+fn main() -> Result<()> {
+    let path = std::env::args()
+        .nth(1)
+        .ok_or_else(|| anyhow!("usage: reward-sim <script.json>"))?;
+    let input = fs::read_to_string(&path).with_context(|| format!("cannot read {}", path))?;
+    let script: Script = serde_json::from_str(&input)?;
+
+    let mut sim = Simulator::new(script.params);
+    let result = sim.run(&script.actions);
+    println!("{}", serde_json::to_string_pretty(&sim.report())?);
+
+    if let Err((i, err)) = result {
+        return Err(anyhow!("action {} ({:?}) rejected: {}", i, script.actions[i], err));
+    }
+    Ok(())
+}
@@ -272,11 +272,9 @@ describe('token-stake-model', () => {
   });
 
   it('Unstake NFT', async () => {
-    const proof = tree.getProof(nftArray[1]['account']);
     await program.rpc.processUnstakeNft(
       epoch_state_bump,
       stake_info_bump,
-      proof,
       {
         accounts: {
           userAccount: userAccount.publicKey,
@@ -285,7 +283,6 @@ describe('token-stake-model', () => {
           nftVaultAccount: nft_vault_pda,
           stakeInfoAccount: user_stake_pda,
           vaultAuth: nft_auth_pda,
-          merkle: merkle_pda,
           stakeUser: stake_user_pda,
           epochState: epoch_state_pda,
           stakeInfo: stake_info_pda,